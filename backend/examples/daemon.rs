@@ -1,23 +1,5 @@
 use std::env;
-use system76_keyboard_configurator_backend::{run_daemon, Backend};
-
-#[cfg(target_os = "linux")]
-fn with_daemon<F: Fn(Backend)>(f: F) {
-    if unsafe { libc::geteuid() == 0 } {
-        eprintln!("Already running as root");
-        let server = Backend::new().expect("Failed to create server");
-        f(server);
-        return;
-    }
-
-    f(Backend::new_pkexec().unwrap());
-}
-
-#[cfg(not(target_os = "linux"))]
-fn with_daemon<F: Fn(Box<dyn Daemon>)>(f: F) {
-    let server = Backend::new().expect("Failed to create server");
-    f(server);
-}
+use system76_keyboard_configurator_backend::{run_daemon, BackendBuilder, BackendSource};
 
 fn main() {
     for arg in env::args().skip(1) {
@@ -26,7 +8,19 @@ fn main() {
         }
     }
 
-    with_daemon(|_daemon| {
-        // println!("boards: {:?}", daemon.boards());
-    });
+    let backend = BackendBuilder::new()
+        .source(BackendSource::root())
+        .build()
+        .expect("Failed to create server");
+
+    let context = glib::MainContext::default();
+    context
+        .block_on(backend.refresh_async())
+        .expect("Failed to refresh boards");
+    while context.pending() {
+        context.iteration(false);
+    }
+    for board in backend.boards() {
+        println!("{}: {}", board.model(), board.layout().meta.display_name);
+    }
 }
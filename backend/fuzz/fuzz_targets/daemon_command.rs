@@ -0,0 +1,12 @@
+#![no_main]
+use backend::{DaemonServer, EcEmulator};
+use libfuzzer_sys::fuzz_target;
+use std::io;
+
+// Commands from the unprivileged client, as read by the root daemon
+fuzz_target!(|data: &[u8]| {
+    let emulator = EcEmulator::new("system76/launch_1").unwrap();
+    let input = io::Cursor::new(data.to_vec());
+    let server = DaemonServer::new_emulated(input, io::sink(), vec![emulator]).unwrap();
+    let _ = server.run();
+});
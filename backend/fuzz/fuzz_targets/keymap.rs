@@ -0,0 +1,15 @@
+#![no_main]
+use backend::{KeyMap, Layout};
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+
+static LAYOUT: Lazy<Layout> = Lazy::new(|| Layout::from_board("system76/launch_1").unwrap());
+
+// Imported keymap files
+fuzz_target!(|data: &[u8]| {
+    if let Ok(json) = std::str::from_utf8(data) {
+        if let Ok(keymap) = KeyMap::from_str(json) {
+            keymap.validate(&LAYOUT);
+        }
+    }
+});
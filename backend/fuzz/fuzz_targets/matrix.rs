@@ -0,0 +1,18 @@
+#![no_main]
+use backend::Matrix;
+use libfuzzer_sys::fuzz_target;
+
+// Matrix replies from an EC or daemon, with a size that may not match the data
+fuzz_target!(|data: &[u8]| {
+    if let Ok(matrix) = Matrix::from_ec_data(data) {
+        assert_eq!(
+            Matrix::default().diff(&matrix).count(),
+            matrix.pressed().count()
+        );
+    }
+    if let Ok(matrix) = serde_json::from_slice::<Matrix>(data) {
+        for (row, col) in matrix.pressed() {
+            assert_eq!(matrix.get(row, col), Some(true));
+        }
+    }
+});
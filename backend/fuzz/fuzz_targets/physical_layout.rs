@@ -0,0 +1,17 @@
+#![no_main]
+use backend::Layout;
+use libfuzzer_sys::fuzz_target;
+
+// `physical.json` with the other layout files of launch_1
+fuzz_target!(|data: &[u8]| {
+    if let Ok(physical_json) = std::str::from_utf8(data) {
+        let _ = Layout::from_data(
+            include_str!("../../../layouts/system76/launch_1/meta.json"),
+            include_str!("../../../layouts/system76/launch_1/default.json"),
+            include_str!("../../../layouts/system76/launch_1/keymap.json"),
+            include_str!("../../../layouts/system76/launch_1/layout.json"),
+            include_str!("../../../layouts/system76/launch_1/leds.json"),
+            Some(physical_json),
+        );
+    }
+});
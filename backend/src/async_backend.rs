@@ -0,0 +1,565 @@
+use futures::channel::mpsc as async_mpsc;
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::UnixListener,
+    },
+    path::Path,
+};
+use std::{
+    process,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::daemon::*;
+use crate::{Layout, LayoutError, Matrix, Rgb};
+
+/// Change in connected boards, from `AsyncBackend`
+pub enum BackendEvent {
+    /// New boards were found, and are being loaded
+    BoardLoading,
+    /// Boards found since `BoardLoading` are loaded
+    BoardLoadingDone,
+    BoardAdded(AsyncBoard),
+    BoardRemoved(BoardId),
+}
+
+/// Stream of `BackendEvent`s for an `AsyncBackend`
+pub type BackendEvents = async_mpsc::UnboundedReceiver<BackendEvent>;
+
+/// Backend that doesn't depend on GLib, usable with any executor
+///
+/// The daemon runs on its own thread. `Backend` wraps this as a GObject, for
+/// use with GTK.
+pub struct AsyncBackend {
+    thread_client: Arc<ThreadClient>,
+}
+
+impl AsyncBackend {
+    pub(crate) fn new_internal<T: Daemon + 'static>(daemon: T) -> (Self, BackendEvents) {
+        let (thread_client, events) = ThreadClient::new(Box::new(daemon));
+        (Self { thread_client }, events)
+    }
+
+    pub fn new_dummy(board_names: Vec<String>) -> Result<(Self, BackendEvents), String> {
+        Ok(Self::new_internal(DaemonDummy::new(board_names)))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn new_s76power() -> Result<(Self, BackendEvents), String> {
+        Ok(Self::new_internal(DaemonS76Power::new()?))
+    }
+
+    pub fn new_pkexec() -> Result<(Self, BackendEvents), String> {
+        Ok(Self::new_internal(DaemonClient::new_pkexec()?))
+    }
+
+    pub fn new() -> Result<(Self, BackendEvents), String> {
+        Ok(Self::new_internal(DaemonServer::new_stdio()?))
+    }
+
+    /// Test for added/removed boards, sending events for them
+    ///
+    /// Completes once new boards are loaded.
+    pub async fn refresh(&self) -> Result<(), String> {
+        self.thread_client.refresh().await
+    }
+
+    /// Read the key matrix of boards at `rate`, or stop reading it with `None`
+    pub async fn set_matrix_get_rate(&self, rate: Option<Duration>) -> Result<(), String> {
+        self.thread_client.set_matrix_get_rate(rate).await
+    }
+
+    /// Stop the daemon thread, waiting for it to exit
+    pub(crate) fn close(&self) {
+        self.thread_client.close();
+    }
+}
+
+impl Drop for AsyncBackend {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Scancodes and LED color of a key, as read when its board was loaded and
+/// updated by changes made through it
+#[derive(Clone, Debug)]
+pub struct KeyState {
+    /// Scancode of each layer
+    pub scancodes: Vec<u16>,
+    /// Color of the first LED of the key, or `None` if it is off or there is no LED
+    pub led_color: Option<Rgb>,
+}
+
+/// LED settings of a layer, as read when its board was loaded and updated by
+/// changes made through it
+#[derive(Clone, Debug)]
+pub struct LayerState {
+    /// Mode index and speed, if the board supports modes
+    pub mode: Option<(u8, u8)>,
+    pub brightness: i32,
+    /// Color as returned by the daemon, which is RGB for LED index `0xff`, and
+    /// hue and saturation for per-layer indexes
+    pub color: Option<(u8, u8, u8)>,
+}
+
+/// LED index of the settings of `layer`, or `0xff` if the board has one
+/// setting for all layers
+pub(crate) fn layer_led_index(layout: &Layout, layer: u8) -> u8 {
+    if layout.meta.has_per_layer {
+        0xf0 + layer
+    } else {
+        0xff
+    }
+}
+
+struct AsyncBoardInner {
+    thread_client: Arc<ThreadClient>,
+    board: BoardId,
+    model: String,
+    layout: Layout,
+    max_brightness: i32,
+    has_led_save: bool,
+    has_matrix: bool,
+    is_fake: bool,
+    keys: Mutex<Vec<KeyState>>,
+    layers: Mutex<Vec<LayerState>>,
+    matrix_receiver: Mutex<Option<async_mpsc::UnboundedReceiver<Matrix>>>,
+}
+
+/// Board that doesn't depend on GLib, which may be sent between threads
+///
+/// Cloning gives another handle to the same board.
+#[derive(Clone)]
+pub struct AsyncBoard(Arc<AsyncBoardInner>);
+
+impl AsyncBoard {
+    /// Read the layout and state of `board`, from the daemon thread
+    pub(crate) fn load(
+        daemon: &dyn Daemon,
+        thread_client: Arc<ThreadClient>,
+        board: BoardId,
+        matrix_receiver: async_mpsc::UnboundedReceiver<Matrix>,
+    ) -> Result<Self, String> {
+        let model = match daemon.model(board) {
+            Ok(model) => model,
+            Err(err) => {
+                return Err(format!("Failed to get board model: {}", err));
+            }
+        };
+        let layout = match daemon.layout(board) {
+            Some(layout) => Ok(layout),
+            None => Layout::from_board(&model),
+        };
+        let layout = match layout {
+            Ok(layout) => layout,
+            // Show the raw matrix of unknown boards, so they can still be remapped
+            Err(err @ LayoutError::UnknownBoard(_)) => {
+                let matrix = daemon
+                    .matrix_get(board)
+                    .map_err(|_| format!("Failed to load layout for '{}': {}", model, err))?;
+                warn!("No layout for '{}', using generic layout", model);
+                Layout::generic(&model, matrix.rows(), matrix.cols())
+                    .map_err(|err| format!("Failed to load layout for '{}': {}", model, err))?
+            }
+            Err(err) => return Err(format!("Failed to load layout for '{}': {}", model, err)),
+        };
+
+        let max_brightness = daemon.max_brightness(board).unwrap_or_else(|err| {
+            error!("Error getting max brightness: {}", err);
+            100
+        });
+
+        let has_led_save = daemon.led_save(board).is_ok();
+        let has_matrix = daemon.matrix_get(board).is_ok();
+
+        let keys = layout
+            .physical
+            .keys
+            .iter()
+            .map(|physical_key| {
+                let logical_name = physical_key.logical_name();
+                let electrical = layout.electrical(&logical_name).unwrap_or((0, 0));
+                let scancodes = (0..layout.meta.num_layers)
+                    .map(|layer| {
+                        daemon
+                            .keymap_get(board, layer, electrical.0, electrical.1)
+                            .unwrap_or_else(|err| {
+                                error!("Failed to read scancode: {:?}", err);
+                                0
+                            })
+                    })
+                    .collect();
+                let led = layout.leds.get(&logical_name).and_then(|leds| leds.first());
+                let led_color = match led {
+                    Some(led) if layout.meta.has_mode => match daemon.color(board, *led) {
+                        Ok((0, 0, 0)) => None,
+                        Ok((r, g, b)) => Some(Rgb::new(r, g, b)),
+                        Err(err) => {
+                            error!("error getting key color: {}", err);
+                            None
+                        }
+                    },
+                    _ => None,
+                };
+                KeyState {
+                    scancodes,
+                    led_color,
+                }
+            })
+            .collect();
+
+        let num_layers = if layout.meta.has_per_layer {
+            layout.meta.num_layers
+        } else {
+            1
+        };
+        let layers = (0..num_layers)
+            .map(|layer| {
+                let index = layer_led_index(&layout, layer);
+                let mode = if layout.meta.has_mode {
+                    daemon.mode(board, layer).map(Some).unwrap_or_else(|err| {
+                        error!("Error getting layer mode: {}", err);
+                        None
+                    })
+                } else {
+                    None
+                };
+                let brightness = daemon.brightness(board, index).unwrap_or_else(|err| {
+                    error!("error getting layer brightness: {}", err);
+                    0
+                });
+                let color = daemon
+                    .color(board, index)
+                    .map_err(|err| error!("error getting layer color: {}", err))
+                    .ok();
+                LayerState {
+                    mode,
+                    brightness,
+                    color,
+                }
+            })
+            .collect();
+
+        Ok(Self(Arc::new(AsyncBoardInner {
+            thread_client,
+            board,
+            model,
+            layout,
+            max_brightness,
+            has_led_save,
+            has_matrix,
+            is_fake: daemon.is_fake(board),
+            keys: Mutex::new(keys),
+            layers: Mutex::new(layers),
+            matrix_receiver: Mutex::new(Some(matrix_receiver)),
+        })))
+    }
+
+    pub fn board(&self) -> BoardId {
+        self.0.board
+    }
+
+    pub fn model(&self) -> &str {
+        &self.0.model
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.0.layout
+    }
+
+    pub fn max_brightness(&self) -> i32 {
+        self.0.max_brightness
+    }
+
+    pub fn has_led_save(&self) -> bool {
+        self.0.has_led_save
+    }
+
+    pub fn has_matrix(&self) -> bool {
+        self.0.has_matrix
+    }
+
+    pub fn is_fake(&self) -> bool {
+        self.0.is_fake
+    }
+
+    /// State of each key of `layout().physical_keys()`, as read when loaded
+    /// and changed since
+    pub fn keys(&self) -> Vec<KeyState> {
+        self.0.keys.lock().unwrap().clone()
+    }
+
+    /// LED settings of each layer, or only one if the board has no per-layer
+    /// settings, as read when loaded and changed since
+    pub fn layers(&self) -> Vec<LayerState> {
+        self.0.layers.lock().unwrap().clone()
+    }
+
+    /// Update the state of keys, by logical name, after a change
+    fn update_keys<F: FnMut(&str, &mut KeyState)>(&self, mut f: F) {
+        let mut keys = self.0.keys.lock().unwrap();
+        for (physical_key, key) in self.layout().physical_keys().iter().zip(keys.iter_mut()) {
+            f(&physical_key.logical_name(), key);
+        }
+    }
+
+    /// Update the state of layers with LED `index` after a change
+    fn update_layers<F: FnMut(&mut LayerState)>(&self, index: u8, mut f: F) {
+        let mut layers = self.0.layers.lock().unwrap();
+        for (layer, state) in layers.iter_mut().enumerate() {
+            if layer_led_index(self.layout(), layer as u8) == index {
+                f(state);
+            }
+        }
+    }
+
+    /// Stream of the key matrix each time it changes, while enabled with
+    /// `AsyncBackend::set_matrix_get_rate`
+    ///
+    /// There is only one stream for each board, so this returns `None` after
+    /// the first call.
+    pub fn take_matrix_stream(&self) -> Option<async_mpsc::UnboundedReceiver<Matrix>> {
+        self.0.matrix_receiver.lock().unwrap().take()
+    }
+
+    pub async fn keymap_set(
+        &self,
+        layer: u8,
+        output: u8,
+        input: u8,
+        value: u16,
+    ) -> Result<(), String> {
+        self.0
+            .thread_client
+            .keymap_set(self.board(), layer, output, input, value)
+            .await?;
+        let layout = self.layout();
+        self.update_keys(|name, key| {
+            if layout.electrical(name) == Some((output, input)) {
+                if let Some(scancode) = key.scancodes.get_mut(usize::from(layer)) {
+                    *scancode = value;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Set the color of an LED `index`, which is a key LED, `0xf0 + layer`
+    /// for per-layer settings, or `0xff` for the whole keyboard
+    pub async fn set_color(&self, index: u8, color: (u8, u8, u8)) -> Result<(), String> {
+        self.0
+            .thread_client
+            .set_color(self.board(), index, color)
+            .await?;
+        let layout = self.layout();
+        self.update_keys(|name, key| {
+            if layout.leds(name).first() == Some(&index) {
+                key.led_color = match color {
+                    (0, 0, 0) => None,
+                    (r, g, b) => Some(Rgb::new(r, g, b)),
+                };
+            }
+        });
+        self.update_layers(index, |layer| layer.color = Some(color));
+        Ok(())
+    }
+
+    /// Set the brightness of an LED `index`, as in `set_color`
+    pub async fn set_brightness(&self, index: u8, brightness: i32) -> Result<(), String> {
+        self.0
+            .thread_client
+            .set_brightness(self.board(), index, brightness)
+            .await?;
+        self.update_layers(index, |layer| layer.brightness = brightness);
+        Ok(())
+    }
+
+    pub async fn set_mode(&self, layer: u8, mode: u8, speed: u8) -> Result<(), String> {
+        self.0
+            .thread_client
+            .set_mode(self.board(), layer, mode, speed)
+            .await?;
+        if let Some(state) = self.0.layers.lock().unwrap().get_mut(usize::from(layer)) {
+            state.mode = Some((mode, speed));
+        }
+        Ok(())
+    }
+
+    /// Save LED settings, so they persist when the keyboard is reconnected
+    pub async fn led_save(&self) -> Result<(), String> {
+        self.0.thread_client.led_save(self.board()).await
+    }
+}
+
+pub fn run_daemon() -> ! {
+    let server = DaemonServer::new_stdio().expect("Failed to create server");
+    server.run().expect("Failed to run server");
+    process::exit(0)
+}
+
+/// Serve clients of `BackendSource::Socket` connecting to `path`, one at a time
+///
+/// A socket left at `path` by a previous run is replaced. The socket has mode
+/// 0660, so only its owner and group can connect; with `group`, it belongs to
+/// that group instead of the daemon's. Only returns if the socket can't be
+/// created.
+#[cfg(unix)]
+pub fn run_daemon_socket(path: &Path, group: Option<&str>) -> Result<(), String> {
+    let listener = bind_socket(path, group)
+        .map_err(|err| format!("Failed to create socket {}: {}", path.display(), err))?;
+    for stream in listener.incoming() {
+        let res = stream.map_err(|err| err.to_string()).and_then(|stream| {
+            let read = stream.try_clone().map_err(|err| err.to_string())?;
+            let server = DaemonServer::new(read, stream)?;
+            server.run().map_err(|err| err.to_string())
+        });
+        if let Err(err) = res {
+            error!("Failed to serve client: {}", err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn bind_socket(path: &Path, group: Option<&str>) -> Result<UnixListener, String> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            fs::remove_file(path).map_err(|err| err.to_string())?;
+        }
+        Ok(_) => return Err("Path exists and is not a socket".to_string()),
+        Err(_) => {}
+    }
+    let listener = UnixListener::bind(path).map_err(|err| err.to_string())?;
+    if let Some(group) = group {
+        set_group(path, group)?;
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(0o660)).map_err(|err| err.to_string())?;
+    Ok(listener)
+}
+
+#[cfg(target_os = "linux")]
+fn set_group(path: &Path, group: &str) -> Result<(), String> {
+    use std::{ffi::CString, io, os::unix::ffi::OsStrExt};
+
+    let name = CString::new(group).map_err(|err| err.to_string())?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("Unknown group '{}'", group));
+    }
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|err| err.to_string())?;
+    if unsafe { libc::chown(path.as_ptr(), libc::uid_t::MAX, (*entry).gr_gid) } != 0 {
+        return Err(format!(
+            "Failed to set group '{}': {}",
+            group,
+            io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn set_group(_path: &Path, _group: &str) -> Result<(), String> {
+    Err("Setting the socket group is only supported on Linux".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, prelude::*};
+
+    #[test]
+    fn async_backend_without_glib() {
+        let (backend, mut events) =
+            AsyncBackend::new_dummy(vec!["system76/launch_1".to_string()]).unwrap();
+        block_on(backend.refresh()).unwrap();
+
+        assert!(matches!(
+            block_on(events.next()),
+            Some(BackendEvent::BoardLoading)
+        ));
+        let board = match block_on(events.next()) {
+            Some(BackendEvent::BoardAdded(board)) => board,
+            _ => panic!("Expected BoardAdded"),
+        };
+        assert!(matches!(
+            block_on(events.next()),
+            Some(BackendEvent::BoardLoadingDone)
+        ));
+
+        assert_eq!(board.model(), "system76/launch_1");
+        assert!(board.is_fake());
+        assert_eq!(board.keys().len(), board.layout().physical_keys().len());
+
+        // Handles can be used from other threads
+        let handle = board.clone();
+        std::thread::spawn(move || block_on(handle.keymap_set(0, 0, 0, 0x29)))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert!(board.take_matrix_stream().is_some());
+        assert!(board.take_matrix_stream().is_none());
+    }
+
+    #[test]
+    fn async_board_state_updates() {
+        let (backend, mut events) =
+            AsyncBackend::new_dummy(vec!["system76/darp6".to_string()]).unwrap();
+        block_on(backend.refresh()).unwrap();
+        let board = loop {
+            match block_on(events.next()) {
+                Some(BackendEvent::BoardAdded(board)) => break board,
+                Some(_) => {}
+                None => panic!("Expected BoardAdded"),
+            }
+        };
+
+        let layout = board.layout();
+        let (i, (output, input)) = layout
+            .physical_keys()
+            .iter()
+            .enumerate()
+            .find_map(|(i, key)| Some((i, layout.electrical(&key.logical_name())?)))
+            .unwrap();
+        block_on(board.keymap_set(1, output, input, 0x29)).unwrap();
+        assert_eq!(board.keys()[i].scancodes[1], 0x29);
+
+        block_on(board.set_brightness(0xff, 42)).unwrap();
+        block_on(board.set_color(0xff, (1, 2, 3))).unwrap();
+        block_on(board.set_mode(0, 2, 3)).unwrap();
+        let layer = &board.layers()[0];
+        assert_eq!(layer.brightness, 42);
+        assert_eq!(layer.color, Some((1, 2, 3)));
+        assert_eq!(layer.mode, Some((2, 3)));
+
+        // Failed changes leave the state as it was
+        assert!(block_on(board.set_brightness(0xf0, 7)).is_err());
+        assert_eq!(board.layers()[0].brightness, 42);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_socket() {
+        use std::{env, process};
+
+        let path = env::temp_dir().join(format!(
+            "keyboard-configurator-stale-{}.sock",
+            process::id()
+        ));
+        drop(bind_socket(&path, None).unwrap());
+        // The socket file remains, and is replaced
+        let _listener = bind_socket(&path, None).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        fs::remove_file(&path).unwrap();
+
+        // Other files are not
+        fs::write(&path, "").unwrap();
+        assert!(bind_socket(&path, None).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,19 +1,18 @@
+use futures::prelude::*;
 use glib::{
-    clone,
     prelude::*,
     subclass::{prelude::*, Signal},
     SignalHandlerId,
 };
 use once_cell::sync::Lazy;
-use std::{cell::RefCell, collections::HashMap, process, sync::Arc, time::Duration};
+use std::{cell::RefCell, collections::HashMap, time::Duration};
 
-use crate::daemon::*;
-use crate::{Board, DerefCell};
+use crate::{AsyncBackend, BackendEvent, BackendEvents, Board, BoardId, DerefCell};
 
 #[derive(Default)]
 #[doc(hidden)]
 pub struct BackendInner {
-    thread_client: DerefCell<Arc<ThreadClient>>,
+    core: DerefCell<AsyncBackend>,
     boards: RefCell<HashMap<BoardId, Board>>,
 }
 
@@ -48,7 +47,7 @@ impl ObjectImpl for BackendInner {
     }
 
     fn dispose(&self, _obj: &Self::Type) {
-        self.thread_client.close();
+        self.core.close();
     }
 }
 
@@ -57,50 +56,63 @@ glib::wrapper! {
 }
 
 impl Backend {
-    fn new_internal<T: Daemon + 'static>(daemon: T) -> Result<Self, String> {
+    pub(crate) fn new_internal(
+        (core, mut events): (AsyncBackend, BackendEvents),
+    ) -> Result<Self, String> {
         let self_ = glib::Object::new::<Self>(&[]).unwrap();
-        let thread_client = ThreadClient::new(
-            Box::new(daemon),
-            clone!(@weak self_ => move |response| {
-                match response {
-                    ThreadResponse::BoardLoading => {
+        self_.inner().core.set(core);
+
+        let weak = self_.downgrade();
+        glib::MainContext::default().spawn_local(async move {
+            while let Some(event) = events.next().await {
+                let self_ = match weak.upgrade() {
+                    Some(self_) => self_,
+                    None => break,
+                };
+                match event {
+                    BackendEvent::BoardLoading => {
                         self_.emit_by_name("board-loading", &[]).unwrap();
-                    },
-                    ThreadResponse::BoardLoadingDone => {
+                    }
+                    BackendEvent::BoardLoadingDone => {
                         self_.emit_by_name("board-loading-done", &[]).unwrap();
-                    },
-                    ThreadResponse::BoardAdded(board) => {
+                    }
+                    BackendEvent::BoardAdded(core) => {
+                        let board = Board::new(core);
                         self_.emit_by_name("board-added", &[&board]).unwrap();
-                        self_.inner().boards.borrow_mut().insert(board.board(), board);
-                    },
-                    ThreadResponse::BoardRemoved(id) => {
-                        let boards = self_.inner().boards.borrow();
-                        let board = &boards[&id];
-                        self_.emit_by_name("board-removed", &[board]).unwrap();
-                        board.emit_by_name("removed", &[]).unwrap();
-                    },
+                        self_
+                            .inner()
+                            .boards
+                            .borrow_mut()
+                            .insert(board.board(), board);
+                    }
+                    BackendEvent::BoardRemoved(id) => {
+                        let board = self_.inner().boards.borrow_mut().remove(&id);
+                        if let Some(board) = board {
+                            self_.emit_by_name("board-removed", &[&board]).unwrap();
+                            board.emit_by_name("removed", &[]).unwrap();
+                        }
+                    }
                 }
-            }),
-        );
-        self_.inner().thread_client.set(thread_client);
+            }
+        });
         Ok(self_)
     }
 
     pub fn new_dummy(board_names: Vec<String>) -> Result<Self, String> {
-        Self::new_internal(DaemonDummy::new(board_names))
+        Self::new_internal(AsyncBackend::new_dummy(board_names)?)
     }
 
     #[cfg(target_os = "linux")]
     pub fn new_s76power() -> Result<Self, String> {
-        Self::new_internal(DaemonS76Power::new()?)
+        Self::new_internal(AsyncBackend::new_s76power()?)
     }
 
     pub fn new_pkexec() -> Result<Self, String> {
-        Self::new_internal(DaemonClient::new_pkexec())
+        Self::new_internal(AsyncBackend::new_pkexec()?)
     }
 
     pub fn new() -> Result<Self, String> {
-        Self::new_internal(DaemonServer::new_stdio()?)
+        Self::new_internal(AsyncBackend::new()?)
     }
 
     fn inner(&self) -> &BackendInner {
@@ -113,16 +125,37 @@ impl Backend {
     pub fn refresh(&self) {
         let self_ = self.clone();
         glib::MainContext::default().spawn_local(async move {
-            if let Err(err) = self_.inner().thread_client.refresh().await {
+            if let Err(err) = self_.inner().core.refresh().await {
                 error!("Failed to refresh boards: {}", err);
             }
         });
     }
 
+    /// Test for added/removed boards, completing once new boards are loaded
+    ///
+    /// Signals for the changes may still be pending on the main context when
+    /// this returns.
+    pub async fn refresh_async(&self) -> Result<(), String> {
+        self.inner().core.refresh().await
+    }
+
+    /// Boards currently connected, ordered by model
+    pub fn boards(&self) -> Vec<Board> {
+        let mut boards = self
+            .inner()
+            .boards
+            .borrow()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        boards.sort_by(|a, b| a.model().cmp(b.model()).then(a.board().cmp(&b.board())));
+        boards
+    }
+
     pub fn set_matrix_get_rate(&self, rate: Option<Duration>) {
         let self_ = self.clone();
         glib::MainContext::default().spawn_local(async move {
-            let _ = self_.inner().thread_client.set_matrix_get_rate(rate).await;
+            let _ = self_.inner().core.set_matrix_get_rate(rate).await;
         });
     }
 
@@ -158,9 +191,3 @@ impl Backend {
         .unwrap()
     }
 }
-
-pub fn run_daemon() -> ! {
-    let server = DaemonServer::new_stdio().expect("Failed to create server");
-    server.run().expect("Failed to run server");
-    process::exit(0)
-}
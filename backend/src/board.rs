@@ -1,30 +1,25 @@
-use futures::{channel::mpsc as async_mpsc, prelude::*};
+use futures::{prelude::*, stream::FuturesUnordered};
 use glib::{
     prelude::*,
     subclass::{prelude::*, Signal},
     SignalHandlerId,
 };
 use once_cell::sync::Lazy;
-use std::{cell::Cell, collections::HashMap, sync::Arc};
+use std::{cell::Cell, collections::HashMap, pin::Pin};
 
-use crate::daemon::ThreadClient;
-use crate::{BoardId, Daemon, DerefCell, Key, KeyMap, KeyMapLayer, Layer, Layout, Matrix};
+use crate::{
+    AsyncBoard, BoardId, DerefCell, Key, KeyMap, KeyMapImportOptions, KeyMapLayer, Layer, Layout,
+    Matrix, Mode,
+};
 
 #[derive(Default)]
 #[doc(hidden)]
 pub struct BoardInner {
-    thread_client: DerefCell<Arc<ThreadClient>>,
-    board: DerefCell<BoardId>,
-    model: DerefCell<String>,
-    layout: DerefCell<Layout>,
+    core: DerefCell<AsyncBoard>,
     keys: DerefCell<Vec<Key>>,
     layers: DerefCell<Vec<Layer>>,
-    max_brightness: DerefCell<i32>,
     leds_changed: Cell<bool>,
-    has_led_save: DerefCell<bool>,
     led_save_blocked: Cell<bool>,
-    has_matrix: DerefCell<bool>,
-    is_fake: DerefCell<bool>,
 }
 
 #[glib::object_subclass]
@@ -51,78 +46,56 @@ glib::wrapper! {
     pub struct Board(ObjectSubclass<BoardInner>);
 }
 
-unsafe impl Send for Board {}
-
 impl Board {
-    pub fn new(
-        daemon: &dyn Daemon,
-        thread_client: Arc<ThreadClient>,
-        board: BoardId,
-        mut matrix_reciever: async_mpsc::UnboundedReceiver<Matrix>,
-    ) -> Result<Self, String> {
-        let model = match daemon.model(board) {
-            Ok(model) => model,
-            Err(err) => {
-                return Err(format!("Failed to get board model: {}", err));
-            }
-        };
-        let layout = Layout::from_board(&model)
-            .ok_or_else(|| format!("Failed to locate layout for '{}'", model))?;
-
-        let max_brightness = daemon.max_brightness(board).unwrap_or_else(|err| {
-            error!("Error getting max brightness: {}", err);
-            100
-        });
-
-        let num_layers = if layout.meta.has_per_layer {
-            layout.meta.num_layers
-        } else {
-            1
-        };
-
-        let has_led_save = daemon.led_save(board).is_ok();
-        let has_matrix = daemon.matrix_get(board).is_ok();
-
+    /// Wrap `core`, which must be used from the thread of the main context
+    pub fn new(core: AsyncBoard) -> Self {
         let self_ = glib::Object::new::<Board>(&[]).unwrap();
-        self_.inner().thread_client.set(thread_client);
-        self_.inner().board.set(board);
-        self_.inner().model.set(model);
-        self_.inner().layout.set(layout);
-        self_.inner().max_brightness.set(max_brightness);
-        self_.inner().has_led_save.set(has_led_save);
-        self_.inner().has_matrix.set(has_matrix);
-        self_.inner().is_fake.set(daemon.is_fake());
+        self_.inner().core.set(core.clone());
 
         let keys = self_
             .layout()
             .physical
             .keys
             .iter()
-            .map(|i| Key::new(daemon, &self_, i))
+            .zip(core.keys())
+            .map(|(physical_key, state)| Key::new(&self_, physical_key, &state))
             .collect();
         self_.inner().keys.set(keys);
 
-        let layers = (0..num_layers)
-            .map(|layer| Layer::new(daemon, &self_, layer))
+        let layers = core
+            .layers()
+            .iter()
+            .enumerate()
+            .map(|(layer, state)| Layer::new(&self_, layer as u8, state))
             .collect();
         self_.inner().layers.set(layers);
 
-        {
-            let self_ = self_.clone();
-            glib::MainContext::default().spawn(async move {
-                while let Some(matrix) = matrix_reciever.next().await {
-                    for key in self_.keys() {
-                        let pressed = matrix
-                            .get(key.electrical.0 as usize, key.electrical.1 as usize)
-                            .unwrap_or(false);
-                        key.pressed.set(pressed);
+        if let Some(mut matrix_stream) = core.take_matrix_stream() {
+            let self_ = self_.downgrade();
+            glib::MainContext::default().spawn_local(async move {
+                let mut last_matrix = Matrix::default();
+                while let Some(matrix) = matrix_stream.next().await {
+                    let self_ = match self_.upgrade() {
+                        Some(self_) => self_,
+                        None => break,
+                    };
+                    for event in last_matrix.diff(&matrix) {
+                        let (row, col) = event.position();
+                        for key in self_.keys() {
+                            if (usize::from(key.electrical.0), usize::from(key.electrical.1))
+                                == (row, col)
+                            {
+                                key.pressed.set(event.is_pressed());
+                            }
+                        }
                     }
+                    last_matrix = matrix;
                     self_.emit_by_name("matrix-changed", &[]).unwrap();
                 }
             });
         }
 
-        Ok(self_)
+        self_
     }
 
     fn inner(&self) -> &BoardInner {
@@ -151,19 +124,20 @@ impl Board {
     }
 
     pub fn board(&self) -> BoardId {
-        *self.inner().board
+        self.core().board()
     }
 
-    pub(crate) fn thread_client(&self) -> &ThreadClient {
-        &self.inner().thread_client
+    /// Board this wraps, which doesn't depend on GLib
+    pub fn core(&self) -> &AsyncBoard {
+        &self.inner().core
     }
 
     pub fn model(&self) -> &str {
-        &self.inner().model
+        self.core().model()
     }
 
     pub fn has_matrix(&self) -> bool {
-        *self.inner().has_matrix
+        self.core().has_matrix()
     }
 
     pub fn connect_matrix_changed<F: Fn() + 'static>(&self, cb: F) -> SignalHandlerId {
@@ -175,7 +149,7 @@ impl Board {
     }
 
     pub fn max_brightness(&self) -> i32 {
-        *self.inner().max_brightness
+        self.core().max_brightness()
     }
 
     pub async fn led_save(&self) -> Result<(), String> {
@@ -183,7 +157,7 @@ impl Board {
             return Ok(());
         }
         if self.has_led_save() && self.inner().leds_changed.get() {
-            self.thread_client().led_save(self.board()).await?;
+            self.core().led_save().await?;
             self.inner().leds_changed.set(false);
             debug!("led_save");
         }
@@ -199,15 +173,15 @@ impl Board {
     }
 
     pub fn is_fake(&self) -> bool {
-        *self.inner().is_fake
+        self.core().is_fake()
     }
 
     pub fn has_led_save(&self) -> bool {
-        *self.inner().has_led_save
+        self.core().has_led_save()
     }
 
     pub fn layout(&self) -> &Layout {
-        &*self.inner().layout
+        self.core().layout()
     }
 
     pub fn layers(&self) -> &[Layer] {
@@ -247,4 +221,82 @@ impl Board {
             layers,
         }
     }
+
+    /// Apply the parts of `keymap` selected by `options` to the board
+    ///
+    /// Keys and layers that don't exist on this board are skipped. Errors
+    /// setting individual keys or layers are logged, and don't stop the import.
+    pub async fn import_keymap(&self, keymap: &KeyMap, options: &KeyMapImportOptions) {
+        let key_indices = self
+            .keys()
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.logical_name.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        let key = |name: &str| match key_indices.get(name) {
+            Some(i) => Some(&self.keys()[*i]),
+            None => {
+                error!("Keymap contains unknown key '{}'", name);
+                None
+            }
+        };
+        let num_layers = self.layout().meta.num_layers as usize;
+
+        let futures = FuturesUnordered::<Pin<Box<dyn Future<Output = ()>>>>::new();
+
+        for (k, layer, scancode_name) in options.selected_scancodes(keymap, num_layers) {
+            let key = match key(k) {
+                Some(key) => key,
+                None => continue,
+            };
+            futures.push(Box::pin(async move {
+                if let Err(err) = key.set_scancode(layer, scancode_name).await {
+                    error!("Failed to set keymap: {}", err);
+                }
+            }));
+        }
+
+        for (k, hs) in options.selected_key_leds(keymap) {
+            let key = match key(k) {
+                Some(key) => key,
+                None => continue,
+            };
+            futures.push(Box::pin(async move {
+                if let Err(err) = key.set_color(hs).await {
+                    error!("Failed to key LED: {}", err);
+                }
+            }));
+        }
+
+        let has_per_layer = self.layout().meta.has_per_layer;
+        for (i, keymap_layer) in options.selected_layers(keymap, has_per_layer) {
+            let layer = match self.layers().get(i) {
+                Some(layer) => layer,
+                None => {
+                    error!("Keymap contains unknown layer {}", i);
+                    continue;
+                }
+            };
+            futures.push(Box::pin(async move {
+                if let Some((mode, speed)) = keymap_layer.mode {
+                    match Mode::from_index(mode) {
+                        Some(mode) => {
+                            if let Err(err) = layer.set_mode(mode, speed).await {
+                                error!("Failed to set layer mode: {}", err)
+                            }
+                        }
+                        None => error!("Keymap contains unknown mode {}", mode),
+                    }
+                }
+                if let Err(err) = layer.set_brightness(keymap_layer.brightness).await {
+                    error!("Failed to set layer brightness: {}", err)
+                }
+                if let Err(err) = layer.set_color(keymap_layer.color).await {
+                    error!("Failed to set layer color: {}", err)
+                }
+            }));
+        }
+
+        futures.collect::<()>().await;
+    }
 }
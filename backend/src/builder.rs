@@ -0,0 +1,202 @@
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::daemon::*;
+#[cfg(feature = "glib")]
+use crate::Backend;
+use crate::{AsyncBackend, BackendEvents, ViaDefinition};
+
+/// Way of finding boards, for `BackendBuilder`
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendSource {
+    /// Access boards in this process, which requires running as root
+    Direct,
+    /// Access boards through a daemon running as root, started with `pkexec`
+    Pkexec,
+    /// Access boards through a daemon listening on this socket, started with
+    /// `--daemon-socket`, which the user needs to be in the group of
+    #[cfg(unix)]
+    Socket(PathBuf),
+    /// Keyboard backlight of system76-power, over D-Bus, shown as a darp6 on
+    /// any laptop, so not used by default
+    #[cfg(target_os = "linux")]
+    S76Power,
+    /// Keyboard backlights of the LED class in sysfs, other than those of
+    /// System76 laptops, for lighting only; changing them needs write access
+    /// to sysfs, normally as root, so not used by default
+    #[cfg(target_os = "linux")]
+    Sysfs,
+    /// QMK keyboards with VIA enabled, matching these definitions, over USB
+    Via(Vec<ViaDefinition>),
+    /// Fake boards with the given models
+    Dummy(Vec<String>),
+}
+
+impl BackendSource {
+    /// `Direct` if running as root, otherwise `Pkexec`
+    pub fn root() -> Self {
+        #[cfg(target_os = "linux")]
+        if unsafe { libc::geteuid() != 0 } {
+            return Self::Pkexec;
+        }
+        Self::Direct
+    }
+
+    /// Sources used when none are chosen, in priority order
+    pub fn defaults() -> Vec<Self> {
+        vec![Self::root()]
+    }
+
+    /// Parse sources separated by commas, like `root,s76power`
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        s.split(',').map(str::parse).collect()
+    }
+
+    /// Whether the source can find the computer's built-in keyboard, which
+    /// may then also be found by other such sources
+    fn finds_builtin(&self) -> bool {
+        !matches!(self, Self::Via(_) | Self::Dummy(_))
+    }
+
+    fn daemon(&self) -> Result<Box<dyn Daemon>, String> {
+        Ok(match self {
+            Self::Direct => Box::new(DaemonServer::new_stdio()?),
+            Self::Pkexec => Box::new(DaemonClient::new_pkexec()?),
+            #[cfg(unix)]
+            Self::Socket(path) => Box::new(DaemonClient::new_socket(path)?),
+            #[cfg(target_os = "linux")]
+            Self::S76Power => Box::new(DaemonS76Power::new()?),
+            #[cfg(target_os = "linux")]
+            Self::Sysfs => Box::new(DaemonSysfs::new()?),
+            Self::Via(definitions) => Box::new(DaemonVia::new(definitions.clone())?),
+            Self::Dummy(board_names) => Box::new(DaemonDummy::new(board_names.clone())),
+        })
+    }
+}
+
+/// Parses the sources that need no other data: `root`, `direct`, `pkexec`,
+/// `s76power`, `sysfs`, and `socket:PATH`
+impl FromStr for BackendSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        #[cfg(unix)]
+        if let Some(path) = s.strip_prefix("socket:") {
+            return Ok(Self::Socket(PathBuf::from(path)));
+        }
+        match s {
+            "root" => Ok(Self::root()),
+            "direct" => Ok(Self::Direct),
+            "pkexec" => Ok(Self::Pkexec),
+            #[cfg(target_os = "linux")]
+            "s76power" => Ok(Self::S76Power),
+            #[cfg(target_os = "linux")]
+            "sysfs" => Ok(Self::Sysfs),
+            _ => Err(format!("Unknown backend source '{}'", s)),
+        }
+    }
+}
+
+/// Backend combining the boards of several `BackendSource`s
+///
+/// Sources are in priority order. If a source fails to start, the others are
+/// still used. Since the computer's built-in keyboard can be found by several
+/// sources, such as `root` and `S76Power`, a board of those sources is only
+/// shown from the first that has a board of its model.
+///
+/// ```no_run
+/// use system76_keyboard_configurator_backend::{BackendBuilder, BackendSource};
+///
+/// let backend = BackendBuilder::new()
+///     .source(BackendSource::root())
+///     .dummy(vec!["system76/launch_1".to_string()])
+///     .build()?;
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BackendBuilder {
+    sources: Vec<BackendSource>,
+}
+
+impl BackendBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `source`, with lower priority than those already added
+    pub fn source(mut self, source: BackendSource) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Add fake boards, unless `board_names` is empty
+    pub fn dummy(self, board_names: Vec<String>) -> Self {
+        if board_names.is_empty() {
+            self
+        } else {
+            self.source(BackendSource::Dummy(board_names))
+        }
+    }
+
+    pub fn sources(&self) -> &[BackendSource] {
+        &self.sources
+    }
+
+    /// Start each source, failing only if none of them start
+    pub fn build_async(self) -> Result<(AsyncBackend, BackendEvents), String> {
+        let mut daemons = Vec::new();
+        let mut errors = Vec::new();
+        for source in &self.sources {
+            match source.daemon() {
+                Ok(daemon) => daemons.push((daemon, source.finds_builtin())),
+                Err(err) => {
+                    warn!("Failed to start {:?} backend: {}", source, err);
+                    errors.push(format!("{:?}: {}", source, err));
+                }
+            }
+        }
+
+        if daemons.is_empty() {
+            if errors.is_empty() {
+                return Err("No backend sources".to_string());
+            }
+            return Err(format!("Failed to start backends: {}", errors.join("; ")));
+        }
+
+        Ok(AsyncBackend::new_internal(DaemonMulti::new(daemons)))
+    }
+
+    #[cfg(feature = "glib")]
+    pub fn build(self) -> Result<Backend, String> {
+        Backend::new_internal(self.build_async()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_sources() {
+        assert!(BackendBuilder::new().build_async().is_err());
+        assert!(BackendBuilder::new().dummy(Vec::new()).sources().is_empty());
+    }
+
+    #[test]
+    fn parse_source() {
+        assert_eq!("direct".parse(), Ok(BackendSource::Direct));
+        assert_eq!("root".parse(), Ok(BackendSource::root()));
+        #[cfg(unix)]
+        assert_eq!(
+            "socket:/run/keyboard.sock".parse(),
+            Ok(BackendSource::Socket(PathBuf::from("/run/keyboard.sock")))
+        );
+        assert!("socket".parse::<BackendSource>().is_err());
+        assert!("dummy".parse::<BackendSource>().is_err());
+        assert_eq!(
+            BackendSource::parse_list("direct,pkexec"),
+            Ok(vec![BackendSource::Direct, BackendSource::Pkexec])
+        );
+    }
+}
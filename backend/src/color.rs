@@ -8,20 +8,9 @@ type PaletteLinSrgb = palette::LinSrgb<f64>;
 
 /// Floating point hue/saturation color
 #[derive(
-    Clone,
-    Copy,
-    Debug,
-    Serialize,
-    Deserialize,
-    Default,
-    PartialEq,
-    glib::GBoxed,
-    Hash,
-    Eq,
-    Ord,
-    PartialOrd,
+    Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Hash, Eq, Ord, PartialOrd,
 )]
-#[gboxed(type_name = "S76Hs")]
+#[cfg_attr(feature = "glib", derive(glib::GBoxed), gboxed(type_name = "S76Hs"))]
 pub struct Hs {
     /// Hue, in radians
     pub h: NotNan<f64>,
@@ -56,8 +45,8 @@ impl Hs {
 }
 
 /// Integer RGB color
-#[derive(Clone, Copy, Debug, Default, glib::GBoxed)]
-#[gboxed(type_name = "S76Rgb")]
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "glib", derive(glib::GBoxed), gboxed(type_name = "S76Rgb"))]
 pub struct Rgb {
     /// Red
     pub r: u8,
@@ -1,21 +1,24 @@
 use std::{
     cell::RefCell,
     env,
-    io::{BufRead, BufReader, Write},
-    path::PathBuf,
-    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
 };
 
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
 use super::{err_str, Daemon, DaemonClientTrait, DaemonCommand, DaemonResponse};
 
 pub struct DaemonClient {
-    child: Child,
-    read: RefCell<BufReader<ChildStdout>>,
-    write: RefCell<ChildStdin>,
+    child: Option<Child>,
+    read: RefCell<BufReader<Box<dyn Read + Send>>>,
+    write: RefCell<Box<dyn Write + Send>>,
 }
 
 impl DaemonClient {
-    pub fn new_pkexec() -> Self {
+    pub fn new_pkexec() -> Result<Self, String> {
         // Use canonicalized command name
         let command_path = if cfg!(feature = "appimage") {
             PathBuf::from(env::var("APPIMAGE").expect("Failed to get executable path"))
@@ -29,25 +32,55 @@ impl DaemonClient {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
-            .expect("Failed to spawn daemon");
+            .map_err(|err| format!("Failed to spawn daemon: {}", err))?;
 
         let stdin = child.stdin.take().unwrap();
-        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let stdout = child.stdout.take().unwrap();
+        Self::new_internal(Some(child), Box::new(stdout), Box::new(stdin))
+            .map_err(|_| "Failed to start daemon with pkexec".to_string())
+    }
+
+    /// Connect to a daemon started with `--daemon-socket`
+    #[cfg(unix)]
+    pub fn new_socket(path: &Path) -> Result<Self, String> {
+        let stream = UnixStream::connect(path)
+            .map_err(|err| format!("Failed to connect to {}: {}", path.display(), err))?;
+        let read = stream.try_clone().map_err(err_str)?;
+        Self::new(read, stream)
+    }
+
+    /// Use a `DaemonServer` running at the other end of `read` and `write`
+    pub fn new<R: Read + Send + 'static, W: Write + Send + 'static>(
+        read: R,
+        write: W,
+    ) -> Result<Self, String> {
+        Self::new_internal(None, Box::new(read), Box::new(write))
+    }
+
+    fn new_internal(
+        mut child: Option<Child>,
+        read: Box<dyn Read + Send>,
+        write: Box<dyn Write + Send>,
+    ) -> Result<Self, String> {
+        let mut read = BufReader::new(read);
 
         // Check if daemon has started
         let mut line = String::new();
-        if let Ok(count) = stdout.read_line(&mut line) {
-            // pkexec terminated returning EOF
+        if let Ok(count) = read.read_line(&mut line) {
+            // Daemon terminated returning EOF
             if count == 0 {
-                panic!("Failed to start daemon with pkexec");
+                if let Some(child) = &mut child {
+                    let _ = child.wait();
+                }
+                return Err("Failed to start daemon".to_string());
             }
         }
 
-        Self {
+        Ok(Self {
             child,
-            read: RefCell::new(stdout),
-            write: RefCell::new(stdin),
-        }
+            read: RefCell::new(read),
+            write: RefCell::new(write),
+        })
     }
 }
 
@@ -55,10 +88,11 @@ impl DaemonClientTrait for DaemonClient {
     fn send_command(&self, command: DaemonCommand) -> Result<DaemonResponse, String> {
         let mut command_json = serde_json::to_string(&command).map_err(err_str)?;
         command_json.push('\n');
-        self.write
-            .borrow_mut()
-            .write_all(command_json.as_bytes())
-            .map_err(err_str)?;
+        {
+            let mut write = self.write.borrow_mut();
+            write.write_all(command_json.as_bytes()).map_err(err_str)?;
+            write.flush().map_err(err_str)?;
+        }
 
         let mut response_json = String::new();
         self.read
@@ -73,9 +107,11 @@ impl Drop for DaemonClient {
     fn drop(&mut self) {
         let _ = self.exit();
 
-        let status = self.child.wait().expect("Failed to wait for daemon");
-        if !status.success() {
-            panic!("Failed to run daemon with exit status {:?}", status);
+        if let Some(child) = &mut self.child {
+            let status = child.wait().expect("Failed to wait for daemon");
+            if !status.success() {
+                panic!("Failed to run daemon with exit status {:?}", status);
+            }
         }
     }
 }
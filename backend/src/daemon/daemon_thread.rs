@@ -6,7 +6,6 @@ use futures::{
     task::LocalSpawnExt,
 };
 use futures_timer::Delay;
-use glib::clone;
 use std::{
     cell::{Cell, RefCell},
     cmp::PartialEq,
@@ -19,7 +18,7 @@ use std::{
 };
 
 use super::{BoardId, Daemon, Matrix};
-use crate::Board;
+use crate::{AsyncBoard, BackendEvent, BackendEvents};
 
 #[derive(Clone, Debug)]
 struct Item<K: Hash + Eq, V> {
@@ -78,23 +77,18 @@ pub struct ThreadClient {
 }
 
 impl ThreadClient {
-    pub fn new<F: Fn(ThreadResponse) + 'static>(daemon: Box<dyn Daemon>, cb: F) -> Arc<Self> {
+    pub fn new(daemon: Box<dyn Daemon>) -> (Arc<Self>, BackendEvents) {
         let (sender, reciever) = async_mpsc::unbounded();
         let client = Arc::new(Self {
             cancels: Mutex::new(HashMap::new()),
             channel: sender,
             join_handle: Mutex::new(None),
         });
-        let (response_sender, mut response_reciever) = async_mpsc::unbounded();
-        glib::MainContext::default().spawn_local(async move {
-            while let Some(response) = response_reciever.next().await {
-                cb(response)
-            }
-        });
+        let (response_sender, response_reciever) = async_mpsc::unbounded();
 
         let join_handle = Thread::new(daemon, client.clone(), response_sender).spawn(reciever);
         *client.join_handle.lock().unwrap() = Some(join_handle);
-        client
+        (client, response_reciever)
     }
 
     async fn send(&self, set_enum: SetEnum) -> Result<(), String> {
@@ -196,13 +190,6 @@ impl ThreadClient {
     }
 }
 
-pub enum ThreadResponse {
-    BoardLoading,
-    BoardLoadingDone,
-    BoardAdded(Board),
-    BoardRemoved(BoardId),
-}
-
 struct ThreadBoard {
     matrix: Matrix,
     matrix_channel: async_mpsc::UnboundedSender<Matrix>,
@@ -223,7 +210,7 @@ struct Thread {
     daemon: Box<dyn Daemon>,
     boards: RefCell<HashMap<BoardId, ThreadBoard>>,
     client: Weak<ThreadClient>,
-    response_channel: async_mpsc::UnboundedSender<ThreadResponse>,
+    response_channel: async_mpsc::UnboundedSender<BackendEvent>,
     matrix_get_rate: Cell<Option<Duration>>,
 }
 
@@ -231,7 +218,7 @@ impl Thread {
     fn new(
         daemon: Box<dyn Daemon>,
         client: Arc<ThreadClient>,
-        response_channel: async_mpsc::UnboundedSender<ThreadResponse>,
+        response_channel: async_mpsc::UnboundedSender<BackendEvent>,
     ) -> Self {
         Self {
             daemon,
@@ -249,18 +236,21 @@ impl Thread {
 
             let self_ = Rc::new(self);
 
-            spawner
-                .spawn_local(clone!(@strong self_ => async move {
-                    loop {
-                        if let Some(rate) = self_.matrix_get_rate.get() {
-                            Delay::new(rate).await;
-                            self_.matrix_refresh_all();
-                        } else {
-                            Delay::new(Duration::from_millis(100)).await;
+            {
+                let self_ = self_.clone();
+                spawner
+                    .spawn_local(async move {
+                        loop {
+                            if let Some(rate) = self_.matrix_get_rate.get() {
+                                Delay::new(rate).await;
+                                self_.matrix_refresh_all();
+                            } else {
+                                Delay::new(Duration::from_millis(100)).await;
+                            }
                         }
-                    }
-                }))
-                .unwrap();
+                    })
+                    .unwrap();
+            }
 
             pool.run_until(async move {
                 while let Some(set) = channel.next().await {
@@ -332,7 +322,7 @@ impl Thread {
         let response_channel = &self.response_channel;
         boards.retain(|id, _| {
             if new_ids.iter().find(|i| *i == id).is_none() {
-                let _ = response_channel.unbounded_send(ThreadResponse::BoardRemoved(*id));
+                let _ = response_channel.unbounded_send(BackendEvent::BoardRemoved(*id));
                 return false;
             }
             true
@@ -348,12 +338,12 @@ impl Thread {
             if !have_new_board {
                 let _ = self
                     .response_channel
-                    .unbounded_send(ThreadResponse::BoardLoading);
+                    .unbounded_send(BackendEvent::BoardLoading);
                 have_new_board = true;
             }
 
             let (matrix_sender, matrix_reciever) = async_mpsc::unbounded();
-            match Board::new(
+            match AsyncBoard::load(
                 self.daemon.as_ref(),
                 self.client.upgrade().unwrap(),
                 *i,
@@ -363,7 +353,7 @@ impl Thread {
                     boards.insert(*i, ThreadBoard::new(matrix_sender, board.has_matrix()));
                     let _ = self
                         .response_channel
-                        .unbounded_send(ThreadResponse::BoardAdded(board));
+                        .unbounded_send(BackendEvent::BoardAdded(board));
                 }
                 Err(err) => error!("Failed to add board: {}", err),
             }
@@ -372,7 +362,7 @@ impl Thread {
         if have_new_board {
             let _ = self
                 .response_channel
-                .unbounded_send(ThreadResponse::BoardLoadingDone);
+                .unbounded_send(BackendEvent::BoardLoadingDone);
         }
 
         Ok(())
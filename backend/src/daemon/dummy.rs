@@ -49,7 +49,7 @@ impl Daemon for DaemonDummy {
         Ok(self.board(board)?.name.clone())
     }
 
-    fn is_fake(&self) -> bool {
+    fn is_fake(&self, _board: BoardId) -> bool {
         true
     }
 
@@ -72,7 +72,7 @@ impl Daemon for DaemonDummy {
     }
 
     fn matrix_get(&self, _board: BoardId) -> Result<Matrix, String> {
-        Ok(Matrix::new(0, 0, Vec::new().into_boxed_slice()))
+        Ok(Matrix::default())
     }
 
     fn color(&self, board: BoardId, index: u8) -> Result<(u8, u8, u8), String> {
@@ -0,0 +1,68 @@
+// Keymap and switch matrix of emulated keyboards, for testing without hardware
+//
+// Emulators are cloneable handles to shared state, so keys can be pressed
+// while a daemon uses one.
+
+/// Keymap of each layer, and pressed keys, of a matrix of `rows` by `cols`
+pub(crate) struct EmulatedMatrix {
+    pub rows: u8,
+    pub cols: u8,
+    pub num_layers: u8,
+    pub keymap: Vec<u16>,
+    /// Pressed state of each key, by row
+    pub pressed: Vec<bool>,
+}
+
+impl EmulatedMatrix {
+    /// Matrix with empty layers, and no keys pressed
+    pub fn new(rows: u8, cols: u8, num_layers: u8) -> Self {
+        let keys = usize::from(rows) * usize::from(cols);
+        Self {
+            rows,
+            cols,
+            num_layers,
+            keymap: vec![0; keys * usize::from(num_layers)],
+            pressed: vec![false; keys],
+        }
+    }
+
+    /// Index in `keymap`, if in range
+    pub fn key_index(&self, layer: u8, row: u8, col: u8) -> Option<usize> {
+        if layer < self.num_layers && row < self.rows && col < self.cols {
+            let (rows, cols) = (usize::from(self.rows), usize::from(self.cols));
+            Some((usize::from(layer) * rows + usize::from(row)) * cols + usize::from(col))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_pressed(&mut self, row: u8, col: u8, pressed: bool) {
+        if row < self.rows && col < self.cols {
+            let i = usize::from(row) * usize::from(self.cols) + usize::from(col);
+            self.pressed[i] = pressed;
+        }
+    }
+}
+
+/// Check keymap and matrix access of `daemon`, using an emulated keyboard with
+/// the matrix of `system76/launch_1`
+#[cfg(test)]
+pub fn test_emulated_launch<F: Fn(u8, u8, bool)>(
+    daemon: &dyn super::Daemon,
+    board: super::BoardId,
+    set_pressed: F,
+    keycode: u16,
+) {
+    daemon.keymap_set(board, 1, 2, 3, keycode).unwrap();
+    assert_eq!(daemon.keymap_get(board, 1, 2, 3), Ok(keycode));
+    assert_eq!(daemon.keymap_get(board, 0, 2, 3), Ok(0));
+
+    set_pressed(5, 13, true);
+    set_pressed(0, 2, true);
+    let matrix = daemon.matrix_get(board).unwrap();
+    assert_eq!((matrix.rows(), matrix.cols()), (6, 14));
+    assert_eq!(matrix.pressed().collect::<Vec<_>>(), vec![(0, 2), (5, 13)]);
+    set_pressed(0, 2, false);
+    let matrix = daemon.matrix_get(board).unwrap();
+    assert_eq!(matrix.pressed().collect::<Vec<_>>(), vec![(5, 13)]);
+}
@@ -1,52 +1,31 @@
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+use crate::{Layout, Matrix};
 
 mod client;
 mod daemon_thread;
 mod dummy;
+mod emulator;
+mod multi;
 mod server;
+mod via;
 
 #[cfg(target_os = "linux")]
 mod s76power;
 #[cfg(target_os = "linux")]
 pub use self::s76power::*;
 
-pub use self::{client::*, daemon_thread::*, dummy::*, server::*};
+#[cfg(target_os = "linux")]
+mod sysfs;
+#[cfg(target_os = "linux")]
+pub use self::sysfs::*;
+
+pub use self::{client::*, daemon_thread::*, dummy::*, multi::*, server::*, via::*};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct BoardId(u128);
 
-#[derive(Deserialize, Serialize, Default, PartialEq, Clone)]
-pub struct Matrix {
-    rows: usize,
-    cols: usize,
-    data: Box<[u8]>,
-}
-
-impl Matrix {
-    pub fn new(rows: usize, cols: usize, data: Box<[u8]>) -> Self {
-        Self { rows, cols, data }
-    }
-
-    pub fn rows(&self) -> usize {
-        self.rows
-    }
-
-    pub fn cols(&self) -> usize {
-        self.cols
-    }
-
-    pub fn get(&self, row: usize, col: usize) -> Option<bool> {
-        if row < self.rows && col < self.cols {
-            let i = row * self.cols + col;
-            let byte = i / 8;
-            let bit = i % 8;
-            Some((self.data[byte] & (1 << bit)) != 0)
-        } else {
-            None
-        }
-    }
-}
-
 pub trait DaemonClientTrait: Send + 'static {
     fn send_command(&self, command: DaemonCommand) -> Result<DaemonResponse, String>;
 }
@@ -59,10 +38,16 @@ macro_rules! commands {
             fn $func(&self, $( $arg: $type ),*) -> Result<$ret, String>;
         )*
 
-            fn is_fake(&self) -> bool {
+            fn is_fake(&self, _board: BoardId) -> bool {
                 false
             }
 
+            /// Layout of `board`, for boards that describe their own layout
+            /// instead of having one for their model
+            fn layout(&self, _board: BoardId) -> Option<Layout> {
+                None
+            }
+
             fn dispatch_command_to_method(&self, command: DaemonCommand) -> Result<DaemonResponse, String> {
                 match command {
                 $(
@@ -128,3 +113,25 @@ commands! {
 fn err_str<E: std::fmt::Debug>(err: E) -> String {
     format!("{:?}", err)
 }
+
+/// Source of `BoardId`s for a daemon, never giving the same one twice
+#[derive(Default)]
+struct BoardIds(Cell<u128>);
+
+impl BoardIds {
+    fn next(&self) -> BoardId {
+        let id = self.0.get();
+        self.0.set(id + 1);
+        BoardId(id)
+    }
+}
+
+/// Fail unless `index` is 0xFF, for daemons that only have LED settings for
+/// the whole keyboard
+fn whole_keyboard_led(index: u8, action: &str) -> Result<(), String> {
+    if index == 0xFF {
+        Ok(())
+    } else {
+        Err(format!("Can't {} index {}", action, index))
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use super::{BoardId, BoardIds, Daemon};
+use crate::{Layout, Matrix};
+
+/// Boards of several daemons, in priority order
+///
+/// Each daemon comes with whether it can find the computer's built-in
+/// keyboard. A board of such a daemon is hidden if an earlier one of them has
+/// a board of the same model, since that is probably the same keyboard found
+/// through another path (such as an EC accessed directly and through
+/// system76-power). Other boards are always shown.
+pub struct DaemonMulti {
+    daemons: Vec<(Box<dyn Daemon>, bool)>,
+    // Id of each board of each daemon, and its model
+    ids: RefCell<HashMap<(usize, BoardId), (BoardId, String)>>,
+    boards: RefCell<HashMap<BoardId, (usize, BoardId)>>,
+    board_ids: BoardIds,
+}
+
+impl DaemonMulti {
+    pub fn new(daemons: Vec<(Box<dyn Daemon>, bool)>) -> Self {
+        Self {
+            daemons,
+            ids: RefCell::new(HashMap::new()),
+            boards: RefCell::new(HashMap::new()),
+            board_ids: BoardIds::default(),
+        }
+    }
+
+    fn board(&self, board: BoardId) -> Result<(&dyn Daemon, BoardId), String> {
+        let (i, id) = *self
+            .boards
+            .borrow()
+            .get(&board)
+            .ok_or_else(|| "No board".to_string())?;
+        Ok((self.daemons[i].0.as_ref(), id))
+    }
+}
+
+impl Daemon for DaemonMulti {
+    fn boards(&self) -> Result<Vec<BoardId>, String> {
+        let mut ids = self.ids.borrow_mut();
+        let mut found_ids = HashMap::new();
+        let mut boards = HashMap::new();
+        let mut board_ids = Vec::new();
+        let mut models = HashSet::new();
+
+        for (i, (daemon, builtin)) in self.daemons.iter().enumerate() {
+            let daemon_boards = match daemon.boards() {
+                Ok(daemon_boards) => daemon_boards,
+                Err(err) => {
+                    error!("Failed to list boards: {}", err);
+                    continue;
+                }
+            };
+
+            let mut daemon_models = Vec::new();
+            for board in daemon_boards {
+                let (id, model) = match ids.remove(&(i, board)) {
+                    Some(entry) => entry,
+                    None => {
+                        let model = match daemon.model(board) {
+                            Ok(model) => model,
+                            Err(err) => {
+                                error!("Failed to get board model: {}", err);
+                                continue;
+                            }
+                        };
+                        let id = self.board_ids.next();
+                        (id, model)
+                    }
+                };
+
+                if *builtin && models.contains(&model) {
+                    info!("Ignoring '{}', already found by another daemon", model);
+                } else {
+                    boards.insert(id, (i, board));
+                    board_ids.push(id);
+                }
+                if *builtin {
+                    daemon_models.push(model.clone());
+                }
+                found_ids.insert((i, board), (id, model));
+            }
+            models.extend(daemon_models);
+        }
+
+        *ids = found_ids;
+        *self.boards.borrow_mut() = boards;
+        Ok(board_ids)
+    }
+
+    fn model(&self, board: BoardId) -> Result<String, String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.model(board)
+    }
+
+    fn is_fake(&self, board: BoardId) -> bool {
+        match self.board(board) {
+            Ok((daemon, board)) => daemon.is_fake(board),
+            Err(_) => false,
+        }
+    }
+
+    fn layout(&self, board: BoardId) -> Option<Layout> {
+        let (daemon, board) = self.board(board).ok()?;
+        daemon.layout(board)
+    }
+
+    fn keymap_get(&self, board: BoardId, layer: u8, output: u8, input: u8) -> Result<u16, String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.keymap_get(board, layer, output, input)
+    }
+
+    fn keymap_set(
+        &self,
+        board: BoardId,
+        layer: u8,
+        output: u8,
+        input: u8,
+        value: u16,
+    ) -> Result<(), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.keymap_set(board, layer, output, input, value)
+    }
+
+    fn matrix_get(&self, board: BoardId) -> Result<Matrix, String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.matrix_get(board)
+    }
+
+    fn color(&self, board: BoardId, index: u8) -> Result<(u8, u8, u8), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.color(board, index)
+    }
+
+    fn set_color(&self, board: BoardId, index: u8, color: (u8, u8, u8)) -> Result<(), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.set_color(board, index, color)
+    }
+
+    fn max_brightness(&self, board: BoardId) -> Result<i32, String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.max_brightness(board)
+    }
+
+    fn brightness(&self, board: BoardId, index: u8) -> Result<i32, String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.brightness(board, index)
+    }
+
+    fn set_brightness(&self, board: BoardId, index: u8, brightness: i32) -> Result<(), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.set_brightness(board, index, brightness)
+    }
+
+    fn mode(&self, board: BoardId, layer: u8) -> Result<(u8, u8), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.mode(board, layer)
+    }
+
+    fn set_mode(&self, board: BoardId, layer: u8, mode: u8, speed: u8) -> Result<(), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.set_mode(board, layer, mode, speed)
+    }
+
+    fn led_save(&self, board: BoardId) -> Result<(), String> {
+        let (daemon, board) = self.board(board)?;
+        daemon.led_save(board)
+    }
+
+    fn refresh(&self) -> Result<(), String> {
+        for (daemon, _) in &self.daemons {
+            if let Err(err) = daemon.refresh() {
+                error!("Failed to refresh boards: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn exit(&self) -> Result<(), String> {
+        let mut res = Ok(());
+        for (daemon, _) in &self.daemons {
+            if let Err(err) = daemon.exit() {
+                res = Err(err);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::DaemonDummy;
+
+    fn dummy(models: &[&str], builtin: bool) -> (Box<dyn Daemon>, bool) {
+        let daemon = DaemonDummy::new(models.iter().map(|x| x.to_string()).collect());
+        (Box::new(daemon), builtin)
+    }
+
+    #[test]
+    fn priority_and_dedup() {
+        let daemon = DaemonMulti::new(vec![
+            dummy(&["system76/launch_1"], true),
+            dummy(&["system76/launch_1", "system76/launch_2"], true),
+        ]);
+        let boards = daemon.boards().unwrap();
+        let models = boards
+            .iter()
+            .map(|board| daemon.model(*board).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(models, ["system76/launch_1", "system76/launch_2"]);
+
+        // Commands go to the daemon the board came from
+        daemon.keymap_set(boards[1], 0, 1, 2, 0x29).unwrap();
+        assert_eq!(daemon.keymap_get(boards[1], 0, 1, 2), Ok(0x29));
+        assert_eq!(daemon.keymap_get(boards[0], 0, 1, 2), Ok(0));
+        assert!(daemon.is_fake(boards[0]));
+
+        // Ids are stable between refreshes
+        assert_eq!(daemon.boards().unwrap(), boards);
+    }
+
+    #[test]
+    fn duplicate_boards_of_one_daemon() {
+        let daemon = DaemonMulti::new(vec![dummy(
+            &["system76/launch_1", "system76/launch_1"],
+            true,
+        )]);
+        assert_eq!(daemon.boards().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn no_dedup_without_builtin() {
+        // A fake board isn't hidden by a real one of the same model
+        let daemon = DaemonMulti::new(vec![
+            dummy(&["system76/launch_1"], true),
+            dummy(&["system76/launch_1"], false),
+        ]);
+        assert_eq!(daemon.boards().unwrap().len(), 2);
+
+        // Nor does it hide boards found later
+        let daemon = DaemonMulti::new(vec![
+            dummy(&["system76/launch_1"], false),
+            dummy(&["system76/launch_1"], true),
+        ]);
+        assert_eq!(daemon.boards().unwrap().len(), 2);
+    }
+}
@@ -1,18 +1,19 @@
 #[cfg(target_os = "linux")]
 use ectool::AccessLpcLinux;
-use ectool::{Access, AccessHid, Ec};
+use ectool::{Access, AccessHid, Ec, Error};
 use hidapi::{DeviceInfo, HidApi};
 use std::{
     cell::{Cell, RefCell, RefMut},
     collections::HashMap,
     io::{self, BufRead, BufReader, Read, Write},
     str,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 use uuid::Uuid;
 
-use super::{err_str, BoardId, Daemon, DaemonCommand};
-use crate::Matrix;
+use super::{emulator::EmulatedMatrix, err_str, BoardId, Daemon, DaemonCommand};
+use crate::{Layout, Matrix};
 
 pub struct DaemonServer<R: Read + Send + 'static, W: Write + Send + 'static> {
     hidapi: RefCell<Option<HidApi>>,
@@ -71,6 +72,36 @@ impl<R: Read + Send + 'static, W: Write + Send + 'static> DaemonServer<R, W> {
         })
     }
 
+    /// Use only `ecs`, such as an `EcEmulator`, without probing for hardware
+    pub fn new_with_ecs(read: R, write: W, ecs: Vec<Ec<Box<dyn Access>>>) -> Self {
+        let mut boards = HashMap::new();
+        let mut board_ids = Vec::new();
+        for ec in ecs {
+            let id = BoardId(Uuid::new_v4().as_u128());
+            boards.insert(id, (ec, None));
+            board_ids.push(id);
+        }
+
+        Self {
+            hidapi: RefCell::new(None),
+            running: Cell::new(true),
+            read: BufReader::new(read),
+            write,
+            boards: RefCell::new(boards),
+            board_ids: RefCell::new(board_ids),
+        }
+    }
+
+    /// Use `emulators` as ECs, for testing and fuzzing without hardware
+    pub fn new_emulated(read: R, write: W, emulators: Vec<EcEmulator>) -> Result<Self, String> {
+        let ecs = emulators
+            .into_iter()
+            .map(|emulator| unsafe { Ec::new(emulator) }.map(|ec| ec.into_dyn()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err_str)?;
+        Ok(Self::new_with_ecs(read, write, ecs))
+    }
+
     fn have_device(&self, info: &DeviceInfo) -> bool {
         for (_, i) in self.boards.borrow().values() {
             if let Some(i) = i {
@@ -85,21 +116,27 @@ impl<R: Read + Send + 'static, W: Write + Send + 'static> DaemonServer<R, W> {
     }
 
     pub fn run(mut self) -> io::Result<()> {
-        println!("Daemon started");
+        self.write.write_all(b"Daemon started\n")?;
+        self.write.flush()?;
 
         while self.running.get() {
             let mut command_json = String::new();
-            self.read.read_line(&mut command_json)?;
+            if self.read.read_line(&mut command_json)? == 0 {
+                // Client closed the connection without `exit`
+                break;
+            }
 
-            let command = serde_json::from_str::<DaemonCommand>(&command_json)
-                .expect("failed to deserialize command");
-            let response = self.dispatch_command_to_method(command);
+            let response = match serde_json::from_str::<DaemonCommand>(&command_json) {
+                Ok(command) => self.dispatch_command_to_method(command),
+                Err(err) => Err(format!("Invalid command: {}", err)),
+            };
 
             //TODO: what to do if we fail to serialize result?
             let mut result_json =
                 serde_json::to_string(&response).expect("failed to serialize result");
             result_json.push('\n');
             self.write.write_all(result_json.as_bytes())?;
+            self.write.flush()?;
         }
 
         Ok(())
@@ -153,9 +190,7 @@ impl<R: Read + Send + 'static, W: Write + Send + 'static> Daemon for DaemonServe
         let mut data = vec![0; data_size];
         unsafe { ec.matrix_get(&mut data).map_err(err_str)? };
 
-        let rows = data.remove(0) as usize;
-        let cols = data.remove(0) as usize;
-        Ok(Matrix::new(rows, cols, data.into_boxed_slice()))
+        Matrix::from_ec_data(&data)
     }
 
     fn color(&self, board: BoardId, index: u8) -> Result<(u8, u8, u8), String> {
@@ -276,3 +311,284 @@ impl<R: Read + Send + 'static, W: Write + Send + 'static> Daemon for DaemonServe
         Ok(())
     }
 }
+
+// Commands of the System76 EC protocol, as used by `Ec`
+const CMD_PROBE: u8 = 1;
+const CMD_BOARD: u8 = 2;
+const CMD_KEYMAP_GET: u8 = 9;
+const CMD_KEYMAP_SET: u8 = 10;
+const CMD_LED_GET_VALUE: u8 = 11;
+const CMD_LED_SET_VALUE: u8 = 12;
+const CMD_LED_GET_COLOR: u8 = 13;
+const CMD_LED_SET_COLOR: u8 = 14;
+const CMD_LED_GET_MODE: u8 = 15;
+const CMD_LED_SET_MODE: u8 = 16;
+const CMD_MATRIX_GET: u8 = 17;
+const CMD_LED_SAVE: u8 = 18;
+
+const RES_OK: u8 = 0;
+const RES_ERR: u8 = 1;
+
+/// Data size of an `EcEmulator`, enough for the board string and matrix
+const EMULATOR_DATA_SIZE: usize = 256;
+
+struct EcEmulatorState {
+    board: String,
+    matrix: EmulatedMatrix,
+    /// Brightness and color of each LED index
+    leds: HashMap<u8, (u8, (u8, u8, u8))>,
+    /// Mode and speed of each layer
+    modes: HashMap<u8, (u8, u8)>,
+    led_saves: usize,
+}
+
+impl EcEmulatorState {
+    /// Handle a command, returning its result code
+    fn handle(&mut self, cmd: u8, data: &mut [u8]) -> u8 {
+        match (cmd, data.len()) {
+            (CMD_PROBE, len) if len >= 3 => {
+                data[..3].copy_from_slice(&[0x76, 0xEC, 1]);
+            }
+            (CMD_BOARD, len) => {
+                let board = self.board.as_bytes();
+                if len <= board.len() {
+                    return RES_ERR;
+                }
+                data[..board.len()].copy_from_slice(board);
+                data[board.len()] = 0;
+            }
+            (CMD_KEYMAP_GET, len) if len >= 5 => {
+                match self.matrix.key_index(data[0], data[1], data[2]) {
+                    Some(i) => data[3..5].copy_from_slice(&self.matrix.keymap[i].to_le_bytes()),
+                    None => return RES_ERR,
+                }
+            }
+            (CMD_KEYMAP_SET, len) if len >= 5 => {
+                match self.matrix.key_index(data[0], data[1], data[2]) {
+                    Some(i) => self.matrix.keymap[i] = u16::from_le_bytes([data[3], data[4]]),
+                    None => return RES_ERR,
+                }
+            }
+            (CMD_LED_GET_VALUE, len) if len >= 3 => {
+                let (value, _) = self.leds.get(&data[0]).copied().unwrap_or_default();
+                data[1] = value;
+                data[2] = 255;
+            }
+            (CMD_LED_SET_VALUE, len) if len >= 2 => {
+                self.leds.entry(data[0]).or_default().0 = data[1];
+            }
+            (CMD_LED_GET_COLOR, len) if len >= 4 => {
+                let (_, (r, g, b)) = self.leds.get(&data[0]).copied().unwrap_or_default();
+                data[1..4].copy_from_slice(&[r, g, b]);
+            }
+            (CMD_LED_SET_COLOR, len) if len >= 4 => {
+                self.leds.entry(data[0]).or_default().1 = (data[1], data[2], data[3]);
+            }
+            (CMD_LED_GET_MODE, len) if len >= 3 && data[0] < self.matrix.num_layers => {
+                let (mode, speed) = self.modes.get(&data[0]).copied().unwrap_or_default();
+                data[1] = mode;
+                data[2] = speed;
+            }
+            (CMD_LED_SET_MODE, len) if len >= 3 && data[0] < self.matrix.num_layers => {
+                self.modes.insert(data[0], (data[1], data[2]));
+            }
+            (CMD_MATRIX_GET, len) if len >= 2 => {
+                for byte in data.iter_mut() {
+                    *byte = 0;
+                }
+                data[0] = self.matrix.rows;
+                data[1] = self.matrix.cols;
+                for (i, pressed) in self.matrix.pressed.iter().enumerate() {
+                    let byte = 2 + i / 8;
+                    if *pressed && byte < len {
+                        data[byte] |= 1 << (i % 8);
+                    }
+                }
+            }
+            (CMD_LED_SAVE, _) => self.led_saves += 1,
+            _ => return RES_ERR,
+        }
+        RES_OK
+    }
+}
+
+/// Emulated System76 EC, for testing `DaemonServer` without hardware
+///
+/// Clones share the same EC.
+#[derive(Clone)]
+pub struct EcEmulator(Arc<Mutex<EcEmulatorState>>);
+
+impl EcEmulator {
+    /// EC of `board`, with the matrix size and number of layers of its layout,
+    /// and an empty keymap
+    pub fn new(board: &str) -> Result<Self, String> {
+        let layout = Layout::from_board(board).map_err(|err| err.to_string())?;
+        let (rows, cols) = layout
+            .layout
+            .values()
+            .fold((0, 0), |(rows, cols), (output, input)| {
+                (rows.max(output + 1), cols.max(input + 1))
+            });
+        Ok(Self(Arc::new(Mutex::new(EcEmulatorState {
+            board: board.to_string(),
+            matrix: EmulatedMatrix::new(rows, cols, layout.meta.num_layers),
+            leds: HashMap::new(),
+            modes: HashMap::new(),
+            led_saves: 0,
+        }))))
+    }
+
+    pub fn set_pressed(&self, output: u8, input: u8, pressed: bool) {
+        let mut state = self.0.lock().unwrap();
+        state.matrix.set_pressed(output, input, pressed);
+    }
+
+    /// Number of times LED settings were saved
+    pub fn led_saves(&self) -> usize {
+        self.0.lock().unwrap().led_saves
+    }
+}
+
+impl Access for EcEmulator {
+    unsafe fn command(&mut self, cmd: u8, data: &mut [u8]) -> Result<u8, Error> {
+        Ok(self.0.lock().unwrap().handle(cmd, data))
+    }
+
+    fn data_size(&self) -> usize {
+        EMULATOR_DATA_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::{emulator::test_emulated_launch, DaemonClient};
+    use proptest::prelude::*;
+    use std::{
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    /// Output of a server, readable after `run` consumes it
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run a server on an emulated launch_1 with `input`, returning its output lines
+    fn run_emulated(input: &str) -> Vec<String> {
+        let emulator = EcEmulator::new("system76/launch_1").unwrap();
+        let output = SharedOutput::default();
+        let input = io::Cursor::new(input.as_bytes().to_vec());
+        let server = DaemonServer::new_emulated(input, output.clone(), vec![emulator]).unwrap();
+        server.run().unwrap();
+        let output = output.0.lock().unwrap();
+        String::from_utf8(output.clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let output = run_emulated(
+            "not json\n{\"t\":\"model\",\"c\":{\"board\":5}}\n{\"t\":\"exit\",\"c\":{}}\n",
+        );
+        assert_eq!(output.len(), 4);
+        assert_eq!(output[0], "Daemon started");
+        assert!(output[1].starts_with("{\"Err\":\"Invalid command"));
+        assert_eq!(output[2], "{\"Err\":\"failed to find board\"}");
+        assert_eq!(output[3], "{\"Ok\":{\"t\":\"exit\",\"c\":null}}");
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn any_commands(lines in prop::collection::vec("[^\\n]*", 1..8)) {
+            let mut input = lines.join("\n");
+            input.push('\n');
+            // One reply per line, after the startup message
+            prop_assert_eq!(run_emulated(&input).len(), lines.len() + 1);
+        }
+    }
+
+    #[test]
+    fn emulated_ec() {
+        let emulator = EcEmulator::new("system76/launch_1").unwrap();
+
+        // Client and server connected like `pkexec`, but over TCP
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_emulator = emulator.clone();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let ec = unsafe { Ec::new(server_emulator) }.unwrap().into_dyn();
+            DaemonServer::new_with_ecs(stream.try_clone().unwrap(), stream, vec![ec])
+                .run()
+                .unwrap();
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let client = DaemonClient::new(stream.try_clone().unwrap(), stream).unwrap();
+
+        let board = client.boards().unwrap()[0];
+        assert_eq!(client.model(board).unwrap(), "system76/launch_1");
+
+        test_emulated_launch(
+            &client,
+            board,
+            |output, input, pressed| emulator.set_pressed(output, input, pressed),
+            0x29,
+        );
+        assert!(client.keymap_get(board, 0, 100, 3).is_err());
+
+        assert_eq!(client.max_brightness(board), Ok(255));
+        client.set_brightness(board, 0xF0, 128).unwrap();
+        assert_eq!(client.brightness(board, 0xF0), Ok(128));
+        client.set_color(board, 0xF1, (255, 0, 0)).unwrap();
+        assert_eq!(client.color(board, 0xF1), Ok((255, 0, 0)));
+        client.set_mode(board, 1, 2, 100).unwrap();
+        assert_eq!(client.mode(board, 1), Ok((2, 100)));
+        assert!(client.mode(board, 100).is_err());
+        client.led_save(board).unwrap();
+        assert_eq!(emulator.led_saves(), 1);
+
+        // Dropping the client stops the server
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_client() {
+        use std::{env, fs, os::unix::net::UnixListener, process};
+
+        let path = env::temp_dir().join(format!("keyboard-configurator-{}.sock", process::id()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let emulator = EcEmulator::new("system76/launch_1").unwrap();
+            DaemonServer::new_emulated(stream.try_clone().unwrap(), stream, vec![emulator])
+                .unwrap()
+                .run()
+                .unwrap();
+        });
+        let client = DaemonClient::new_socket(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let board = client.boards().unwrap()[0];
+        assert_eq!(client.model(board).unwrap(), "system76/launch_1");
+
+        drop(client);
+        server.join().unwrap();
+    }
+}
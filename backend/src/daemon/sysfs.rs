@@ -0,0 +1,298 @@
+// Keyboard backlights of the Linux LED class, for laptops without EC access
+// See https://www.kernel.org/doc/html/latest/leds/leds-class.html
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{err_str, whole_keyboard_led, BoardId, BoardIds, Daemon, Matrix};
+use crate::Layout;
+
+fn read_value(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|value| value.trim().to_string())
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))
+}
+
+fn write_value(path: &Path, value: &str) -> Result<(), String> {
+    fs::write(path, value).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+fn read_i32(path: &Path) -> Result<i32, String> {
+    read_value(path)?.parse().map_err(err_str)
+}
+
+struct Led {
+    name: String,
+    path: PathBuf,
+}
+
+impl Led {
+    fn max_brightness(&self) -> Result<i32, String> {
+        read_i32(&self.path.join("max_brightness"))
+    }
+
+    /// Index of red, green, and blue in `multi_intensity`, if it is a multicolor LED
+    fn color_indexes(&self) -> Result<[usize; 3], String> {
+        let multi_index = read_value(&self.path.join("multi_index"))
+            .map_err(|_| format!("'{}' has no color support", self.name))?;
+        let multi_index = multi_index.split_whitespace().collect::<Vec<_>>();
+        let mut indexes = [0; 3];
+        for (index, color) in indexes.iter_mut().zip(&["red", "green", "blue"]) {
+            *index = multi_index
+                .iter()
+                .position(|x| x == color)
+                .ok_or_else(|| format!("'{}' has no {} channel", self.name, color))?;
+        }
+        Ok(indexes)
+    }
+}
+
+/// Keyboard backlights in `/sys/class/leds`, supporting only brightness, and
+/// color for multicolor LEDs
+pub struct DaemonSysfs {
+    root: PathBuf,
+    leds: RefCell<HashMap<BoardId, Led>>,
+    board_ids: BoardIds,
+}
+
+impl DaemonSysfs {
+    pub fn new() -> Result<Self, String> {
+        Self::new_with_root("/sys")
+    }
+
+    /// Use sysfs mounted at `root` instead of `/sys`, such as a test tree
+    pub fn new_with_root<P: Into<PathBuf>>(root: P) -> Result<Self, String> {
+        let daemon = Self {
+            root: root.into(),
+            leds: RefCell::new(HashMap::new()),
+            board_ids: BoardIds::default(),
+        };
+        daemon.refresh()?;
+        Ok(daemon)
+    }
+
+    fn led<T, F: FnOnce(&Led) -> Result<T, String>>(
+        &self,
+        board: BoardId,
+        f: F,
+    ) -> Result<T, String> {
+        f(self.leds.borrow().get(&board).ok_or("No board")?)
+    }
+}
+
+impl Daemon for DaemonSysfs {
+    fn boards(&self) -> Result<Vec<BoardId>, String> {
+        let mut boards = self.leds.borrow().keys().copied().collect::<Vec<_>>();
+        boards.sort();
+        Ok(boards)
+    }
+
+    fn model(&self, board: BoardId) -> Result<String, String> {
+        self.led(board, |led| Ok(format!("sysfs/{}", led.name)))
+    }
+
+    fn layout(&self, board: BoardId) -> Option<Layout> {
+        let model = self.model(board).ok()?;
+        Some(Layout::lighting_only(&model, "Keyboard Backlight"))
+    }
+
+    fn keymap_get(
+        &self,
+        _board: BoardId,
+        _layer: u8,
+        _output: u8,
+        _input: u8,
+    ) -> Result<u16, String> {
+        Err("Unimplemented".to_string())
+    }
+
+    fn keymap_set(
+        &self,
+        _board: BoardId,
+        _layer: u8,
+        _output: u8,
+        _input: u8,
+        _value: u16,
+    ) -> Result<(), String> {
+        Err("Unimplemented".to_string())
+    }
+
+    fn matrix_get(&self, _board: BoardId) -> Result<Matrix, String> {
+        Err("Unimplemented".to_string())
+    }
+
+    fn color(&self, board: BoardId, index: u8) -> Result<(u8, u8, u8), String> {
+        whole_keyboard_led(index, "get color")?;
+        self.led(board, |led| {
+            let indexes = led.color_indexes()?;
+            let max = led.max_brightness()?.max(1);
+            let intensity = read_value(&led.path.join("multi_intensity"))?
+                .split_whitespace()
+                .map(|x| x.parse::<i32>().map_err(err_str))
+                .collect::<Result<Vec<_>, _>>()?;
+            let channel = |i: usize| -> Result<u8, String> {
+                let value = *intensity.get(indexes[i]).ok_or("Missing color channel")?;
+                Ok((value.max(0).min(max) * 255 / max) as u8)
+            };
+            Ok((channel(0)?, channel(1)?, channel(2)?))
+        })
+    }
+
+    fn set_color(&self, board: BoardId, index: u8, color: (u8, u8, u8)) -> Result<(), String> {
+        whole_keyboard_led(index, "set color")?;
+        self.led(board, |led| {
+            let indexes = led.color_indexes()?;
+            let max = led.max_brightness()?;
+            let mut intensity = vec![0; indexes.iter().max().unwrap() + 1];
+            for (i, value) in indexes.iter().zip(&[color.0, color.1, color.2]) {
+                intensity[*i] = i32::from(*value) * max / 255;
+            }
+            let intensity = intensity
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            write_value(&led.path.join("multi_intensity"), &intensity)
+        })
+    }
+
+    fn max_brightness(&self, board: BoardId) -> Result<i32, String> {
+        self.led(board, Led::max_brightness)
+    }
+
+    fn brightness(&self, board: BoardId, index: u8) -> Result<i32, String> {
+        whole_keyboard_led(index, "get brightness")?;
+        self.led(board, |led| read_i32(&led.path.join("brightness")))
+    }
+
+    fn set_brightness(&self, board: BoardId, index: u8, brightness: i32) -> Result<(), String> {
+        whole_keyboard_led(index, "set brightness")?;
+        self.led(board, |led| {
+            write_value(&led.path.join("brightness"), &brightness.to_string())
+        })
+    }
+
+    fn mode(&self, _board: BoardId, _layer: u8) -> Result<(u8, u8), String> {
+        Err("Unimplemented".to_string())
+    }
+
+    fn set_mode(&self, _board: BoardId, _layer: u8, _mode: u8, _speed: u8) -> Result<(), String> {
+        Err("Unimplemented".to_string())
+    }
+
+    fn led_save(&self, _board: BoardId) -> Result<(), String> {
+        Err("Unimplemented".to_string())
+    }
+
+    fn refresh(&self) -> Result<(), String> {
+        let dir = self.root.join("class/leds");
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(format!("Failed to read {}: {}", dir.display(), err)),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let name = entry.map_err(err_str)?.file_name();
+            if let Some(name) = name.to_str() {
+                // System76 laptops are found through their EC or system76-power
+                if name.ends_with("kbd_backlight") && !name.starts_with("system76") {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        let mut leds = self.leds.borrow_mut();
+        leds.retain(|_, led| names.contains(&led.name));
+        names.sort();
+        for name in names {
+            if leds.values().any(|led| led.name == name) {
+                continue;
+            }
+            info!("Adding sysfs keyboard backlight '{}'", name);
+            let id = self.board_ids.next();
+            let path = dir.join(&name);
+            leds.insert(id, Led { name, path });
+        }
+
+        Ok(())
+    }
+
+    fn exit(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn led(root: &Path, name: &str, files: &[(&str, &str)]) {
+        let dir = root.join("class/leds").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, value) in files {
+            fs::write(dir.join(file), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    #[test]
+    fn sysfs_tree() {
+        let root = env::temp_dir().join(format!("keyboard-configurator-sysfs-{}", process::id()));
+        led(
+            &root,
+            "dell::kbd_backlight",
+            &[("brightness", "1"), ("max_brightness", "2")],
+        );
+        led(
+            &root,
+            "rgb:kbd_backlight",
+            &[
+                ("brightness", "100"),
+                ("max_brightness", "100"),
+                ("multi_index", "blue green red"),
+                ("multi_intensity", "0 50 100"),
+            ],
+        );
+        led(&root, "input3::capslock", &[("brightness", "0")]);
+        led(
+            &root,
+            "system76_acpi::kbd_backlight",
+            &[("brightness", "0"), ("max_brightness", "255")],
+        );
+
+        let daemon = DaemonSysfs::new_with_root(&root).unwrap();
+        let boards = daemon.boards().unwrap();
+        assert_eq!(boards.len(), 2);
+        let (dell, rgb) = (boards[0], boards[1]);
+        assert_eq!(daemon.model(dell).unwrap(), "sysfs/dell::kbd_backlight");
+        let layout = daemon.layout(dell).unwrap();
+        assert!(!layout.is_generic() && layout.physical_keys().is_empty());
+        assert!(daemon.matrix_get(dell).is_err());
+
+        assert_eq!(daemon.max_brightness(dell), Ok(2));
+        assert_eq!(daemon.brightness(dell, 0xFF), Ok(1));
+        daemon.set_brightness(dell, 0xFF, 2).unwrap();
+        assert_eq!(daemon.brightness(dell, 0xFF), Ok(2));
+        assert!(daemon.color(dell, 0xFF).is_err());
+
+        assert_eq!(daemon.color(rgb, 0xFF), Ok((255, 127, 0)));
+        daemon.set_color(rgb, 0xFF, (0, 255, 51)).unwrap();
+        assert_eq!(
+            read_value(&root.join("class/leds/rgb:kbd_backlight/multi_intensity")),
+            Ok("20 100 0".to_string())
+        );
+
+        // Removed LEDs are dropped, and others keep their ids
+        fs::remove_dir_all(root.join("class/leds/dell::kbd_backlight")).unwrap();
+        daemon.refresh().unwrap();
+        assert_eq!(daemon.boards().unwrap(), vec![rgb]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
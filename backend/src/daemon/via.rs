@@ -0,0 +1,612 @@
+// Keyboards running QMK with VIA enabled, using the VIA raw HID protocol
+// See quantum/via.h in QMK
+
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+use std::{cell::RefCell, collections::HashMap};
+
+#[cfg(test)]
+use super::emulator::EmulatedMatrix;
+use super::{err_str, whole_keyboard_led, BoardId, BoardIds, Daemon, Matrix};
+use crate::{Hs, Layout, Rgb, ViaDefinition};
+
+/// Size of VIA raw HID reports
+pub const VIA_REPORT_SIZE: usize = 32;
+
+const VIA_USAGE_PAGE: u16 = 0xFF60;
+const VIA_USAGE: u16 = 0x61;
+
+const ID_GET_PROTOCOL_VERSION: u8 = 0x01;
+const ID_GET_KEYBOARD_VALUE: u8 = 0x02;
+const ID_DYNAMIC_KEYMAP_GET_KEYCODE: u8 = 0x04;
+const ID_DYNAMIC_KEYMAP_SET_KEYCODE: u8 = 0x05;
+const ID_CUSTOM_SET_VALUE: u8 = 0x07;
+const ID_CUSTOM_GET_VALUE: u8 = 0x08;
+const ID_CUSTOM_SAVE: u8 = 0x09;
+const ID_DYNAMIC_KEYMAP_GET_LAYER_COUNT: u8 = 0x11;
+const ID_UNHANDLED: u8 = 0xFF;
+
+// Values of `ID_GET_KEYBOARD_VALUE`
+const ID_SWITCH_MATRIX_STATE: u8 = 0x03;
+
+/// Bytes of switch matrix state in a reply, after the command and value IDs
+/// (and the row offset, for newer protocol versions)
+const SWITCH_MATRIX_STATE_SIZE: usize = 28;
+
+// Values of the RGB matrix channel of `ID_CUSTOM_GET_VALUE`
+const CHANNEL_RGB_MATRIX: u8 = 3;
+const ID_RGB_MATRIX_BRIGHTNESS: u8 = 1;
+const ID_RGB_MATRIX_COLOR: u8 = 4;
+
+/// First protocol version with the `ID_CUSTOM_*` lighting commands
+const PROTOCOL_VERSION_CUSTOM_VALUES: u16 = 12;
+/// First protocol version where switch matrix state starts at a row offset,
+/// so large matrices can be read in several parts
+const PROTOCOL_VERSION_MATRIX_OFFSET: u16 = 12;
+
+/// Connection to a VIA keyboard
+pub trait ViaTransport: Send + 'static {
+    /// Send `report` to the keyboard, replacing it with the reply
+    fn transfer(&self, report: &mut [u8; VIA_REPORT_SIZE]) -> Result<(), String>;
+}
+
+struct ViaHid(HidDevice);
+
+impl ViaTransport for ViaHid {
+    fn transfer(&self, report: &mut [u8; VIA_REPORT_SIZE]) -> Result<(), String> {
+        // First byte is the report ID, which is always 0
+        let mut data = [0; VIA_REPORT_SIZE + 1];
+        data[1..].copy_from_slice(report);
+        self.0.write(&data).map_err(err_str)?;
+        match self.0.read_timeout(report, 1000).map_err(err_str)? {
+            0 => Err("Timed out reading reply from VIA keyboard".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct ViaBoard {
+    definition: ViaDefinition,
+    transport: Box<dyn ViaTransport>,
+    info: Option<DeviceInfo>,
+    model: String,
+    protocol_version: u16,
+    num_layers: u8,
+}
+
+impl ViaBoard {
+    fn new(
+        definition: ViaDefinition,
+        transport: Box<dyn ViaTransport>,
+        info: Option<DeviceInfo>,
+    ) -> Result<Self, String> {
+        let (vendor_id, product_id) = definition.usb_id()?;
+        let mut board = Self {
+            definition,
+            transport,
+            info,
+            model: format!("via/{:04x}_{:04x}", vendor_id, product_id),
+            protocol_version: 0,
+            num_layers: 0,
+        };
+        let reply = board.command(&[ID_GET_PROTOCOL_VERSION])?;
+        board.protocol_version = u16::from_be_bytes([reply[1], reply[2]]);
+        let reply = board.command(&[ID_DYNAMIC_KEYMAP_GET_LAYER_COUNT])?;
+        board.num_layers = reply[1];
+        Ok(board)
+    }
+
+    /// Send a command, with `args` starting with the command ID
+    fn command(&self, args: &[u8]) -> Result<[u8; VIA_REPORT_SIZE], String> {
+        let mut report = [0; VIA_REPORT_SIZE];
+        report[..args.len()].copy_from_slice(args);
+        self.transport.transfer(&mut report)?;
+        if report[0] == ID_UNHANDLED {
+            Err(format!("Keyboard does not support command {:?}", args))
+        } else if report[0] != args[0] {
+            Err(format!("Unexpected reply to command {:?}", args))
+        } else {
+            Ok(report)
+        }
+    }
+
+    fn check_lighting(&self) -> Result<(), String> {
+        if self.protocol_version < PROTOCOL_VERSION_CUSTOM_VALUES {
+            return Err(format!(
+                "VIA protocol version {} does not support RGB matrix settings",
+                self.protocol_version
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get an RGB matrix setting, returning its data
+    fn lighting_get(&self, value_id: u8) -> Result<[u8; 2], String> {
+        self.check_lighting()?;
+        let reply = self.command(&[ID_CUSTOM_GET_VALUE, CHANNEL_RGB_MATRIX, value_id])?;
+        Ok([reply[3], reply[4]])
+    }
+
+    fn lighting_set(&self, value_id: u8, data: &[u8]) -> Result<(), String> {
+        self.check_lighting()?;
+        let mut args = vec![ID_CUSTOM_SET_VALUE, CHANNEL_RGB_MATRIX, value_id];
+        args.extend_from_slice(data);
+        self.command(&args)?;
+        Ok(())
+    }
+}
+
+/// Keyboards running QMK with VIA enabled, using the layout of their VIA
+/// definition
+///
+/// Lighting settings are brightness and color of QMK's RGB matrix, for the
+/// whole keyboard. QMK effects aren't System76 modes, so modes are not
+/// supported.
+pub struct DaemonVia {
+    definitions: Vec<ViaDefinition>,
+    hidapi: RefCell<Option<HidApi>>,
+    boards: RefCell<HashMap<BoardId, ViaBoard>>,
+    board_ids: BoardIds,
+}
+
+impl DaemonVia {
+    /// Find keyboards matching `definitions` over USB raw HID
+    pub fn new(definitions: Vec<ViaDefinition>) -> Result<Self, String> {
+        let hidapi = HidApi::new().map_err(err_str)?;
+        let daemon = Self {
+            definitions,
+            hidapi: RefCell::new(Some(hidapi)),
+            boards: RefCell::new(HashMap::new()),
+            board_ids: BoardIds::default(),
+        };
+        daemon.refresh()?;
+        Ok(daemon)
+    }
+
+    /// Use one keyboard described by `definition`, through `transport`, such
+    /// as a `ViaEmulator`
+    #[cfg(test)]
+    pub fn new_with_transport(
+        definition: ViaDefinition,
+        transport: Box<dyn ViaTransport>,
+    ) -> Result<Self, String> {
+        let daemon = Self {
+            definitions: Vec::new(),
+            hidapi: RefCell::new(None),
+            boards: RefCell::new(HashMap::new()),
+            board_ids: BoardIds::default(),
+        };
+        daemon.add_board(ViaBoard::new(definition, transport, None)?);
+        Ok(daemon)
+    }
+
+    fn add_board(&self, board: ViaBoard) {
+        info!("Adding VIA keyboard '{}'", board.definition.name);
+        let id = self.board_ids.next();
+        self.boards.borrow_mut().insert(id, board);
+    }
+
+    fn board<T, F: FnOnce(&ViaBoard) -> Result<T, String>>(
+        &self,
+        board: BoardId,
+        f: F,
+    ) -> Result<T, String> {
+        f(self.boards.borrow().get(&board).ok_or("No board")?)
+    }
+}
+
+impl Daemon for DaemonVia {
+    fn boards(&self) -> Result<Vec<BoardId>, String> {
+        let mut boards = self.boards.borrow().keys().copied().collect::<Vec<_>>();
+        boards.sort();
+        Ok(boards)
+    }
+
+    fn model(&self, board: BoardId) -> Result<String, String> {
+        self.board(board, |board| Ok(board.model.clone()))
+    }
+
+    fn layout(&self, board: BoardId) -> Option<Layout> {
+        self.board(board, |board| {
+            Layout::from_via_definition(&board.model, &board.definition, board.num_layers)
+        })
+        .map_err(|err| error!("Failed to load VIA layout: {}", err))
+        .ok()
+    }
+
+    fn keymap_get(&self, board: BoardId, layer: u8, output: u8, input: u8) -> Result<u16, String> {
+        self.board(board, |board| {
+            let reply = board.command(&[ID_DYNAMIC_KEYMAP_GET_KEYCODE, layer, output, input])?;
+            Ok(u16::from_be_bytes([reply[4], reply[5]]))
+        })
+    }
+
+    fn keymap_set(
+        &self,
+        board: BoardId,
+        layer: u8,
+        output: u8,
+        input: u8,
+        value: u16,
+    ) -> Result<(), String> {
+        self.board(board, |board| {
+            let [high, low] = value.to_be_bytes();
+            board.command(&[
+                ID_DYNAMIC_KEYMAP_SET_KEYCODE,
+                layer,
+                output,
+                input,
+                high,
+                low,
+            ])?;
+            Ok(())
+        })
+    }
+
+    fn matrix_get(&self, board: BoardId) -> Result<Matrix, String> {
+        self.board(board, |board| {
+            let rows = usize::from(board.definition.matrix.rows);
+            let cols = usize::from(board.definition.matrix.cols);
+            // Each row is big endian, with as many bytes as QMK's `matrix_row_t`
+            // needs for the columns
+            let row_size = (cols + 7) / 8;
+            if row_size > 4 {
+                return Err("Matrix too wide for VIA switch matrix state".to_string());
+            }
+
+            let mut pressed = Vec::new();
+            let mut read_rows = |data: &[u8], first_row: usize, num_rows: usize| {
+                for row in 0..num_rows {
+                    for col in 0..cols {
+                        if data[row * row_size + row_size - 1 - col / 8] & (1 << (col % 8)) != 0 {
+                            pressed.push((first_row + row, col));
+                        }
+                    }
+                }
+            };
+            if board.protocol_version >= PROTOCOL_VERSION_MATRIX_OFFSET {
+                // As many rows as fit, from the offset in the request
+                let rows_per_reply = SWITCH_MATRIX_STATE_SIZE / row_size;
+                for first_row in (0..rows).step_by(rows_per_reply) {
+                    let reply = board.command(&[
+                        ID_GET_KEYBOARD_VALUE,
+                        ID_SWITCH_MATRIX_STATE,
+                        first_row as u8,
+                    ])?;
+                    read_rows(&reply[3..], first_row, rows_per_reply.min(rows - first_row));
+                }
+            } else {
+                // All rows, only if the firmware's size check passes
+                if (cols / 8 + 1) * rows > SWITCH_MATRIX_STATE_SIZE {
+                    return Err("Matrix too large for VIA switch matrix state".to_string());
+                }
+                let reply = board.command(&[ID_GET_KEYBOARD_VALUE, ID_SWITCH_MATRIX_STATE])?;
+                read_rows(&reply[2..], 0, rows);
+            }
+            Matrix::from_pressed(rows, cols, pressed)
+        })
+    }
+
+    fn color(&self, board: BoardId, index: u8) -> Result<(u8, u8, u8), String> {
+        whole_keyboard_led(index, "get color")?;
+        self.board(board, |board| {
+            let [h, s] = board.lighting_get(ID_RGB_MATRIX_COLOR)?;
+            let rgb = Hs::from_ints(h, s).to_rgb();
+            Ok((rgb.r, rgb.g, rgb.b))
+        })
+    }
+
+    fn set_color(&self, board: BoardId, index: u8, color: (u8, u8, u8)) -> Result<(), String> {
+        whole_keyboard_led(index, "set color")?;
+        self.board(board, |board| {
+            let (h, s) = Rgb::new(color.0, color.1, color.2).to_hs_lossy().to_ints();
+            board.lighting_set(ID_RGB_MATRIX_COLOR, &[h, s])
+        })
+    }
+
+    fn max_brightness(&self, _board: BoardId) -> Result<i32, String> {
+        Ok(255)
+    }
+
+    fn brightness(&self, board: BoardId, index: u8) -> Result<i32, String> {
+        whole_keyboard_led(index, "get brightness")?;
+        self.board(board, |board| {
+            Ok(i32::from(board.lighting_get(ID_RGB_MATRIX_BRIGHTNESS)?[0]))
+        })
+    }
+
+    fn set_brightness(&self, board: BoardId, index: u8, brightness: i32) -> Result<(), String> {
+        whole_keyboard_led(index, "set brightness")?;
+        self.board(board, |board| {
+            let brightness = brightness.max(0).min(255) as u8;
+            board.lighting_set(ID_RGB_MATRIX_BRIGHTNESS, &[brightness])
+        })
+    }
+
+    fn mode(&self, _board: BoardId, _layer: u8) -> Result<(u8, u8), String> {
+        Err("VIA keyboards have no System76 LED modes".to_string())
+    }
+
+    fn set_mode(&self, _board: BoardId, _layer: u8, _mode: u8, _speed: u8) -> Result<(), String> {
+        Err("VIA keyboards have no System76 LED modes".to_string())
+    }
+
+    fn led_save(&self, board: BoardId) -> Result<(), String> {
+        self.board(board, |board| {
+            board.check_lighting()?;
+            board.command(&[ID_CUSTOM_SAVE, CHANNEL_RGB_MATRIX])?;
+            Ok(())
+        })
+    }
+
+    fn refresh(&self) -> Result<(), String> {
+        if let Some(api) = &mut *self.hidapi.borrow_mut() {
+            if let Err(err) = api.refresh_devices() {
+                error!("Failed to refresh hidapi devices: {}", err);
+            }
+
+            // Remove USB boards that are no longer attached
+            self.boards
+                .borrow_mut()
+                .retain(|_, board| match &board.info {
+                    Some(info) => api.device_list().any(|i| i.path() == info.path()),
+                    None => true,
+                });
+
+            for info in api.device_list() {
+                if (info.usage_page(), info.usage()) != (VIA_USAGE_PAGE, VIA_USAGE) {
+                    continue;
+                }
+                let usb_id = Ok((info.vendor_id(), info.product_id()));
+                let definition = match self.definitions.iter().find(|x| x.usb_id() == usb_id) {
+                    Some(definition) => definition,
+                    None => continue,
+                };
+                // Skip if device already open
+                if self
+                    .boards
+                    .borrow()
+                    .values()
+                    .any(|board| board.info.as_ref().map(DeviceInfo::path) == Some(info.path()))
+                {
+                    continue;
+                }
+
+                let board = info.open_device(&api).map_err(err_str).and_then(|device| {
+                    ViaBoard::new(
+                        definition.clone(),
+                        Box::new(ViaHid(device)),
+                        Some(info.clone()),
+                    )
+                });
+                match board {
+                    Ok(board) => self.add_board(board),
+                    Err(err) => error!("Failed to open VIA keyboard at {:?}: {}", info.path(), err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exit(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+struct ViaEmulatorState {
+    protocol_version: u16,
+    matrix: EmulatedMatrix,
+    brightness: u8,
+    color: (u8, u8),
+}
+
+#[cfg(test)]
+impl ViaEmulatorState {
+    /// Switch matrix state, like `id_switch_matrix_state` in QMK's `via.c`
+    fn switch_matrix_state(&self, report: &mut [u8; VIA_REPORT_SIZE]) {
+        let matrix = &self.matrix;
+        let (rows, cols) = (usize::from(matrix.rows), usize::from(matrix.cols));
+        let row_size = (cols + 7) / 8;
+        let (start, first_row, num_rows) =
+            if self.protocol_version >= PROTOCOL_VERSION_MATRIX_OFFSET {
+                (
+                    3,
+                    usize::from(report[2]),
+                    SWITCH_MATRIX_STATE_SIZE / row_size,
+                )
+            } else if (cols / 8 + 1) * rows <= SWITCH_MATRIX_STATE_SIZE {
+                (2, 0, rows)
+            } else {
+                return;
+            };
+        for row in first_row..rows.min(first_row + num_rows) {
+            for col in 0..cols {
+                if matrix.pressed[row * cols + col] {
+                    let byte = start + (row - first_row) * row_size + row_size - 1 - col / 8;
+                    report[byte] |= 1 << (col % 8);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, report: &mut [u8; VIA_REPORT_SIZE]) {
+        match report[0] {
+            ID_GET_PROTOCOL_VERSION => {
+                report[1..3].copy_from_slice(&self.protocol_version.to_be_bytes());
+            }
+            ID_DYNAMIC_KEYMAP_GET_LAYER_COUNT => report[1] = self.matrix.num_layers,
+            ID_DYNAMIC_KEYMAP_GET_KEYCODE => {
+                let keycode = self
+                    .matrix
+                    .key_index(report[1], report[2], report[3])
+                    .map_or(0, |i| self.matrix.keymap[i]);
+                report[4..6].copy_from_slice(&keycode.to_be_bytes());
+            }
+            ID_DYNAMIC_KEYMAP_SET_KEYCODE => {
+                if let Some(i) = self.matrix.key_index(report[1], report[2], report[3]) {
+                    self.matrix.keymap[i] = u16::from_be_bytes([report[4], report[5]]);
+                }
+            }
+            ID_GET_KEYBOARD_VALUE if report[1] == ID_SWITCH_MATRIX_STATE => {
+                self.switch_matrix_state(report);
+            }
+            ID_CUSTOM_GET_VALUE | ID_CUSTOM_SET_VALUE | ID_CUSTOM_SAVE
+                if self.protocol_version < PROTOCOL_VERSION_CUSTOM_VALUES =>
+            {
+                report[0] = ID_UNHANDLED;
+            }
+            ID_CUSTOM_GET_VALUE if report[1] == CHANNEL_RGB_MATRIX => match report[2] {
+                ID_RGB_MATRIX_BRIGHTNESS => report[3] = self.brightness,
+                ID_RGB_MATRIX_COLOR => {
+                    report[3] = self.color.0;
+                    report[4] = self.color.1;
+                }
+                _ => report[0] = ID_UNHANDLED,
+            },
+            ID_CUSTOM_SET_VALUE if report[1] == CHANNEL_RGB_MATRIX => match report[2] {
+                ID_RGB_MATRIX_BRIGHTNESS => self.brightness = report[3],
+                ID_RGB_MATRIX_COLOR => self.color = (report[3], report[4]),
+                _ => report[0] = ID_UNHANDLED,
+            },
+            ID_CUSTOM_SAVE if report[1] == CHANNEL_RGB_MATRIX => {}
+            _ => report[0] = ID_UNHANDLED,
+        }
+    }
+}
+
+/// Emulated VIA keyboard with an RGB matrix, for testing `DaemonVia` without
+/// hardware
+///
+/// Clones share the same keyboard.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct ViaEmulator(Arc<Mutex<ViaEmulatorState>>);
+
+#[cfg(test)]
+impl ViaEmulator {
+    /// Keyboard with the matrix size of `definition`, `num_layers` empty
+    /// layers, and the VIA protocol version of current QMK
+    pub fn new(definition: &ViaDefinition, num_layers: u8) -> Self {
+        let (rows, cols) = (definition.matrix.rows, definition.matrix.cols);
+        Self(Arc::new(Mutex::new(ViaEmulatorState {
+            protocol_version: PROTOCOL_VERSION_MATRIX_OFFSET,
+            matrix: EmulatedMatrix::new(rows, cols, num_layers),
+            brightness: 0,
+            color: (0, 0),
+        })))
+    }
+
+    /// Report an older protocol version, before a `DaemonVia` uses it
+    pub fn set_protocol_version(&self, protocol_version: u16) {
+        self.0.lock().unwrap().protocol_version = protocol_version;
+    }
+
+    pub fn set_pressed(&self, row: u8, col: u8, pressed: bool) {
+        let mut state = self.0.lock().unwrap();
+        state.matrix.set_pressed(row, col, pressed);
+    }
+}
+
+#[cfg(test)]
+impl ViaTransport for ViaEmulator {
+    fn transfer(&self, report: &mut [u8; VIA_REPORT_SIZE]) -> Result<(), String> {
+        self.0.lock().unwrap().handle(report);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::emulator::test_emulated_launch;
+
+    #[test]
+    fn via_emulator() {
+        let launch = Layout::from_board("system76/launch_1").unwrap();
+        let definition = launch.via_definition(0x3384, 0x0001).unwrap();
+        let emulator = ViaEmulator::new(&definition, 4);
+        let daemon = DaemonVia::new_with_transport(definition, Box::new(emulator.clone())).unwrap();
+        let board = daemon.boards().unwrap()[0];
+        assert_eq!(daemon.model(board).unwrap(), "via/3384_0001");
+
+        let layout = daemon.layout(board).unwrap();
+        assert_eq!(layout.meta.num_layers, 4);
+        assert_eq!(layout.physical_keys().len(), launch.physical_keys().len());
+
+        test_emulated_launch(
+            &daemon,
+            board,
+            |row, col, pressed| emulator.set_pressed(row, col, pressed),
+            0x5C01,
+        );
+
+        daemon.set_brightness(board, 0xFF, 300).unwrap();
+        assert_eq!(daemon.brightness(board, 0xFF), Ok(255));
+        daemon.set_color(board, 0xFF, (0, 0, 255)).unwrap();
+        assert_eq!(daemon.color(board, 0xFF), Ok((0, 0, 255)));
+        assert!(daemon.set_mode(board, 0, 3, 128).is_err());
+        assert!(daemon.mode(board, 0).is_err());
+        daemon.led_save(board).unwrap();
+        assert!(daemon.color(board, 0xF0).is_err());
+    }
+
+    fn test_definition(rows: u8, cols: u8) -> ViaDefinition {
+        ViaDefinition::from_str(&format!(
+            r#"{{
+                "name": "Test",
+                "vendorId": "0xFEED",
+                "productId": "0x0000",
+                "matrix": {{"rows": {}, "cols": {}}},
+                "layouts": {{"keymap": [["0,0"]]}}
+            }}"#,
+            rows, cols
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn via_matrix_in_parts() {
+        // 3 bytes per row, so 9 rows per reply and 3 replies
+        let definition = test_definition(20, 20);
+        let emulator = ViaEmulator::new(&definition, 1);
+        let daemon = DaemonVia::new_with_transport(definition, Box::new(emulator.clone())).unwrap();
+        let board = daemon.boards().unwrap()[0];
+
+        let keys = [(0, 0), (8, 19), (9, 0), (17, 10), (18, 8), (19, 19)];
+        for (row, col) in &keys {
+            emulator.set_pressed(*row, *col, true);
+        }
+        let matrix = daemon.matrix_get(board).unwrap();
+        assert_eq!((matrix.rows(), matrix.cols()), (20, 20));
+        let expected = keys
+            .iter()
+            .map(|(row, col)| (usize::from(*row), usize::from(*col)))
+            .collect::<Vec<_>>();
+        assert_eq!(matrix.pressed().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn via_old_protocol() {
+        let definition = test_definition(6, 14);
+        let emulator = ViaEmulator::new(&definition, 1);
+        emulator.set_protocol_version(9);
+        let daemon = DaemonVia::new_with_transport(definition, Box::new(emulator.clone())).unwrap();
+        let board = daemon.boards().unwrap()[0];
+
+        emulator.set_pressed(5, 13, true);
+        let matrix = daemon.matrix_get(board).unwrap();
+        assert_eq!(matrix.pressed().collect::<Vec<_>>(), vec![(5, 13)]);
+        assert!(daemon.brightness(board, 0xFF).is_err());
+
+        // Too large to read without a row offset
+        let definition = test_definition(20, 20);
+        let emulator = ViaEmulator::new(&definition, 1);
+        emulator.set_protocol_version(9);
+        let daemon = DaemonVia::new_with_transport(definition, Box::new(emulator)).unwrap();
+        let board = daemon.boards().unwrap()[0];
+        assert!(daemon.matrix_get(board).is_err());
+    }
+}
@@ -0,0 +1,595 @@
+//! Publishes boards of a `Backend` on D-Bus, for other applications
+//!
+//! Objects, owned by `com.system76.KeyboardConfigurator`:
+//!
+//! - `/com/system76/KeyboardConfigurator`, implementing `Manager`
+//! - `/com/system76/KeyboardConfigurator/board{n}`, implementing `Board`
+//! - `/com/system76/KeyboardConfigurator/board{n}/layer{n}`, implementing `Layer`,
+//!   for the LED settings of each layer. Boards without per-layer settings have
+//!   only `layer0`.
+
+use futures::prelude::*;
+use glib::{clone, SourceId};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    os::unix::io::AsRawFd,
+    rc::Rc,
+    time::Duration,
+};
+use zbus::{dbus_interface, fdo, Connection, ObjectServer};
+use zvariant::{ObjectPath, OwnedObjectPath};
+
+use crate::{
+    service::ServiceHandlers, Backend, Board, BoardId, Key, KeyMap, KeyMapImportOptions, Layout,
+    Mode, Rgb,
+};
+
+pub const DBUS_NAME: &str = "com.system76.KeyboardConfigurator";
+pub const DBUS_PATH: &str = "/com/system76/KeyboardConfigurator";
+
+/// Interval for reading the matrix while `MatrixChanged` is enabled
+const MATRIX_GET_RATE: Duration = Duration::from_millis(50);
+
+fn object_path(path: &str) -> ObjectPath {
+    ObjectPath::try_from(path).unwrap()
+}
+
+/// Run `future` to completion from a D-Bus method
+///
+/// Calls are handled from the main context, so this iterates it until done.
+fn block_on<F: Future<Output = Result<(), String>>>(future: F) -> fdo::Result<()> {
+    glib::MainContext::default()
+        .block_on(future)
+        .map_err(fdo::Error::Failed)
+}
+
+struct ManagerInterface {
+    backend: Backend,
+    boards: Rc<RefCell<Vec<OwnedObjectPath>>>,
+}
+
+#[dbus_interface(name = "com.system76.KeyboardConfigurator.Manager")]
+impl ManagerInterface {
+    /// Object paths of connected boards
+    fn list_boards(&self) -> Vec<OwnedObjectPath> {
+        self.boards.borrow().clone()
+    }
+
+    /// Read the key matrix of boards, emitting `MatrixChanged` when it changes
+    fn watch_matrix(&self, enable: bool) {
+        self.backend
+            .set_matrix_get_rate(if enable { Some(MATRIX_GET_RATE) } else { None });
+    }
+
+    #[dbus_interface(signal)]
+    fn board_added(&self, board: &OwnedObjectPath) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    fn board_removed(&self, board: &OwnedObjectPath) -> zbus::Result<()>;
+}
+
+struct BoardInterface {
+    board: Board,
+}
+
+impl BoardInterface {
+    fn key(&self, name: &str) -> fdo::Result<&Key> {
+        self.board
+            .keys()
+            .iter()
+            .find(|key| key.logical_name == name)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("No key '{}'", name)))
+    }
+
+    fn check_layer(&self, layer: u32) -> fdo::Result<usize> {
+        if layer < u32::from(self.board.layout().meta.num_layers) {
+            Ok(layer as usize)
+        } else {
+            Err(fdo::Error::InvalidArgs(format!("No layer {}", layer)))
+        }
+    }
+}
+
+#[dbus_interface(name = "com.system76.KeyboardConfigurator.Board")]
+impl BoardInterface {
+    #[dbus_interface(property)]
+    fn model(&self) -> String {
+        self.board.model().to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn display_name(&self) -> String {
+        self.board.layout().meta.display_name.clone()
+    }
+
+    /// Number of keymap layers
+    #[dbus_interface(property)]
+    fn layers(&self) -> u32 {
+        self.board.layout().meta.num_layers.into()
+    }
+
+    #[dbus_interface(property)]
+    fn max_brightness(&self) -> i32 {
+        self.board.max_brightness()
+    }
+
+    /// Logical names of keys, as used by other methods
+    fn keys(&self) -> Vec<String> {
+        self.board
+            .keys()
+            .iter()
+            .map(|key| key.logical_name.clone())
+            .collect()
+    }
+
+    /// Scancode name of a key on a layer
+    fn keymap_get(&self, key: &str, layer: u32) -> fdo::Result<String> {
+        let layer = self.check_layer(layer)?;
+        Ok(self.key(key)?.get_scancode(layer).unwrap().1)
+    }
+
+    fn keymap_set(&self, key: &str, layer: u32, scancode: &str) -> fdo::Result<()> {
+        let layer = self.check_layer(layer)?;
+        let key = self.key(key)?;
+        block_on(key.set_scancode(layer, scancode))
+    }
+
+    /// Keymap and LED settings, in the JSON format of exported keymaps
+    fn keymap_export(&self) -> String {
+        self.board.export_keymap().to_string_pretty()
+    }
+
+    /// Load a keymap in the JSON format of `KeymapExport`, possibly of
+    /// another model
+    fn keymap_import(&self, json: &str) -> fdo::Result<()> {
+        let mut keymap =
+            KeyMap::from_str(json).map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        if keymap.model != self.board.model() {
+            let from = Layout::from_board(&keymap.model)
+                .map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+            keymap = keymap.translate(&from, self.board.layout()).keymap;
+        }
+        let issues = keymap.validate(self.board.layout());
+        if !issues.is_empty() {
+            let issues = issues.iter().map(ToString::to_string).collect::<Vec<_>>();
+            return Err(fdo::Error::InvalidArgs(issues.join("\n")));
+        }
+
+        let board = &self.board;
+        block_on(async {
+            board
+                .import_keymap(&keymap, &KeyMapImportOptions::default())
+                .await;
+            board.led_save().await
+        })
+    }
+
+    /// Logical names of the keys now pressed, while enabled with `WatchMatrix`
+    #[dbus_interface(signal)]
+    fn matrix_changed(&self, pressed: &[String]) -> zbus::Result<()>;
+}
+
+struct LayerInterface {
+    board: Board,
+    layer: usize,
+}
+
+impl LayerInterface {
+    fn set(&self, future: impl Future<Output = Result<(), String>>) -> fdo::Result<()> {
+        let board = &self.board;
+        block_on(async {
+            future.await?;
+            board.led_save().await
+        })
+    }
+
+    fn check_has_mode(&self) -> fdo::Result<()> {
+        if self.board.layout().meta.has_mode {
+            Ok(())
+        } else {
+            Err(fdo::Error::NotSupported("Board has no modes".to_string()))
+        }
+    }
+
+    fn mode_speed(&self) -> (&'static Mode, u8) {
+        let layer = &self.board.layers()[self.layer];
+        layer.mode().unwrap_or((&Mode::all()[0], 128))
+    }
+}
+
+#[dbus_interface(name = "com.system76.KeyboardConfigurator.Layer")]
+impl LayerInterface {
+    /// Mode ID, like `SOLID_COLOR`, or empty if the board has no modes, in
+    /// which case setting it or `Speed` fails
+    #[dbus_interface(property)]
+    fn mode(&self) -> String {
+        let layer = &self.board.layers()[self.layer];
+        layer.mode().map_or("", |(mode, _)| mode.id).to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn set_mode(&mut self, id: String) -> fdo::Result<()> {
+        self.check_has_mode()?;
+        let mode = Mode::from_id(&id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown mode '{}'", id)))?;
+        let speed = self.mode_speed().1;
+        self.set(self.board.layers()[self.layer].set_mode(mode, speed))
+    }
+
+    #[dbus_interface(property)]
+    fn speed(&self) -> u8 {
+        self.mode_speed().1
+    }
+
+    #[dbus_interface(property)]
+    fn set_speed(&mut self, speed: u8) -> fdo::Result<()> {
+        self.check_has_mode()?;
+        let mode = self.mode_speed().0;
+        self.set(self.board.layers()[self.layer].set_mode(mode, speed))
+    }
+
+    #[dbus_interface(property)]
+    fn brightness(&self) -> i32 {
+        self.board.layers()[self.layer].brightness()
+    }
+
+    #[dbus_interface(property)]
+    fn set_brightness(&mut self, brightness: i32) -> fdo::Result<()> {
+        let brightness = brightness.max(0).min(self.board.max_brightness());
+        self.set(self.board.layers()[self.layer].set_brightness(brightness))
+    }
+
+    /// Color, like `#ff0000`
+    #[dbus_interface(property)]
+    fn color(&self) -> String {
+        self.board.layers()[self.layer].color().to_rgb().to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn set_color(&mut self, color: String) -> fdo::Result<()> {
+        let rgb = Rgb::parse(&color)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Invalid color '{}'", color)))?;
+        self.set(self.board.layers()[self.layer].set_color(rgb.to_hs_lossy()))
+    }
+}
+
+/// Changes to publish once the object server isn't in use
+enum Event {
+    Added(Board),
+    Removed(Board),
+    MatrixChanged(Board),
+}
+
+struct ServiceInner {
+    server: RefCell<ObjectServer>,
+    /// Object path of each published board
+    paths: RefCell<HashMap<BoardId, String>>,
+    boards: Rc<RefCell<Vec<OwnedObjectPath>>>,
+    next_index: Cell<usize>,
+    pending: RefCell<VecDeque<Event>>,
+}
+
+impl ServiceInner {
+    fn handle_message(&self) {
+        if let Ok(mut server) = self.server.try_borrow_mut() {
+            if let Err(err) = server.try_handle_next() {
+                error!("Failed to handle D-Bus message: {}", err);
+            }
+        }
+        self.flush();
+    }
+
+    fn queue(self: &Rc<Self>, event: Event) {
+        self.pending.borrow_mut().push_back(event);
+        self.flush();
+    }
+
+    /// Publish pending changes, unless called while handling a message
+    fn flush(&self) {
+        let mut server = match self.server.try_borrow_mut() {
+            Ok(server) => server,
+            Err(_) => return,
+        };
+        loop {
+            let event = self.pending.borrow_mut().pop_front();
+            let res = match event {
+                Some(Event::Added(board)) => self.add_board(&mut server, &board),
+                Some(Event::Removed(board)) => self.remove_board(&mut server, &board),
+                Some(Event::MatrixChanged(board)) => self.matrix_changed(&server, &board),
+                None => break,
+            };
+            if let Err(err) = res {
+                error!("Failed to update D-Bus objects: {}", err);
+            }
+        }
+    }
+
+    fn add_board(&self, server: &mut ObjectServer, board: &Board) -> zbus::Result<()> {
+        if self.paths.borrow().contains_key(&board.board()) {
+            return Ok(());
+        }
+        let index = self.next_index.get();
+        self.next_index.set(index + 1);
+        let path = format!("{}/board{}", DBUS_PATH, index);
+
+        server.at(
+            &object_path(&path),
+            BoardInterface {
+                board: board.clone(),
+            },
+        )?;
+        for layer in 0..board.layers().len() {
+            server.at(
+                &object_path(&format!("{}/layer{}", path, layer)),
+                LayerInterface {
+                    board: board.clone(),
+                    layer,
+                },
+            )?;
+        }
+
+        let owned_path = OwnedObjectPath::from(object_path(&path).into_owned());
+        self.paths.borrow_mut().insert(board.board(), path);
+        self.boards.borrow_mut().push(owned_path.clone());
+        server.with(&object_path(DBUS_PATH), |manager: &ManagerInterface| {
+            manager.board_added(&owned_path)
+        })
+    }
+
+    fn remove_board(&self, server: &mut ObjectServer, board: &Board) -> zbus::Result<()> {
+        let path = match self.paths.borrow_mut().remove(&board.board()) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        for layer in 0..board.layers().len() {
+            server.remove::<LayerInterface>(&object_path(&format!("{}/layer{}", path, layer)))?;
+        }
+        server.remove::<BoardInterface>(&object_path(&path))?;
+
+        let owned_path = OwnedObjectPath::from(object_path(&path).into_owned());
+        self.boards.borrow_mut().retain(|i| i != &owned_path);
+        server.with(&object_path(DBUS_PATH), |manager: &ManagerInterface| {
+            manager.board_removed(&owned_path)
+        })
+    }
+
+    fn matrix_changed(&self, server: &ObjectServer, board: &Board) -> zbus::Result<()> {
+        let paths = self.paths.borrow();
+        let path = match paths.get(&board.board()) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let pressed = board
+            .keys()
+            .iter()
+            .filter(|key| key.pressed())
+            .map(|key| key.logical_name.clone())
+            .collect::<Vec<_>>();
+        server.with(&object_path(path), |iface: &BoardInterface| {
+            iface.matrix_changed(&pressed)
+        })
+    }
+}
+
+/// Service publishing the boards of a `Backend` on D-Bus
+///
+/// Messages are handled on the default main context, which must be running.
+/// The service stops when dropped.
+pub struct DbusService {
+    _inner: Rc<ServiceInner>,
+    source_id: RefCell<Option<SourceId>>,
+    _handlers: ServiceHandlers,
+}
+
+impl DbusService {
+    /// Publish boards on the session bus
+    pub fn new_session(backend: &Backend) -> Result<Self, String> {
+        let connection = Connection::new_session().map_err(|err| err.to_string())?;
+        Self::new(backend, connection)
+    }
+
+    /// Publish boards on `connection`, which may be to a private bus
+    pub fn new(backend: &Backend, connection: Connection) -> Result<Self, String> {
+        fdo::DBusProxy::new(&connection)
+            .and_then(|proxy| {
+                proxy.request_name(DBUS_NAME, fdo::RequestNameFlags::DoNotQueue.into())
+            })
+            .map_err(|err| format!("Failed to request name '{}': {}", DBUS_NAME, err))
+            .and_then(|reply| match reply {
+                fdo::RequestNameReply::PrimaryOwner | fdo::RequestNameReply::AlreadyOwner => Ok(()),
+                _ => Err(format!("Name '{}' is already owned", DBUS_NAME)),
+            })?;
+
+        let boards = Rc::new(RefCell::new(Vec::new()));
+        let mut server = ObjectServer::new(&connection);
+        server
+            .at(
+                &object_path(DBUS_PATH),
+                ManagerInterface {
+                    backend: backend.clone(),
+                    boards: boards.clone(),
+                },
+            )
+            .map_err(|err| err.to_string())?;
+
+        let inner = Rc::new(ServiceInner {
+            server: RefCell::new(server),
+            paths: RefCell::new(HashMap::new()),
+            boards,
+            next_index: Cell::new(0),
+            pending: RefCell::new(VecDeque::new()),
+        });
+
+        let weak = Rc::downgrade(&inner);
+        let handlers = ServiceHandlers::new(
+            backend,
+            clone!(@strong weak => move |board| {
+                let handler_id = board.connect_matrix_changed(
+                    clone!(@strong weak, @weak board => move || {
+                        if let Some(inner) = weak.upgrade() {
+                            inner.queue(Event::MatrixChanged(board));
+                        }
+                    }),
+                );
+                if let Some(inner) = weak.upgrade() {
+                    inner.queue(Event::Added(board.clone()));
+                }
+                vec![handler_id]
+            }),
+            clone!(@strong weak => move |board| {
+                if let Some(inner) = weak.upgrade() {
+                    inner.queue(Event::Removed(board.clone()));
+                }
+            }),
+        );
+
+        let source_id = glib::unix_fd_add_local(
+            connection.as_raw_fd(),
+            glib::IOCondition::IN,
+            clone!(@strong weak => move |_, _| {
+                match weak.upgrade() {
+                    Some(inner) => {
+                        inner.handle_message();
+                        glib::Continue(true)
+                    }
+                    None => glib::Continue(false),
+                }
+            }),
+        );
+
+        Ok(Self {
+            _inner: inner,
+            source_id: RefCell::new(Some(source_id)),
+            _handlers: handlers,
+        })
+    }
+}
+
+impl Drop for DbusService {
+    fn drop(&mut self) {
+        if let Some(source_id) = self.source_id.borrow_mut().take() {
+            glib::source_remove(source_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+        sync::mpsc,
+        thread,
+        time::Instant,
+    };
+
+    // Needs `dbus-daemon` to run a private bus, so run explicitly with
+    // `cargo test --features dbus-service -- --ignored private_bus`
+    #[test]
+    #[ignore]
+    fn private_bus() {
+        let mut dbus_daemon = Command::new("dbus-daemon")
+            .args(&["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to run dbus-daemon, which this test needs");
+        let mut address = String::new();
+        BufReader::new(dbus_daemon.stdout.as_mut().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+
+        let context = glib::MainContext::default();
+        let backend = Backend::new_dummy(vec![
+            "system76/launch_1".to_string(),
+            "system76/darp6".to_string(),
+        ])
+        .unwrap();
+        context.block_on(backend.refresh_async()).unwrap();
+        while context.pending() {
+            context.iteration(false);
+        }
+        let connection = Connection::new_for_address(&address, true).unwrap();
+        let _service = DbusService::new(&backend, connection).unwrap();
+
+        // Call from another thread, while this one handles the calls
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let res = (|| -> Result<_, String> {
+                let connection =
+                    Connection::new_for_address(&address, true).map_err(|e| e.to_string())?;
+                let proxy = |path: &str, interface: &str| {
+                    zbus::Proxy::new(&connection, DBUS_NAME, path, interface)
+                        .map_err(|e| e.to_string())
+                };
+                let manager = proxy(DBUS_PATH, "com.system76.KeyboardConfigurator.Manager")?;
+                let boards: Vec<OwnedObjectPath> =
+                    manager.call("ListBoards", &()).map_err(|e| e.to_string())?;
+                let mut paths = HashMap::new();
+                for path in &boards {
+                    let board = proxy(path.as_str(), "com.system76.KeyboardConfigurator.Board")?;
+                    let model: String = board.get_property("Model").map_err(|e| e.to_string())?;
+                    paths.insert(model, path.as_str().to_string());
+                }
+                let path = &paths["system76/launch_1"];
+                let board = proxy(path, "com.system76.KeyboardConfigurator.Board")?;
+                board
+                    .call::<_, ()>("KeymapSet", &("K00", 1u32, "ESC"))
+                    .map_err(|e| e.to_string())?;
+                let scancode: String = board
+                    .call("KeymapGet", &("K00", 1u32))
+                    .map_err(|e| e.to_string())?;
+                let layer = |path: &str| {
+                    proxy(
+                        &format!("{}/layer0", path),
+                        "com.system76.KeyboardConfigurator.Layer",
+                    )
+                };
+                let launch_layer = layer(path)?;
+                launch_layer
+                    .set_property("Color", "#ff0000")
+                    .map_err(|e| e.to_string())?;
+                let color: String = launch_layer
+                    .get_property("Color")
+                    .map_err(|e| e.to_string())?;
+                // Invalid values, and modes on a board without them, are errors
+                let darp_layer = layer(&paths["system76/darp6"])?;
+                let errors = (
+                    launch_layer.set_property("Color", "red").is_err(),
+                    launch_layer.set_property("Mode", "NOT_A_MODE").is_err(),
+                    darp_layer.set_property("Mode", "SOLID_COLOR").is_err(),
+                );
+                Ok((boards.len(), scancode, color, errors))
+            })();
+            sender.send(res).unwrap();
+        });
+
+        let start = Instant::now();
+        let res = loop {
+            if let Ok(res) = receiver.try_recv() {
+                break res;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "D-Bus calls timed out"
+            );
+            context.iteration(false);
+            thread::sleep(Duration::from_millis(1));
+        };
+        dbus_daemon.kill().unwrap();
+
+        assert_eq!(
+            res.unwrap(),
+            (
+                2,
+                "ESC".to_string(),
+                "#ff0000".to_string(),
+                (true, true, true)
+            )
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use glib::clone::Downgrade;
-use std::{cell::Cell, char};
+use std::cell::Cell;
 
-use crate::{Board, Daemon, Hs, PhysicalLayoutKey, Rect, Rgb};
+use crate::{Board, Hs, KeyShape, KeyState, PhysicalLayoutKey, Rgb};
 
 #[derive(Debug)]
 pub struct Key {
@@ -10,8 +10,8 @@ pub struct Key {
     pub logical: (u8, u8),
     /// Logical name (something like K01, where 0 is the row and 1 is the column)
     pub logical_name: String,
-    /// Physical position and size
-    pub physical: Rect,
+    /// Physical position and shape
+    pub physical: KeyShape,
     /// Physical key name (what is printed on the keycap)
     pub physical_name: String,
     /// Electrical mapping (output, input)
@@ -29,36 +29,36 @@ pub struct Key {
     scancodes: Vec<Cell<u16>>,
     /// Background color
     pub background_color: Rgb,
+    /// Legend color, if set in `physical.json`
+    pub foreground_color: Option<Rgb>,
 }
 
 impl Key {
-    pub(crate) fn new(
-        daemon: &dyn Daemon,
-        board: &Board,
-        physical_key: &PhysicalLayoutKey,
-    ) -> Self {
+    pub(crate) fn new(board: &Board, physical_key: &PhysicalLayoutKey, state: &KeyState) -> Self {
         let logical = physical_key.logical;
         let physical = physical_key.physical;
         let physical_name = physical_key.physical_name.clone();
         let background_color = physical_key.background_color;
+        let foreground_color = physical_key.foreground_color;
 
-        debug!("Key {}, {} = {:?}", physical.x, physical.y, physical_name);
+        debug!(
+            "Key {}, {} = {:?}",
+            physical.rect.x, physical.rect.y, physical_name
+        );
 
         debug!("  Logical: {:?}", logical);
 
-        let row_char =
-            char::from_digit(logical.0 as u32, 36).expect("Failed to convert row to char");
-        let col_char =
-            char::from_digit(logical.1 as u32, 36).expect("Failed to convert col to char");
-        let logical_name = format!("K{}{}", row_char, col_char).to_uppercase();
+        let logical_name = physical_key.logical_name();
         debug!("  Logical Name: {}", logical_name);
 
         let electrical = *board
             .layout()
             .layout
             .get(logical_name.as_str())
-            //.expect("Failed to find electrical mapping");
-            .unwrap_or(&(0, 0));
+            .unwrap_or_else(|| {
+                error!("Key {} missing from layout.json", logical_name);
+                &(0, 0)
+            });
         debug!("  Electrical: {:?}", electrical);
 
         let leds = board
@@ -77,33 +77,18 @@ impl Key {
         }
 
         let mut scancodes = Vec::new();
-        for layer in 0..board.layout().meta.num_layers {
+        for (layer, scancode) in state.scancodes.iter().enumerate() {
             debug!("  Layer {}", layer);
-            let scancode = match daemon.keymap_get(board.board(), layer, electrical.0, electrical.1)
-            {
-                Ok(value) => value,
-                Err(err) => {
-                    error!("Failed to read scancode: {:?}", err);
-                    0
-                }
-            };
             debug!("    Scancode: {:04X}", scancode);
             debug!(
                 "    Scancode Name: {:?}",
-                board.layout().scancode_to_name(scancode)
+                board.layout().scancode_to_name(*scancode)
             );
 
-            scancodes.push(Cell::new(scancode));
+            scancodes.push(Cell::new(*scancode));
         }
 
-        let mut led_color = None;
-        if board.layout().meta.has_mode && leds.len() > 0 {
-            match daemon.color(board.board(), leds[0]) {
-                Ok((0, 0, 0)) => {}
-                Ok((r, g, b)) => led_color = Some(Rgb::new(r, g, b).to_hs_lossy()),
-                Err(err) => error!("error getting key color: {}", err),
-            }
-        }
+        let led_color = state.led_color.map(Rgb::to_hs_lossy);
 
         Self {
             board: board.downgrade(),
@@ -119,6 +104,7 @@ impl Key {
             pressed: Cell::new(false),
             scancodes,
             background_color,
+            foreground_color,
         }
     }
 
@@ -138,10 +124,7 @@ impl Key {
         let board = self.board();
         let Rgb { r, g, b } = color.map_or(Rgb::new(0, 0, 0), Hs::to_rgb);
         for index in &self.leds {
-            board
-                .thread_client()
-                .set_color(board.board(), *index, (r, g, b))
-                .await?;
+            board.core().set_color(*index, (r, g, b)).await?;
         }
         self.led_color.set(color);
         board.set_leds_changed();
@@ -151,10 +134,7 @@ impl Key {
     pub fn get_scancode(&self, layer: usize) -> Option<(u16, String)> {
         let board = self.board();
         let scancode = self.scancodes.get(layer)?.get();
-        let scancode_name = match board.layout().scancode_to_name(scancode) {
-            Some(some) => some.to_string(),
-            None => String::new(),
-        };
+        let scancode_name = board.layout().scancode_name(scancode);
         Some((scancode, scancode_name))
     }
 
@@ -165,14 +145,8 @@ impl Key {
             .scancode_from_name(scancode_name)
             .ok_or_else(|| format!("Unable to find scancode '{}'", scancode_name))?;
         board
-            .thread_client()
-            .keymap_set(
-                board.board(),
-                layer as u8,
-                self.electrical.0,
-                self.electrical.1,
-                scancode,
-            )
+            .core()
+            .keymap_set(layer as u8, self.electrical.0, self.electrical.1, scancode)
             .await?;
         self.scancodes[layer].set(scancode);
         Ok(())
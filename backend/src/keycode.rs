@@ -0,0 +1,414 @@
+use std::{fmt, ops};
+
+/// Modifier keys of a composite QMK keycode
+///
+/// QMK can't mix left and right modifiers in one keycode, so `RIGHT` makes
+/// all of them right hand modifiers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Mods(u8);
+
+/// Names of modifiers, without `L` or `R` prefix
+static MOD_NAMES: &[(Mods, &str)] = &[
+    (Mods::CTRL, "CTL"),
+    (Mods::SHIFT, "SFT"),
+    (Mods::ALT, "ALT"),
+    (Mods::GUI, "GUI"),
+];
+
+impl Mods {
+    pub const CTRL: Self = Self(0x01);
+    pub const SHIFT: Self = Self(0x02);
+    pub const ALT: Self = Self(0x04);
+    pub const GUI: Self = Self(0x08);
+    pub const RIGHT: Self = Self(0x10);
+
+    /// Modifiers from the 5 bit QMK representation
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits & !0x1F == 0 {
+            Some(Self(bits))
+        } else {
+            None
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// `true` if no modifier keys are set; `RIGHT` alone is empty
+    pub fn is_empty(self) -> bool {
+        self.0 & !Self::RIGHT.0 == 0
+    }
+
+    /// QMK names of each modifier, like `["LCTL", "LSFT"]`
+    fn names(self) -> Vec<String> {
+        let side = if self.contains(Self::RIGHT) { 'R' } else { 'L' };
+        MOD_NAMES
+            .iter()
+            .filter(|(mods, _)| self.contains(*mods))
+            .map(|(_, name)| format!("{}{}", side, name))
+            .collect()
+    }
+
+    /// Parse a single modifier name, like `LCTL`
+    fn from_name(name: &str) -> Option<Self> {
+        let side = match name.get(..1)? {
+            "L" => Self::default(),
+            "R" => Self::RIGHT,
+            _ => return None,
+        };
+        MOD_NAMES
+            .iter()
+            .find(|(_, mod_name)| *mod_name == &name[1..])
+            .map(|(mods, _)| *mods | side)
+    }
+
+    /// Parse a mask of modifiers, like `MOD_LCTL | MOD_LSFT`
+    fn from_mask(mask: &str) -> Option<Self> {
+        let mut mods = None;
+        for name in mask.split('|') {
+            let name = name.trim();
+            if !name.starts_with("MOD_") {
+                return None;
+            }
+            mods = Some(combine(mods, Self::from_name(&name[4..])?)?);
+        }
+        mods
+    }
+
+    /// Format as a mask of modifiers, like `MOD_LCTL | MOD_LSFT`
+    fn mask(self) -> String {
+        self.names()
+            .iter()
+            .map(|name| format!("MOD_{}", name))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+impl ops::BitOr for Mods {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl ops::BitOrAssign for Mods {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl fmt::Display for Mods {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mask())
+    }
+}
+
+/// Add `new` to `mods`, if they are on the same side
+fn combine(mods: Option<Mods>, new: Mods) -> Option<Mods> {
+    match mods {
+        None => Some(new),
+        Some(mods) if mods.contains(Mods::RIGHT) == new.contains(Mods::RIGHT) => Some(mods | new),
+        Some(_) => None,
+    }
+}
+
+/// QMK keycode, decoded from its 16 bit representation
+///
+/// Layers are numbered from 0, as in QMK. Basic keycodes are in the range
+/// `0x00` to `0xFF`, and are named by `keymap.json`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Keycode {
+    /// Basic keycode, like `A`
+    Basic(u8),
+    /// Basic keycode with modifiers held, like `LCTL(A)`
+    Mods(Mods, u8),
+    /// Modifiers when held, basic keycode when tapped: `MT(MOD_LCTL, A)`
+    ModTap(Mods, u8),
+    /// Layer when held, basic keycode when tapped: `LT(1, A)`
+    LayerTap(u8, u8),
+    /// Turn on only this layer: `TO(1)`
+    To(u8),
+    /// Layer while held: `MO(1)`
+    Momentary(u8),
+    /// Set default layer: `DF(1)`
+    DefaultLayer(u8),
+    /// Toggle layer: `TG(1)`
+    Toggle(u8),
+    /// Layer for the next key press: `OSL(1)`
+    OneShotLayer(u8),
+    /// Modifiers for the next key press: `OSM(MOD_LSFT)`
+    OneShotMods(Mods),
+    /// Layer while held, toggle when tapped: `TT(1)`
+    TapToggle(u8),
+    /// Any other keycode, named only by `keymap.json` if at all
+    Other(u16),
+}
+
+/// Split `F(args)` into `F` and `args`
+fn split_call(name: &str) -> Option<(&str, &str)> {
+    let name = name.trim();
+    if !name.ends_with(')') {
+        return None;
+    }
+    let open = name.find('(')?;
+    Some((name[..open].trim(), &name[open + 1..name.len() - 1]))
+}
+
+/// Split `a, b` into `a` and `b`
+fn split_args(args: &str) -> Option<(&str, &str)> {
+    let mut args = args.splitn(2, ',');
+    Some((args.next()?.trim(), args.next()?.trim()))
+}
+
+fn parse_layer(layer: &str) -> Option<u8> {
+    layer.trim().parse().ok()
+}
+
+impl Keycode {
+    /// Decode a QMK keycode
+    pub fn from_u16(code: u16) -> Self {
+        let low = (code & 0xFF) as u8;
+        let mods = Mods(((code >> 8) & 0x1F) as u8);
+        match code {
+            0x0000..=0x00FF => Self::Basic(low),
+            0x0100..=0x1FFF if !mods.is_empty() => Self::Mods(mods, low),
+            0x4000..=0x4FFF => Self::LayerTap(((code >> 8) & 0xF) as u8, low),
+            // `TO` is always activated on press
+            0x5000..=0x50FF if low & 0xF0 == 0x10 => Self::To(low & 0xF),
+            0x5100..=0x51FF => Self::Momentary(low),
+            0x5200..=0x52FF => Self::DefaultLayer(low),
+            0x5300..=0x53FF => Self::Toggle(low),
+            0x5400..=0x54FF => Self::OneShotLayer(low),
+            0x5500..=0x551F if !Mods(low).is_empty() => Self::OneShotMods(Mods(low)),
+            0x5800..=0x58FF => Self::TapToggle(low),
+            0x6000..=0x7FFF if !mods.is_empty() => Self::ModTap(mods, low),
+            _ => Self::Other(code),
+        }
+    }
+
+    /// Encode as a QMK keycode, or `None` if a layer or modifier is out of range
+    pub fn to_u16(self) -> Option<u16> {
+        let layer = |base: u16, layer: u8, max: u8| {
+            if layer <= max {
+                Some(base | u16::from(layer))
+            } else {
+                None
+            }
+        };
+        let mods = |base: u16, mods: Mods, code: u8| {
+            if mods.is_empty() {
+                None
+            } else {
+                Some(base | u16::from(mods.bits()) << 8 | u16::from(code))
+            }
+        };
+        match self {
+            Self::Basic(code) => Some(code.into()),
+            Self::Mods(m, code) => mods(0x0000, m, code),
+            Self::ModTap(m, code) => mods(0x6000, m, code),
+            Self::LayerTap(l, code) if l <= 0xF => {
+                Some(0x4000 | u16::from(l) << 8 | u16::from(code))
+            }
+            Self::LayerTap(..) => None,
+            Self::To(l) => layer(0x5010, l, 0xF),
+            Self::Momentary(l) => layer(0x5100, l, 0xFF),
+            Self::DefaultLayer(l) => layer(0x5200, l, 0xFF),
+            Self::Toggle(l) => layer(0x5300, l, 0xFF),
+            Self::OneShotLayer(l) => layer(0x5400, l, 0xFF),
+            Self::OneShotMods(m) if !m.is_empty() => Some(0x5500 | u16::from(m.bits())),
+            Self::OneShotMods(_) => None,
+            Self::TapToggle(l) => layer(0x5800, l, 0xFF),
+            Self::Other(code) => Some(code),
+        }
+    }
+
+    /// Name of keycode, like `LT(1, SPACE)`
+    ///
+    /// `base_name` gives the name of basic keycodes. Returns `None` for
+    /// `Other`, or if a basic keycode has no name.
+    pub fn name<F: Fn(u8) -> Option<String>>(self, base_name: F) -> Option<String> {
+        Some(match self {
+            Self::Basic(code) => base_name(code)?,
+            Self::Mods(mods, code) => {
+                let names = mods.names();
+                let mut name = base_name(code)?;
+                for mod_name in names.iter().rev() {
+                    name = format!("{}({})", mod_name, name);
+                }
+                name
+            }
+            Self::ModTap(mods, code) => format!("MT({}, {})", mods, base_name(code)?),
+            Self::LayerTap(layer, code) => format!("LT({}, {})", layer, base_name(code)?),
+            Self::To(layer) => format!("TO({})", layer),
+            Self::Momentary(layer) => format!("MO({})", layer),
+            Self::DefaultLayer(layer) => format!("DF({})", layer),
+            Self::Toggle(layer) => format!("TG({})", layer),
+            Self::OneShotLayer(layer) => format!("OSL({})", layer),
+            Self::OneShotMods(mods) => format!("OSM({})", mods),
+            Self::TapToggle(layer) => format!("TT({})", layer),
+            Self::Other(_) => return None,
+        })
+    }
+
+    /// Parse a name in the format of `Keycode::name`
+    ///
+    /// `base_code` looks up basic keycodes by name.
+    pub fn from_name<F: Fn(&str) -> Option<u8>>(name: &str, base_code: F) -> Option<Self> {
+        let (function, args) = match split_call(name) {
+            Some(call) => call,
+            None => return base_code(name.trim()).map(Self::Basic),
+        };
+
+        Some(match function {
+            "MT" => {
+                let (mods, base) = split_args(args)?;
+                Self::ModTap(Mods::from_mask(mods)?, base_code(base)?)
+            }
+            "LT" => {
+                let (layer, base) = split_args(args)?;
+                Self::LayerTap(parse_layer(layer)?, base_code(base)?)
+            }
+            "TO" => Self::To(parse_layer(args)?),
+            "MO" => Self::Momentary(parse_layer(args)?),
+            "DF" => Self::DefaultLayer(parse_layer(args)?),
+            "TG" => Self::Toggle(parse_layer(args)?),
+            "OSL" => Self::OneShotLayer(parse_layer(args)?),
+            "OSM" => Self::OneShotMods(Mods::from_mask(args)?),
+            "TT" => Self::TapToggle(parse_layer(args)?),
+            _ => {
+                let mods = Mods::from_name(function)?;
+                match Self::from_name(args, base_code)? {
+                    Self::Basic(code) => Self::Mods(mods, code),
+                    Self::Mods(inner, code) => Self::Mods(combine(Some(mods), inner)?, code),
+                    _ => return None,
+                }
+            }
+        })
+    }
+
+    /// Apply `f` to the names of basic keycodes in a keycode name, leaving
+    /// the rest as is
+    ///
+    /// Returns `None` if `name` isn't a valid composite name, or `f` fails.
+    pub(crate) fn map_base_names<F: Fn(&str) -> Option<String>>(
+        name: &str,
+        f: F,
+    ) -> Option<String> {
+        let (function, args) = match split_call(name) {
+            Some(call) => call,
+            None => return f(name.trim()),
+        };
+
+        Some(match function {
+            "MT" | "LT" => {
+                let (arg, base) = split_args(args)?;
+                if split_call(base).is_some() {
+                    return None;
+                }
+                format!("{}({}, {})", function, arg, f(base)?)
+            }
+            "TO" | "MO" | "DF" | "TG" | "OSL" | "TT" => {
+                format!("{}({})", function, parse_layer(args)?)
+            }
+            "OSM" => format!("OSM({})", Mods::from_mask(args)?),
+            _ => {
+                Mods::from_name(function)?;
+                format!("{}({})", function, Self::map_base_names(args, f)?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_name(code: u8) -> Option<String> {
+        match code {
+            0x04 => Some("A".to_string()),
+            0x2C => Some("SPACE".to_string()),
+            _ => None,
+        }
+    }
+
+    fn base_code(name: &str) -> Option<u8> {
+        match name {
+            "A" => Some(0x04),
+            "SPACE" => Some(0x2C),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn keycode_encoding() {
+        let keycodes = [
+            (0x0004, Keycode::Basic(0x04), "A"),
+            (
+                0x0304,
+                Keycode::Mods(Mods::CTRL | Mods::SHIFT, 0x04),
+                "LCTL(LSFT(A))",
+            ),
+            (
+                0x1404,
+                Keycode::Mods(Mods::RIGHT | Mods::ALT, 0x04),
+                "RALT(A)",
+            ),
+            (0x6104, Keycode::ModTap(Mods::CTRL, 0x04), "MT(MOD_LCTL, A)"),
+            (
+                0x7A2C,
+                Keycode::ModTap(Mods::RIGHT | Mods::SHIFT | Mods::GUI, 0x2C),
+                "MT(MOD_RSFT | MOD_RGUI, SPACE)",
+            ),
+            (0x412C, Keycode::LayerTap(1, 0x2C), "LT(1, SPACE)"),
+            (0x5012, Keycode::To(2), "TO(2)"),
+            (0x5101, Keycode::Momentary(1), "MO(1)"),
+            (0x5203, Keycode::DefaultLayer(3), "DF(3)"),
+            (0x5302, Keycode::Toggle(2), "TG(2)"),
+            (0x5401, Keycode::OneShotLayer(1), "OSL(1)"),
+            (0x5502, Keycode::OneShotMods(Mods::SHIFT), "OSM(MOD_LSFT)"),
+            (0x5801, Keycode::TapToggle(1), "TT(1)"),
+        ];
+        for (code, keycode, name) in keycodes.iter() {
+            assert_eq!(Keycode::from_u16(*code), *keycode);
+            assert_eq!(keycode.to_u16(), Some(*code));
+            assert_eq!(keycode.name(base_name).as_deref(), Some(*name));
+            assert_eq!(Keycode::from_name(name, base_code), Some(*keycode));
+        }
+
+        assert_eq!(Keycode::from_u16(0x5C00), Keycode::Other(0x5C00));
+        assert_eq!(Keycode::from_u16(0x1004), Keycode::Other(0x1004));
+        assert_eq!(Keycode::Other(0x5C00).name(base_name), None);
+        assert_eq!(Keycode::LayerTap(16, 0x04).to_u16(), None);
+        assert_eq!(Keycode::Mods(Mods::RIGHT, 0x04).to_u16(), None);
+    }
+
+    #[test]
+    fn keycode_names() {
+        assert_eq!(
+            Keycode::from_name("MT( MOD_LCTL|MOD_LALT ,A)", base_code),
+            Some(Keycode::ModTap(Mods::CTRL | Mods::ALT, 0x04))
+        );
+        // Left and right modifiers can't be combined
+        assert_eq!(Keycode::from_name("LCTL(RSFT(A))", base_code), None);
+        assert_eq!(Keycode::from_name("LCTL(MO(1))", base_code), None);
+        assert_eq!(Keycode::from_name("LT(1, B)", base_code), None);
+        assert_eq!(Keycode::from_name("XYZ(A)", base_code), None);
+
+        assert_eq!(
+            Keycode::map_base_names("LCTL(LSFT(A))", |name| Some(format!("KC_{}", name)))
+                .as_deref(),
+            Some("LCTL(LSFT(KC_A))")
+        );
+        assert_eq!(
+            Keycode::map_base_names("LT(1,KC_SPC)", |name| Some(name[3..].to_string())).as_deref(),
+            Some("LT(1, SPC)")
+        );
+    }
+}
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::io::{Read, Write};
 
-use crate::Hs;
+use crate::{Hs, Layout};
 
 mod hs_serde {
     use super::*;
@@ -80,4 +81,389 @@ impl KeyMap {
     pub fn to_string_pretty(&self) -> String {
         serde_json::to_string_pretty(self).unwrap()
     }
+
+    /// Check keymap against `layout`, returning every problem found
+    ///
+    /// An empty result means the keymap can be fully applied to a board
+    /// with this layout.
+    pub fn validate(&self, layout: &Layout) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        let num_layers = layout.meta.num_layers as usize;
+        let logical_names = layout
+            .physical
+            .keys
+            .iter()
+            .map(|k| k.logical_name())
+            .collect::<HashSet<_>>();
+
+        let mut missing = logical_names
+            .iter()
+            .filter(|k| !self.map.contains_key(*k))
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+        issues.extend(missing.into_iter().map(Issue::MissingKey));
+
+        let mut keys = self.map.iter().collect::<Vec<_>>();
+        keys.sort_by_key(|(k, _)| *k);
+        for (key, scancodes) in keys {
+            if !logical_names.contains(key) {
+                issues.push(Issue::ExtraKey(key.clone()));
+                continue;
+            }
+            if scancodes.len() != num_layers {
+                issues.push(Issue::KeyLayerCount {
+                    key: key.clone(),
+                    expected: num_layers,
+                    found: scancodes.len(),
+                });
+            }
+            for (layer, scancode) in scancodes.iter().enumerate() {
+                if layout.scancode_from_name(scancode).is_none() {
+                    issues.push(Issue::UnknownScancode {
+                        key: key.clone(),
+                        layer,
+                        scancode: scancode.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut key_leds = self.key_leds.keys().collect::<Vec<_>>();
+        key_leds.sort();
+        for key in key_leds {
+            let has_leds = layout.leds.get(key).map_or(false, |leds| !leds.is_empty());
+            if !has_leds || !layout.meta.has_mode {
+                issues.push(Issue::KeyWithoutLed(key.clone()));
+            }
+        }
+
+        let num_led_layers = if layout.meta.has_per_layer {
+            num_layers
+        } else {
+            1
+        };
+        if self.layers.len() != num_led_layers {
+            issues.push(Issue::LayerCount {
+                expected: num_led_layers,
+                found: self.layers.len(),
+            });
+        }
+        for (layer, keymap_layer) in self.layers.iter().enumerate() {
+            let brightness = keymap_layer.brightness;
+            if !(0..=i32::from(u8::MAX)).contains(&brightness) {
+                issues.push(Issue::Brightness { layer, brightness });
+            }
+        }
+
+        issues
+    }
+}
+
+/// Problem found in a `KeyMap` by `KeyMap::validate`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// Scancode name is not known to the layout
+    UnknownScancode {
+        key: String,
+        layer: usize,
+        scancode: String,
+    },
+    /// Key of the layout has no entry in the keymap
+    MissingKey(String),
+    /// Keymap has an entry for a key not in the layout
+    ExtraKey(String),
+    /// Key has a scancode count different from the number of layers
+    KeyLayerCount {
+        key: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Number of layer LED settings doesn't match the layout
+    LayerCount { expected: usize, found: usize },
+    /// LED color given for a key without a controllable LED
+    KeyWithoutLed(String),
+    /// Layer brightness is outside of the range supported by firmware
+    Brightness { layer: usize, brightness: i32 },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownScancode {
+                key,
+                layer,
+                scancode,
+            } => write!(
+                f,
+                "Key {} on layer {} has unknown scancode '{}'",
+                key,
+                layer + 1,
+                scancode
+            ),
+            Self::MissingKey(key) => write!(f, "Key {} is missing", key),
+            Self::ExtraKey(key) => write!(f, "Key {} does not exist on this keyboard", key),
+            Self::KeyLayerCount {
+                key,
+                expected,
+                found,
+            } => write!(f, "Key {} has {} layers, expected {}", key, found, expected),
+            Self::LayerCount { expected, found } => write!(
+                f,
+                "Keymap has LED settings for {} layers, expected {}",
+                found, expected
+            ),
+            Self::KeyWithoutLed(key) => write!(f, "Key {} has no LED to set a color for", key),
+            Self::Brightness { layer, brightness } => write!(
+                f,
+                "Layer {} has out of range brightness {}",
+                layer + 1,
+                brightness
+            ),
+        }
+    }
+}
+
+/// Selects which parts of a `KeyMap` are applied by `Board::import_keymap`
+#[derive(Clone, Debug)]
+pub struct KeyMapImportOptions {
+    /// Layers to import scancodes and layer settings for; `None` for all layers
+    pub layers: Option<HashSet<usize>>,
+    /// Import per-key LED colors
+    pub key_leds: bool,
+    /// Import the mode, brightness and color of each layer
+    pub layer_settings: bool,
+    /// Logical names of keys to import; `None` for all keys
+    pub keys: Option<HashSet<String>>,
+}
+
+impl KeyMapImportOptions {
+    /// Test if scancodes and settings of `layer` should be imported
+    pub fn has_layer(&self, layer: usize) -> bool {
+        self.layers
+            .as_ref()
+            .map_or(true, |layers| layers.contains(&layer))
+    }
+
+    /// Test if the key with logical name `key` should be imported
+    pub fn has_key(&self, key: &str) -> bool {
+        self.keys.as_ref().map_or(true, |keys| keys.contains(key))
+    }
+
+    /// Key names, layers and scancode names of `keymap` to import, for a
+    /// board with `num_layers` layers
+    pub fn selected_scancodes<'a>(
+        &'a self,
+        keymap: &'a KeyMap,
+        num_layers: usize,
+    ) -> impl Iterator<Item = (&'a str, usize, &'a str)> + 'a {
+        keymap
+            .map
+            .iter()
+            .filter(move |(k, _)| self.has_key(k))
+            .flat_map(move |(k, v)| {
+                v.iter()
+                    .enumerate()
+                    .take(num_layers)
+                    .filter(move |(layer, _)| self.has_layer(*layer))
+                    .map(move |(layer, scancode)| (k.as_str(), layer, scancode.as_str()))
+            })
+    }
+
+    /// Key names and LED colors of `keymap` to import
+    pub fn selected_key_leds<'a>(
+        &'a self,
+        keymap: &'a KeyMap,
+    ) -> impl Iterator<Item = (&'a str, Option<Hs>)> + 'a {
+        keymap
+            .key_leds
+            .iter()
+            .filter(move |(k, _)| self.key_leds && self.has_key(k))
+            .map(|(k, hs)| (k.as_str(), *hs))
+    }
+
+    /// Layers of `keymap` to import the settings of
+    ///
+    /// Without per-layer settings, the only layer applies to the whole
+    /// keyboard, so it is imported regardless of `layers`.
+    pub fn selected_layers<'a>(
+        &'a self,
+        keymap: &'a KeyMap,
+        has_per_layer: bool,
+    ) -> impl Iterator<Item = (usize, &'a KeyMapLayer)> + 'a {
+        keymap
+            .layers
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| self.layer_settings && (!has_per_layer || self.has_layer(*i)))
+    }
+}
+
+impl Default for KeyMapImportOptions {
+    fn default() -> Self {
+        Self {
+            layers: None,
+            key_leds: true,
+            layer_settings: true,
+            keys: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn default_keymap_valid() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        assert_eq!(layout.default.validate(&layout), Vec::new());
+    }
+
+    #[test]
+    fn import_options() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        let mut keymap = layout.default.clone();
+        keymap.key_leds.insert("K00".to_string(), None);
+        keymap
+            .key_leds
+            .insert("K01".to_string(), Some(Hs::new(1., 1.)));
+        let layer_indices = |options: &KeyMapImportOptions, has_per_layer| {
+            options
+                .selected_layers(&keymap, has_per_layer)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+
+        let all = KeyMapImportOptions::default();
+        let scancodes = keymap.map.values().map(|v| v.len().min(2)).sum::<usize>();
+        assert_eq!(all.selected_scancodes(&keymap, 2).count(), scancodes);
+        assert_eq!(
+            all.selected_key_leds(&keymap).count(),
+            keymap.key_leds.len()
+        );
+        assert_eq!(
+            layer_indices(&all, true),
+            (0..keymap.layers.len()).collect::<Vec<_>>()
+        );
+
+        let mut options = KeyMapImportOptions {
+            layers: Some(vec![1].into_iter().collect()),
+            key_leds: true,
+            layer_settings: true,
+            keys: Some(vec!["K00".to_string()].into_iter().collect()),
+        };
+        assert_eq!(
+            options.selected_scancodes(&keymap, 4).collect::<Vec<_>>(),
+            vec![("K00", 1, keymap.map["K00"][1].as_str())]
+        );
+        assert_eq!(
+            options.selected_key_leds(&keymap).collect::<Vec<_>>(),
+            vec![("K00", None)]
+        );
+        assert_eq!(layer_indices(&options, true), vec![1]);
+        // Without per-layer settings, layers aren't filtered
+        assert_eq!(layer_indices(&options, false).len(), keymap.layers.len());
+
+        options.key_leds = false;
+        options.layer_settings = false;
+        assert_eq!(options.selected_scancodes(&keymap, 4).count(), 1);
+        assert_eq!(options.selected_key_leds(&keymap).count(), 0);
+        assert_eq!(layer_indices(&options, false), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn invalid_keymap_issues() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        let mut keymap = layout.default.clone();
+        keymap.map.remove("K00");
+        keymap
+            .map
+            .insert("KZZ".to_string(), vec!["A".to_string(); 4]);
+        keymap.map.get_mut("K01").unwrap()[2] = "NOT_A_SCANCODE".to_string();
+        keymap.map.get_mut("K02").unwrap().pop();
+        keymap.layers.pop();
+        keymap.layers[0].brightness = 300;
+
+        let issues = keymap.validate(&layout);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::MissingKey("K00".to_string()),
+                Issue::UnknownScancode {
+                    key: "K01".to_string(),
+                    layer: 2,
+                    scancode: "NOT_A_SCANCODE".to_string(),
+                },
+                Issue::KeyLayerCount {
+                    key: "K02".to_string(),
+                    expected: 4,
+                    found: 3,
+                },
+                Issue::ExtraKey("KZZ".to_string()),
+                Issue::LayerCount {
+                    expected: 4,
+                    found: 3,
+                },
+                Issue::Brightness {
+                    layer: 0,
+                    brightness: 300,
+                },
+            ]
+        );
+    }
+
+    fn keymap_layer() -> impl Strategy<Value = KeyMapLayer> {
+        (any::<Option<(u8, u8)>>(), any::<i32>(), any::<(u8, u8)>()).prop_map(
+            |(mode, brightness, (h, s))| KeyMapLayer {
+                mode,
+                brightness,
+                color: Hs::from_ints(h, s),
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn keymap_from_any_str(json in "\\PC*") {
+            let layout = Layout::from_board("system76/launch_1").unwrap();
+            if let Ok(keymap) = KeyMap::from_str(&json) {
+                keymap.validate(&layout);
+            }
+        }
+
+        #[test]
+        fn keymap_round_trip(
+            model in ".*",
+            map in prop::collection::hash_map(".*", prop::collection::vec(".*", 0..4), 0..8),
+            key_leds in prop::collection::hash_map(".*", any::<Option<(u8, u8)>>(), 0..8),
+            layers in prop::collection::vec(keymap_layer(), 0..4),
+        ) {
+            let layout = Layout::from_board("system76/launch_1").unwrap();
+            let keymap = KeyMap {
+                model,
+                version: 1,
+                map,
+                key_leds: key_leds
+                    .into_iter()
+                    .map(|(k, v)| (k, v.map(|(h, s)| Hs::from_ints(h, s))))
+                    .collect(),
+                layers,
+            };
+            let parsed = KeyMap::from_str(&keymap.to_string_pretty()).unwrap();
+            prop_assert_eq!(&parsed.map, &keymap.map);
+            // Colors are stored as integers, where hue wraps around
+            let ints = |keymap: &KeyMap| {
+                keymap
+                    .key_leds
+                    .iter()
+                    .map(|(k, hs)| (k.clone(), hs.map(Hs::to_ints)))
+                    .collect::<HashMap<_, _>>()
+            };
+            prop_assert_eq!(ints(&parsed), ints(&keymap));
+            prop_assert_eq!(parsed.validate(&layout), keymap.validate(&layout));
+        }
+    }
 }
@@ -1,7 +1,7 @@
 use glib::clone::Downgrade;
 use std::cell::Cell;
 
-use crate::{Board, Daemon, Hs, Mode, Rgb};
+use crate::{layer_led_index, Board, Hs, LayerState, Mode, Rgb};
 
 #[derive(Debug)]
 pub struct Layer {
@@ -14,48 +14,19 @@ pub struct Layer {
 }
 
 impl Layer {
-    pub(crate) fn new(daemon: &dyn Daemon, board: &Board, layer: u8) -> Self {
-        let index = if board.layout().meta.has_per_layer {
-            0xf0 + layer
-        } else {
-            0xff
-        };
-        let mode = if board.layout().meta.has_mode {
-            daemon
-                .mode(board.board(), layer)
-                .map(Some)
-                .unwrap_or_else(|err| {
-                    error!("Error getting layer mode: {}", err);
-                    None
-                })
-        } else {
-            None
+    pub(crate) fn new(board: &Board, layer: u8, state: &LayerState) -> Self {
+        let index = layer_led_index(board.layout(), layer);
+        let color = match state.color {
+            Some((r, g, b)) if index == 0xff => Rgb::new(r, g, b).to_hs_lossy(),
+            Some((h, s, _)) => Hs::from_ints(h, s),
+            None => Hs::new(0., 0.),
         };
-        let brightness = daemon
-            .brightness(board.board(), index)
-            .unwrap_or_else(|err| {
-                error!("error getting layer brightness: {}", err);
-                0
-            });
-        let color = daemon
-            .color(board.board(), index)
-            .map(|color| {
-                if index == 0xff {
-                    Rgb::new(color.0, color.1, color.2).to_hs_lossy()
-                } else {
-                    Hs::from_ints(color.0, color.1)
-                }
-            })
-            .unwrap_or_else(|err| {
-                error!("error getting layer color: {}", err);
-                Hs::new(0., 0.)
-            });
         Self {
             layer,
             index,
             board: board.downgrade(),
-            mode: Cell::new(mode),
-            brightness: Cell::new(brightness),
+            mode: Cell::new(state.mode),
+            brightness: Cell::new(state.brightness),
             color: Cell::new(color),
         }
     }
@@ -72,10 +43,7 @@ impl Layer {
 
     pub async fn set_mode(&self, mode: &Mode, speed: u8) -> Result<(), String> {
         let board = self.board();
-        board
-            .thread_client()
-            .set_mode(board.board(), self.layer, mode.index, speed)
-            .await?;
+        board.core().set_mode(self.layer, mode.index, speed).await?;
         self.mode.set(Some((mode.index, speed)));
         board.set_leds_changed();
         Ok(())
@@ -88,10 +56,7 @@ impl Layer {
 
     pub async fn set_brightness(&self, brightness: i32) -> Result<(), String> {
         let board = self.board();
-        board
-            .thread_client()
-            .set_brightness(board.board(), self.index, brightness)
-            .await?;
+        board.core().set_brightness(self.index, brightness).await?;
         self.brightness.set(brightness);
         board.set_leds_changed();
         Ok(())
@@ -111,10 +76,7 @@ impl Layer {
             let (h, s) = hs.to_ints();
             (h, s, 0)
         };
-        board
-            .thread_client()
-            .set_color(board.board(), self.index, color)
-            .await?;
+        board.core().set_color(self.index, color).await?;
         self.color.set(hs);
         board.set_leds_changed();
         Ok(())
@@ -0,0 +1,103 @@
+use serde::de::DeserializeOwned;
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Error loading a keyboard layout
+#[derive(Debug)]
+pub enum LayoutError {
+    /// No layout exists for this board
+    UnknownBoard(String),
+    /// Failed to read a layout file
+    Io { file: PathBuf, err: io::Error },
+    /// Failed to parse a layout file; `path` is the location within the
+    /// JSON document, like `rows[3][2]`
+    Json {
+        file: PathBuf,
+        path: String,
+        err: serde_json::Error,
+    },
+}
+
+impl LayoutError {
+    /// Layout file the error occured in
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Self::UnknownBoard(_) => None,
+            Self::Io { file, .. } | Self::Json { file, .. } => Some(file),
+        }
+    }
+
+    /// Line in the layout file the error occured on, starting at 1
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::Json { err, .. } => Some(err.line()),
+            _ => None,
+        }
+    }
+
+    /// Column in the layout file the error occured on, starting at 1
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            Self::Json { err, .. } => Some(err.column()),
+            _ => None,
+        }
+    }
+
+    /// Prefix file name with the directory it was loaded from
+    pub(crate) fn in_dir(mut self, dir: &Path) -> Self {
+        match &mut self {
+            Self::UnknownBoard(_) => {}
+            Self::Io { file, .. } | Self::Json { file, .. } => *file = dir.join(&file),
+        }
+        self
+    }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownBoard(board) => write!(f, "No layout for board '{}'", board),
+            Self::Io { file, err } => write!(f, "Failed to read {}: {}", file.display(), err),
+            Self::Json { file, path, err } if path.is_empty() || path == "." => {
+                write!(f, "Failed to parse {}: {}", file.display(), err)
+            }
+            Self::Json { file, path, err } => write!(
+                f,
+                "Failed to parse {} at '{}': {}",
+                file.display(),
+                path,
+                err
+            ),
+        }
+    }
+}
+
+impl Error for LayoutError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::UnknownBoard(_) => None,
+            Self::Io { err, .. } => Some(err),
+            Self::Json { err, .. } => Some(err),
+        }
+    }
+}
+
+/// Parse the layout file `file`, keeping track of where in the document errors occur
+pub(crate) fn parse_json<T: DeserializeOwned>(file: &str, json: &str) -> Result<T, LayoutError> {
+    let json_error = |path: String, err| LayoutError::Json {
+        file: file.into(),
+        path,
+        err,
+    };
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let value = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|err| json_error(err.path().to_string(), err.into_inner()))?;
+    // Trailing characters after the document
+    deserializer
+        .end()
+        .map_err(|err| json_error(String::new(), err))?;
+    Ok(value)
+}
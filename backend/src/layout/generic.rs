@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use super::{
+    layouts, physical_layout::MAX_LOGICAL_DIMENSION, Layout, LayoutError, Meta, PhysicalLayout,
+};
+use crate::{Hs, KeyMap, KeyMapLayer, ViaDefinition};
+
+/// Model name without its trailing version number, like `system76/launch`
+fn family(model: &str) -> &str {
+    model
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end_matches('_')
+}
+
+/// Newest known board of the same family as `model`, or otherwise the one
+/// with the longest common prefix, so `system76/launch_2` uses the scancodes
+/// of `system76/launch_1`
+fn closest_board(model: &str) -> &'static str {
+    if let Some(board) = layouts()
+        .iter()
+        .rev()
+        .find(|board| family(board) == family(model))
+    {
+        return board;
+    }
+
+    let common_prefix = |board: &str| {
+        board
+            .chars()
+            .zip(model.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    layouts()
+        .iter()
+        .rev()
+        .max_by_key(|board| common_prefix(board))
+        .unwrap()
+}
+
+/// Empty keymap, with the layer settings of the first layer of `template`
+fn generic_default(model: &str, template: &Layout) -> KeyMap {
+    KeyMap {
+        model: model.to_string(),
+        version: 1,
+        map: HashMap::new(),
+        key_leds: HashMap::new(),
+        layers: template.default.layers.iter().take(1).cloned().collect(),
+    }
+}
+
+impl Layout {
+    /// Layout for a board with no known layout, with a key for each position
+    /// of its `rows` by `cols` matrix
+    ///
+    /// Scancodes and number of layers are taken from the closest known board,
+    /// which should be of the same firmware family (EC or QMK). There is no
+    /// default keymap, and no LED support.
+    pub fn generic(model: &str, rows: usize, cols: usize) -> Result<Self, LayoutError> {
+        let template_board = closest_board(model);
+        info!(
+            "Using generic {}x{} layout for '{}', with scancodes of '{}'",
+            rows, cols, model, template_board
+        );
+        let template = Self::from_board(template_board)?;
+
+        if rows > MAX_LOGICAL_DIMENSION || cols > MAX_LOGICAL_DIMENSION {
+            warn!(
+                "Matrix of '{}' is {}x{}, only showing {}x{}",
+                model, rows, cols, MAX_LOGICAL_DIMENSION, MAX_LOGICAL_DIMENSION
+            );
+        }
+        let mut layout = HashMap::new();
+        for row in 0..rows.min(MAX_LOGICAL_DIMENSION) {
+            for col in 0..cols.min(MAX_LOGICAL_DIMENSION) {
+                let logical = (row as u8, col as u8);
+                layout.insert(super::physical_layout::logical_name(logical), logical);
+            }
+        }
+
+        let meta = Meta {
+            display_name: format!("Unknown Keyboard ({})", model),
+            has_mode: false,
+            has_per_layer: false,
+            num_layers: template.meta.num_layers,
+        };
+        let default = generic_default(model, &template);
+        let mut physical = PhysicalLayout::synthesize(&meta.display_name, &layout, &default);
+        for key in &mut physical.keys {
+            key.physical_name = format!("{}, {}", key.logical.0, key.logical.1);
+        }
+
+        let mut layout = Self::from_template(template, meta, default, physical, layout);
+        layout.is_generic = true;
+        Ok(layout)
+    }
+
+    /// Layout for a board with a VIA definition and `num_layers` layers, with
+    /// keys placed as in the definition
+    ///
+    /// Like `generic`, there is no default keymap or LED support. Scancodes
+    /// are taken from the newest known QMK board, since VIA boards run QMK.
+    pub fn from_via_definition(
+        model: &str,
+        definition: &ViaDefinition,
+        num_layers: u8,
+    ) -> Result<Self, String> {
+        let template = layouts()
+            .iter()
+            .rev()
+            .filter_map(|board| Self::from_board(board).ok())
+            .find(Self::is_qmk)
+            .ok_or_else(|| "No QMK layout to take scancodes from".to_string())?;
+
+        let keys = definition.keys()?;
+        // Logical names are from the KLE position, like `physical.json`
+        let too_large = |position: usize| position >= MAX_LOGICAL_DIMENSION;
+        if let Some(key) = keys
+            .iter()
+            .find(|key| too_large(key.kle_position.0) || too_large(key.kle_position.1))
+        {
+            return Err(format!(
+                "KLE position {:?} is larger than {}x{}",
+                key.kle_position, MAX_LOGICAL_DIMENSION, MAX_LOGICAL_DIMENSION
+            ));
+        }
+        let physical = PhysicalLayout::from_via_keys(&definition.name, &keys);
+        let layout = physical
+            .keys
+            .iter()
+            .zip(&keys)
+            .map(|(physical_key, key)| (physical_key.logical_name(), key.matrix_position))
+            .collect();
+
+        let meta = Meta {
+            display_name: definition.name.clone(),
+            has_mode: false,
+            has_per_layer: false,
+            num_layers,
+        };
+        let default = generic_default(model, &template);
+
+        Ok(Self::from_template(
+            template, meta, default, physical, layout,
+        ))
+    }
+
+    /// Layout for a board with only lighting, such as a keyboard backlight,
+    /// with no keys or layers to remap
+    pub fn lighting_only(model: &str, display_name: &str) -> Self {
+        let meta = Meta {
+            display_name: display_name.to_string(),
+            has_mode: false,
+            has_per_layer: false,
+            num_layers: 0,
+        };
+        let default = KeyMap {
+            model: model.to_string(),
+            version: 1,
+            map: HashMap::new(),
+            key_leds: HashMap::new(),
+            // Off and white, like the backlights of laptops
+            layers: vec![KeyMapLayer {
+                mode: None,
+                brightness: 0,
+                color: Hs::new(0., 0.),
+            }],
+        };
+        let physical = PhysicalLayout::synthesize(display_name, &HashMap::new(), &default);
+        Self {
+            meta,
+            default,
+            keymap: HashMap::new(),
+            scancode_names: HashMap::new(),
+            physical,
+            layout: HashMap::new(),
+            leds: HashMap::new(),
+            is_generic: false,
+        }
+    }
+
+    fn from_template(
+        template: Self,
+        meta: Meta,
+        default: KeyMap,
+        physical: PhysicalLayout,
+        layout: HashMap<String, (u8, u8)>,
+    ) -> Self {
+        Self {
+            meta,
+            default,
+            keymap: template.keymap,
+            scancode_names: template.scancode_names,
+            physical,
+            layout,
+            leds: HashMap::new(),
+            is_generic: false,
+        }
+    }
+
+    /// `true` if this is a `Layout::generic` layout, for a board with no known layout
+    pub fn is_generic(&self) -> bool {
+        self.is_generic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic_layout() {
+        assert_eq!(closest_board("system76/launch_2"), "system76/launch_1");
+        assert_eq!(closest_board("system76/darp8"), "system76/darp7");
+
+        let layout = Layout::generic("system76/launch_2", 6, 15).unwrap();
+        assert!(layout.is_generic());
+        assert_eq!(layout.meta.num_layers, 4);
+        assert_eq!(layout.physical_keys().len(), 6 * 15);
+        assert_eq!(layout.electrical("K5E"), Some((5, 14)));
+        assert!(layout.scancode_from_name("LAYER_TOGGLE_1").is_some());
+    }
+
+    #[test]
+    fn lighting_only_layout() {
+        let layout = Layout::lighting_only("sysfs/dell::kbd_backlight", "Keyboard Backlight");
+        assert!(!layout.is_generic());
+        assert_eq!(layout.meta.num_layers, 0);
+        assert!(layout.physical_keys().is_empty());
+        assert_eq!(layout.default.validate(&layout), Vec::new());
+    }
+
+    #[test]
+    fn via_definition_layout() {
+        let launch = Layout::from_board("system76/launch_1").unwrap();
+        let definition = launch.via_definition(0x3384, 0x0001).unwrap();
+        let layout = Layout::from_via_definition("via/3384_0001", &definition, 3).unwrap();
+        assert!(!layout.is_generic() && layout.is_qmk());
+        assert_eq!(layout.meta.num_layers, 3);
+        assert_eq!(layout.meta.display_name, definition.name);
+        assert_eq!(layout.physical_keys().len(), launch.physical_keys().len());
+        for (key, launch_key) in layout.physical_keys().iter().zip(launch.physical_keys()) {
+            assert_eq!(key.logical, launch_key.logical);
+            assert_eq!(key.logical_name(), launch_key.logical_name());
+            let logical_name = key.logical_name();
+            assert_eq!(
+                layout.electrical(&logical_name),
+                launch.electrical(&logical_name)
+            );
+        }
+        assert_eq!(layout.physical_keys()[0].physical_name, "0, 0");
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use super::Layout;
+use crate::Issue;
+
+/// Inconsistency between the files of a layout, found by `Layout::lint`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LintIssue {
+    /// Key in `physical.json` missing from `layout.json`
+    MissingElectrical(String),
+    /// Key in `layout.json` not in `physical.json`
+    ExtraElectrical(String),
+    /// Keys in `layout.json` sharing an electrical position
+    DuplicateElectrical {
+        position: (u8, u8),
+        keys: Vec<String>,
+    },
+    /// Key in `leds.json` not in `physical.json`
+    ExtraLeds(String),
+    /// Keys in `leds.json` sharing an LED index
+    DuplicateLed { led: u8, keys: Vec<String> },
+    /// Problem with `default.json`, such as a scancode missing from `keymap.json`
+    Default(Issue),
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingElectrical(key) => write!(f, "Key {} missing from layout.json", key),
+            Self::ExtraElectrical(key) => {
+                write!(f, "Key {} in layout.json is not in physical.json", key)
+            }
+            Self::DuplicateElectrical { position, keys } => write!(
+                f,
+                "Keys {} have the same electrical position {}, {}",
+                keys.join(", "),
+                position.0,
+                position.1
+            ),
+            Self::ExtraLeds(key) => write!(f, "Key {} in leds.json is not in physical.json", key),
+            Self::DuplicateLed { led, keys } => {
+                write!(f, "Keys {} have the same LED {}", keys.join(", "), led)
+            }
+            Self::Default(issue) => write!(f, "default.json: {}", issue),
+        }
+    }
+}
+
+/// Find keys of each value that is shared by more than one key
+fn duplicates<T: Ord>(entries: impl Iterator<Item = (T, String)>) -> Vec<(T, Vec<String>)> {
+    let mut by_value = BTreeMap::<T, Vec<String>>::new();
+    for (value, key) in entries {
+        by_value.entry(value).or_default().push(key);
+    }
+    by_value
+        .into_iter()
+        .filter(|(_, keys)| keys.len() > 1)
+        .map(|(value, mut keys)| {
+            keys.sort();
+            (value, keys)
+        })
+        .collect()
+}
+
+impl Layout {
+    /// Cross-check the files of this layout against `physical.json`
+    ///
+    /// An empty result means the layout is consistent.
+    pub fn lint(&self) -> Vec<LintIssue> {
+        let mut issues = Vec::new();
+
+        let logical_names = self
+            .physical
+            .keys
+            .iter()
+            .map(|k| k.logical_name())
+            .collect::<HashSet<_>>();
+
+        for key in &self.physical.keys {
+            let name = key.logical_name();
+            if !self.layout.contains_key(&name) {
+                issues.push(LintIssue::MissingElectrical(name));
+            }
+        }
+
+        let mut electrical = self.layout.keys().collect::<Vec<_>>();
+        electrical.sort();
+        for key in electrical {
+            if !logical_names.contains(key) {
+                issues.push(LintIssue::ExtraElectrical(key.clone()));
+            }
+        }
+
+        let positions = self.layout.iter().map(|(key, pos)| (*pos, key.clone()));
+        for (position, keys) in duplicates(positions) {
+            issues.push(LintIssue::DuplicateElectrical { position, keys });
+        }
+
+        let mut leds = self.leds.keys().collect::<Vec<_>>();
+        leds.sort();
+        for key in leds {
+            if !logical_names.contains(key) {
+                issues.push(LintIssue::ExtraLeds(key.clone()));
+            }
+        }
+
+        let indexes = self
+            .leds
+            .iter()
+            .flat_map(|(key, leds)| leds.iter().map(move |led| (*led, key.clone())));
+        for (led, keys) in duplicates(indexes) {
+            issues.push(LintIssue::DuplicateLed { led, keys });
+        }
+
+        issues.extend(
+            self.default
+                .validate(self)
+                .into_iter()
+                .map(LintIssue::Default),
+        );
+
+        issues
+    }
+}
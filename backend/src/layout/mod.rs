@@ -1,11 +1,24 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
 
+mod error;
+mod generic;
+mod lint;
 mod meta;
 mod physical_layout;
+use self::error::parse_json;
+pub use self::error::LayoutError;
+pub use self::lint::LintIssue;
 pub use self::meta::Meta;
-pub(crate) use physical_layout::{PhysicalLayout, PhysicalLayoutKey};
+pub(crate) use physical_layout::PhysicalLayout;
+pub use physical_layout::PhysicalLayoutKey;
 
-use crate::{KeyMap, Rgb};
+use crate::{KeyMap, Keycode, Rgb};
 
 pub struct Layout {
     /// Metadata for keyboard
@@ -17,11 +30,28 @@ pub struct Layout {
     pub(crate) physical: PhysicalLayout,
     pub(crate) layout: HashMap<String, (u8, u8)>,
     pub(crate) leds: HashMap<String, Vec<u8>>,
+    is_generic: bool,
 }
 
+/// Layout files of a board; `physical.json` is optional
+type LayoutData = (
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+);
+
 macro_rules! keyboards {
-    ($( $board:expr ),* $(,)?) => {
-        fn layout_data(board: &str) -> Option<(&'static str, &'static str, &'static str, &'static str, &'static str, &'static str)> {
+    (@physical $board:expr) => {
+        Some(include_str!(concat!("../../../layouts/", $board, "/physical.json")))
+    };
+    (@physical $board:expr, synthesized) => {
+        None
+    };
+    ($( $board:literal $( ($synthesized:ident) )? ),* $(,)?) => {
+        fn layout_data(board: &str) -> Option<LayoutData> {
             match board {
                 $(
                 $board => {
@@ -35,8 +65,7 @@ macro_rules! keyboards {
                         include_str!(concat!("../../../layouts/", $board, "/layout.json"));
                     let leds_json =
                         include_str!(concat!("../../../layouts/", $board, "/leds.json"));
-                    let physical_json =
-                        include_str!(concat!("../../../layouts/", $board, "/physical.json"));
+                    let physical_json = keyboards!(@physical $board $(, $synthesized)?);
                     Some((meta_json, default_json, keymap_json, layout_json, leds_json, physical_json))
                 }
                 )*
@@ -51,22 +80,41 @@ macro_rules! keyboards {
     };
 }
 
+// Boards marked `(synthesized)` have no `physical.json`, so keys are laid
+// out in a grid by `PhysicalLayout::synthesize`
 keyboards![
     "system76/addw1",
     "system76/addw2",
     "system76/bonw14",
     "system76/darp5",
     "system76/darp6",
+    "system76/darp7"(synthesized),
+    "system76/galp3-c"(synthesized),
+    "system76/galp4"(synthesized),
+    "system76/galp5"(synthesized),
     "system76/gaze15",
     "system76/launch_alpha_1",
     "system76/launch_alpha_2",
     "system76/launch_1",
+    "system76/launch_test"(synthesized),
     "system76/lemp9",
+    "system76/lemp10"(synthesized),
     "system76/oryp5",
     "system76/oryp6",
     "system76/oryp7",
+    "system76/ortho_split_2u"(synthesized),
+    "system76/virgo"(synthesized),
 ];
 
+/// Parse a raw scancode written as `0x` and up to 4 hex digits, like `0x5C01`
+pub(crate) fn parse_raw_scancode(name: &str) -> Option<u16> {
+    let digits = name.strip_prefix("0x")?;
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u16::from_str_radix(digits, 16).ok()
+}
+
 impl Layout {
     pub fn from_data(
         meta_json: &str,
@@ -74,15 +122,18 @@ impl Layout {
         keymap_json: &str,
         layout_json: &str,
         leds_json: &str,
-        physical_json: &str,
-    ) -> Self {
-        let meta = serde_json::from_str(meta_json).unwrap();
-        let default = KeyMap::from_str(default_json).unwrap();
-        let (keymap, scancode_names) = parse_keymap_json(keymap_json);
-        let layout = serde_json::from_str(layout_json).unwrap();
-        let leds = serde_json::from_str(leds_json).unwrap();
-        let physical = PhysicalLayout::from_str(physical_json);
-        Self {
+        physical_json: Option<&str>,
+    ) -> Result<Self, LayoutError> {
+        let meta: Meta = parse_json("meta.json", meta_json)?;
+        let default = parse_json("default.json", default_json)?;
+        let (keymap, scancode_names) = parse_keymap_json(keymap_json)?;
+        let layout = parse_json("layout.json", layout_json)?;
+        let leds = parse_json("leds.json", leds_json)?;
+        let physical = match physical_json {
+            Some(physical_json) => PhysicalLayout::from_str(physical_json)?,
+            None => PhysicalLayout::synthesize(&meta.display_name, &layout, &default),
+        };
+        Ok(Self {
             meta,
             default,
             keymap,
@@ -90,59 +141,156 @@ impl Layout {
             physical,
             layout,
             leds,
-        }
+            is_generic: false,
+        })
     }
 
-    #[allow(dead_code)]
-    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Self {
+    /// Load layout from a directory containing `meta.json`, `layout.json`, etc.
+    ///
+    /// If there is no `physical.json`, keys are laid out in a grid.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self, LayoutError> {
         let dir = dir.as_ref();
+        let read = |name: &str| {
+            fs::read_to_string(dir.join(name)).map_err(|err| LayoutError::Io {
+                file: dir.join(name),
+                err,
+            })
+        };
 
-        let meta_json =
-            fs::read_to_string(dir.join("meta.json")).expect("Failed to load meta.json");
-        let default_json =
-            fs::read_to_string(dir.join("default.json")).expect("Failed to load default.json");
-        let keymap_json =
-            fs::read_to_string(dir.join("keymap.json")).expect("Failed to load keymap.json");
-        let layout_json =
-            fs::read_to_string(dir.join("layout.json")).expect("Failed to load layout.json");
-        let leds_json =
-            fs::read_to_string(dir.join("leds.json")).expect("Failed to load leds.json");
-        let physical_json =
-            fs::read_to_string(dir.join("physical.json")).expect("Failed to load physical.json");
+        let physical_json = if dir.join("physical.json").exists() {
+            Some(read("physical.json")?)
+        } else {
+            None
+        };
 
         Self::from_data(
-            &meta_json,
-            &default_json,
-            &keymap_json,
-            &layout_json,
-            &leds_json,
-            &physical_json,
+            &read("meta.json")?,
+            &read("default.json")?,
+            &read("keymap.json")?,
+            &read("layout.json")?,
+            &read("leds.json")?,
+            physical_json.as_deref(),
         )
+        .map_err(|err| err.in_dir(dir))
     }
 
-    pub fn from_board(board: &str) -> Option<Self> {
-        layout_data(board).map(
-            |(meta_json, default_json, keymap_json, layout_json, leds_json, physical_json)| {
-                Self::from_data(
-                    meta_json,
-                    default_json,
-                    keymap_json,
-                    layout_json,
-                    leds_json,
-                    physical_json,
-                )
-            },
+    /// Load layout for `board`, like `system76/launch_1`
+    ///
+    /// Each directory from `layout_dirs` is searched first, in order, so
+    /// layouts can be added or overridden without rebuilding. Layouts built
+    /// into the binary are used as a fallback.
+    pub fn from_board(board: &str) -> Result<Self, LayoutError> {
+        Self::from_board_in(board, &layout_dirs())
+    }
+
+    /// Load layout for `board`, searching `dirs` before built in layouts
+    ///
+    /// Only boards named by `/` separated components are searched for, so
+    /// a name from a device like `../launch_1` can't escape `dirs`.
+    pub fn from_board_in<P: AsRef<Path>>(board: &str, dirs: &[P]) -> Result<Self, LayoutError> {
+        let valid_name = board
+            .split('/')
+            .all(|c| !c.is_empty() && c != "." && c != ".." && !c.contains('\\'));
+        if !valid_name {
+            return Err(LayoutError::UnknownBoard(board.to_string()));
+        }
+
+        for dir in dirs {
+            let dir = dir.as_ref().join(board);
+            if dir.join("meta.json").is_file() {
+                info!("Loading layout for '{}' from {}", board, dir.display());
+                return Self::from_dir(&dir);
+            }
+        }
+
+        let (meta_json, default_json, keymap_json, layout_json, leds_json, physical_json) =
+            layout_data(board).ok_or_else(|| LayoutError::UnknownBoard(board.to_string()))?;
+        Self::from_data(
+            meta_json,
+            default_json,
+            keymap_json,
+            layout_json,
+            leds_json,
+            physical_json,
         )
     }
 
-    /// Get the scancode number corresponding to a name
-    pub fn scancode_to_name(&self, scancode: u16) -> Option<&str> {
-        self.scancode_names.get(&scancode).map(String::as_str)
+    /// `true` if the board runs QMK firmware, which supports composite keycodes
+    pub fn is_qmk(&self) -> bool {
+        // QMK basic keycodes are HID usages, unlike EC scancodes
+        self.keymap.get("A") == Some(&0x04)
     }
 
     /// Get the name corresponding to a scancode number
+    ///
+    /// On QMK boards, scancodes not in `keymap.json` may have a composite
+    /// name like `LT(1, SPACE)`; see `Keycode`.
+    pub fn scancode_to_name(&self, scancode: u16) -> Option<String> {
+        if let Some(name) = self.scancode_names.get(&scancode) {
+            return Some(name.clone());
+        }
+        if !self.is_qmk() {
+            return None;
+        }
+        Keycode::from_u16(scancode).name(|code| self.scancode_names.get(&code.into()).cloned())
+    }
+
+    /// Get the name of a scancode number, or its raw value in hex, like
+    /// `0x5C01`, if it has no name
+    pub fn scancode_name(&self, scancode: u16) -> String {
+        self.scancode_to_name(scancode)
+            .unwrap_or_else(|| format!("0x{:04X}", scancode))
+    }
+
+    /// Get the scancode number corresponding to a name
+    ///
+    /// Raw values in hex, as produced by `scancode_name`, are also accepted.
     pub fn scancode_from_name(&self, name: &str) -> Option<u16> {
-        self.keymap.get(name).copied()
+        if let Some(scancode) = self.keymap.get(name) {
+            return Some(*scancode);
+        }
+        if let Some(scancode) = parse_raw_scancode(name) {
+            return Some(scancode);
+        }
+        if !self.is_qmk() {
+            return None;
+        }
+        Keycode::from_name(name, |base| {
+            let scancode = *self.keymap.get(base)?;
+            if scancode <= 0xFF {
+                Some(scancode as u8)
+            } else {
+                None
+            }
+        })?
+        .to_u16()
+    }
+
+    /// Decode the scancode of `name`, which may be a composite name
+    pub fn keycode_from_name(&self, name: &str) -> Option<Keycode> {
+        self.scancode_from_name(name).map(Keycode::from_u16)
+    }
+
+    /// Names of basic keycodes, for use in composite keycodes, in order
+    pub fn basic_keycode_names(&self) -> Vec<(u8, &str)> {
+        (0..=0xFF)
+            .filter_map(|code| Some((code, self.scancode_names.get(&code.into())?.as_str())))
+            .collect()
+    }
+
+    /// Keys in `physical.json`, in order
+    pub fn physical_keys(&self) -> &[PhysicalLayoutKey] {
+        &self.physical.keys
+    }
+
+    /// Get the electrical mapping (output, input) of a key by logical name
+    pub fn electrical(&self, logical_name: &str) -> Option<(u8, u8)> {
+        self.layout.get(logical_name).copied()
+    }
+
+    /// Get the LED indexes of a key by logical name
+    pub fn leds(&self, logical_name: &str) -> &[u8] {
+        self.leds.get(logical_name).map_or(&[], Vec::as_slice)
     }
 
     pub fn pressed_color(&self) -> Rgb {
@@ -150,20 +298,64 @@ impl Layout {
     }
 }
 
-fn parse_keymap_json(keymap_json: &str) -> (HashMap<String, u16>, HashMap<u16, String>) {
+/// Environment variable with a list of directories to load layouts from,
+/// taking precedence over the XDG data directories
+pub const LAYOUT_DIRS_ENV: &str = "SYSTEM76_KEYBOARD_LAYOUTS";
+
+/// Directories searched for layouts, in order of precedence
+///
+/// These are the directories in `$SYSTEM76_KEYBOARD_LAYOUTS`, then
+/// `system76-keyboard-configurator/layouts` in `$XDG_DATA_HOME` and
+/// `$XDG_DATA_DIRS`. A layout for `system76/launch_1` is in the
+/// `system76/launch_1` subdirectory of one of these.
+pub fn layout_dirs() -> Vec<PathBuf> {
+    layout_dirs_from_vars(|name| env::var_os(name))
+}
+
+/// `layout_dirs`, with environment variables from `var`
+fn layout_dirs_from_vars<F: Fn(&str) -> Option<OsString>>(var: F) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(paths) = var(LAYOUT_DIRS_ENV) {
+        dirs.extend(env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+
+    let data_home = var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".local/share")));
+    let data_dirs = var("XDG_DATA_DIRS")
+        .filter(|paths| !paths.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    for dir in data_home
+        .into_iter()
+        .chain(env::split_paths(&data_dirs).filter(|p| p.is_absolute()))
+    {
+        dirs.push(dir.join("system76-keyboard-configurator/layouts"));
+    }
+
+    dirs
+}
+
+/// Scancode numbers by name, and names by number
+type ScancodeMaps = (HashMap<String, u16>, HashMap<u16, String>);
+
+fn parse_keymap_json(keymap_json: &str) -> Result<ScancodeMaps, LayoutError> {
     let mut keymap = HashMap::new();
     let mut scancode_names = HashMap::new();
-    let l: Vec<(String, u16)> = serde_json::from_str(keymap_json).unwrap();
+    let l: Vec<(String, u16)> = parse_json("keymap.json", keymap_json)?;
     for (scancode_name, scancode) in l {
         keymap.insert(scancode_name.clone(), scancode);
         scancode_names.insert(scancode, scancode_name);
     }
-    (keymap, scancode_names)
+    Ok((keymap, scancode_names))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Mods;
+    use proptest::prelude::*;
     use std::collections::HashSet;
 
     #[test]
@@ -173,6 +365,214 @@ mod tests {
         }
     }
 
+    #[test]
+    fn layout_from_dir_in_dirs() {
+        let dir = env::temp_dir().join(format!("keyboard-layouts-{}", std::process::id()));
+        let board_dir = dir.join("test/runtime");
+        fs::create_dir_all(&board_dir).unwrap();
+        for file in &[
+            "meta.json",
+            "default.json",
+            "keymap.json",
+            "layout.json",
+            "leds.json",
+            "physical.json",
+        ] {
+            fs::copy(
+                Path::new("../layouts/system76/launch_1").join(file),
+                board_dir.join(file),
+            )
+            .unwrap();
+        }
+
+        assert!(Layout::from_board("test/runtime").is_err());
+        let layout = Layout::from_board_in("test/runtime", &[&dir]).unwrap();
+        assert_eq!(layout.meta.display_name, "Launch Keyboard");
+        for board in &[
+            "test/../test/runtime",
+            "/test/runtime",
+            "test\\runtime",
+            "test//runtime",
+        ] {
+            assert!(Layout::from_board_in(board, &[&dir]).is_err());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn layout_dirs_from_env() {
+        let dirs = layout_dirs_from_vars(|name| match name {
+            LAYOUT_DIRS_ENV => Some("/a::/b".into()),
+            "HOME" => Some("/home/user".into()),
+            "XDG_DATA_DIRS" => Some("/c:relative".into()),
+            _ => None,
+        });
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("/a"),
+                PathBuf::from("/b"),
+                PathBuf::from("/home/user/.local/share/system76-keyboard-configurator/layouts"),
+                PathBuf::from("/c/system76-keyboard-configurator/layouts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_error_location() {
+        let physical_json = include_str!("../../../layouts/system76/launch_1/physical.json")
+            .replacen("1.5", "\"1.5\"", 1);
+        let err = match PhysicalLayout::from_str(&physical_json) {
+            Ok(_) => panic!("invalid physical.json parsed"),
+            Err(err) => err,
+        };
+        assert_eq!(err.file(), Some(Path::new("physical.json")));
+        assert_eq!(err.line(), Some(31), "{}", err);
+        match &err {
+            LayoutError::Json { path, .. } => assert_eq!(path, "rows[0][15].w", "{}", err),
+            _ => panic!("unexpected error {}", err),
+        }
+
+        let err = Layout::from_board("system76/not_a_board").err().unwrap();
+        assert!(matches!(err, LayoutError::UnknownBoard(_)));
+    }
+
+    #[test]
+    fn physical_layout_too_large() {
+        let rows = vec![vec!["A"; 37]];
+        let physical_json = serde_json::json!({
+            "name": "Test",
+            "author": "Test",
+            "pressed_color": "#202020",
+            "rows": rows,
+        });
+        match PhysicalLayout::from_str(&physical_json.to_string()) {
+            Err(LayoutError::Json { path, .. }) => assert_eq!(path, "rows[0][36]"),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("too large physical.json parsed"),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn physical_layout_from_any_str(physical_json in "\\PC*") {
+            let _ = PhysicalLayout::from_str(&physical_json);
+        }
+
+        #[test]
+        fn physical_layout_from_rows(
+            rows in prop::collection::vec(prop::collection::vec("[A-Z]{0,3}", 0..40), 0..40),
+        ) {
+            let physical_json = serde_json::json!({
+                "name": "Test",
+                "author": "Test",
+                "pressed_color": "#202020",
+                "rows": rows,
+            });
+            let res = PhysicalLayout::from_str(&physical_json.to_string());
+            let fits = rows.len() <= 36 && rows.iter().all(|row| row.len() <= 36);
+            prop_assert_eq!(res.is_ok(), fits);
+            if let Ok(physical) = res {
+                let names = physical.keys.iter().map(|k| k.logical_name()).collect::<HashSet<_>>();
+                prop_assert_eq!(names.len(), rows.iter().map(Vec::len).sum::<usize>());
+            }
+        }
+    }
+
+    #[test]
+    fn physical_layout_kle_properties() {
+        let physical = PhysicalLayout::from_str(
+            r##"{
+                "name": "Test",
+                "author": "Test",
+                "pressed_color": "#202020",
+                "rows": [
+                    [{"t": "#ff0000\n#00ff00"}, "A", {"x": 0.25, "w": 1.25, "h": 2, "x2": -0.25, "w2": 1.5, "h2": 1}, "Enter"],
+                    [{"r": 15, "rx": 4, "ry": 1, "y": -0.5}, "B", "C"],
+                    ["D"]
+                ]
+            }"##,
+        )
+        .unwrap();
+        let keys = &physical.keys;
+
+        assert_eq!(keys[0].foreground_color.unwrap().to_string(), "#ff0000");
+        assert!(keys[0].physical.rect2.is_none());
+        let enter = &keys[1].physical;
+        assert_eq!(
+            (enter.rect.x, enter.rect.w, enter.rect.h),
+            (1.25, 1.25, 2.0)
+        );
+        let rect2 = enter.rect2.unwrap();
+        assert_eq!((rect2.x, rect2.y, rect2.w, rect2.h), (1.0, 0.0, 1.5, 1.0));
+
+        let rotated = &keys[2].physical;
+        assert_eq!(rotated.rotation, 15.0);
+        assert_eq!(rotated.rotation_origin, (4.0, -1.0));
+        assert_eq!((rotated.rect.x, rotated.rect.y), (4.0, -0.5));
+        assert_eq!(keys[3].physical.rect.x, 5.0);
+        // Rows of a rotated cluster start at the rotation origin
+        assert_eq!(
+            (keys[4].physical.rect.x, keys[4].physical.rect.y),
+            (4.0, -1.5)
+        );
+        assert_eq!(keys[4].physical.rotation, 15.0);
+    }
+
+    #[test]
+    fn synthesized_physical_layout() {
+        let layout = Layout::from_board("system76/ortho_split_2u").unwrap();
+        let keys = layout.physical_keys();
+        assert_eq!(keys.len(), layout.layout.len());
+        assert_eq!(keys[0].logical_name(), "LA1");
+        assert_eq!(keys[0].physical_name, "ESC");
+        assert_eq!(keys[1].logical_name(), "LA2");
+        assert_eq!(keys[1].physical.rect.x, 1.0);
+    }
+
+    #[test]
+    fn layouts_lint() {
+        let mut failed = false;
+        for i in layouts() {
+            for issue in Layout::from_board(i).unwrap().lint() {
+                println!("{}: {}", i, issue);
+                failed = true;
+            }
+        }
+        assert!(!failed, "layouts have lint issues");
+    }
+
+    #[test]
+    fn lint_issues() {
+        let mut layout = Layout::from_board("system76/launch_1").unwrap();
+        layout.layout.remove("K00");
+        layout.layout.insert("K01".to_string(), (0, 2));
+        layout.leds.insert("KZZ".to_string(), vec![70]);
+        layout.default.map.get_mut("K03").unwrap()[0] = "NOT_A_SCANCODE".to_string();
+
+        assert_eq!(
+            layout.lint(),
+            vec![
+                LintIssue::MissingElectrical("K00".to_string()),
+                LintIssue::DuplicateElectrical {
+                    position: (0, 2),
+                    keys: vec!["K01".to_string(), "K02".to_string()],
+                },
+                LintIssue::ExtraLeds("KZZ".to_string()),
+                LintIssue::DuplicateLed {
+                    led: 70,
+                    keys: vec!["K01".to_string(), "KZZ".to_string()],
+                },
+                LintIssue::Default(crate::Issue::UnknownScancode {
+                    key: "K03".to_string(),
+                    layer: 0,
+                    scancode: "NOT_A_SCANCODE".to_string(),
+                }),
+            ]
+        );
+    }
+
     #[test]
     fn default_keys_exist() {
         let mut missing = HashSet::new();
@@ -188,6 +588,47 @@ mod tests {
         assert_eq!(missing, HashSet::new());
     }
 
+    #[test]
+    fn composite_keycodes() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        assert!(layout.is_qmk());
+        assert_eq!(layout.scancode_from_name("LT(1, SPACE)"), Some(0x412C));
+        assert_eq!(layout.scancode_to_name(0x412C).unwrap(), "LT(1, SPACE)");
+        // Names in `keymap.json` take precedence
+        assert_eq!(layout.scancode_from_name("MO(1)"), Some(0x5101));
+        assert_eq!(layout.scancode_to_name(0x5101).unwrap(), "FN");
+        assert_eq!(
+            layout.keycode_from_name("LCTL(ESC)"),
+            Some(Keycode::Mods(Mods::CTRL, 0x29))
+        );
+        assert_eq!(layout.scancode_from_name("LCTL(FN)"), None);
+
+        let layout = Layout::from_board("system76/darp6").unwrap();
+        assert!(!layout.is_qmk());
+        assert_eq!(layout.scancode_from_name("LT(1, SPACE)"), None);
+    }
+
+    #[test]
+    fn raw_scancodes() {
+        for board in &["system76/darp6", "system76/launch_1"] {
+            let layout = Layout::from_board(board).unwrap();
+            for &scancode in &[0x0000, 0x0004, 0x5C01, 0x7E00, 0xFFFF] {
+                let name = layout.scancode_name(scancode);
+                assert_eq!(layout.scancode_from_name(&name), Some(scancode));
+            }
+        }
+
+        let layout = Layout::from_board("system76/darp6").unwrap();
+        assert_eq!(layout.scancode_name(0x5C01), "0x5C01");
+        assert_eq!(layout.scancode_from_name("0x5c01"), Some(0x5C01));
+        assert_eq!(layout.scancode_from_name("0x1"), Some(0x1));
+        for name in &[
+            "0x", "0x10000", "0x00001", "0x+12", "0x-1", "0x 12", "0X12", "5C01",
+        ] {
+            assert_eq!(layout.scancode_from_name(name), None, "{}", name);
+        }
+    }
+
     #[test]
     fn qmk_has_ec_keycodes() {
         let layout_ec = Layout::from_board("system76/darp6").unwrap();
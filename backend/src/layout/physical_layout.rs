@@ -1,7 +1,19 @@
 /// Serde based deserialization for physical.json
-use serde::Deserialize;
+use serde::{
+    de::{self, Deserializer, MapAccess, Visitor},
+    Deserialize,
+};
+use std::{
+    char,
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
 
-use crate::{Rect, Rgb};
+use super::{parse_json, LayoutError};
+use crate::{KeyMap, KeyShape, Rect, Rgb, ViaKey};
+
+/// Maximum logical rows or columns; logical names have one base 36 digit for each
+pub(super) const MAX_LOGICAL_DIMENSION: usize = 36;
 
 pub(crate) struct PhysicalLayout {
     pub meta: PhysicalLayoutMeta,
@@ -9,64 +21,240 @@ pub(crate) struct PhysicalLayout {
 }
 
 impl PhysicalLayout {
-    pub fn from_str(physical_json: &str) -> Self {
-        let json = serde_json::from_str::<PhysicalLayoutJson>(physical_json).unwrap();
+    pub fn from_str(physical_json: &str) -> Result<Self, LayoutError> {
+        let json = parse_json::<PhysicalLayoutJson>("physical.json", physical_json)?;
 
         let mut keys = Vec::new();
 
         let mut row_i = 0;
         let mut col_i = 0;
         let mut physical = Rect::new(0.0, 0.0, 1.0, 1.0);
+        // Second rectangle of the next key, relative to the first
+        let (mut x2, mut y2, mut w2, mut h2) = (None, None, None, None);
+        let mut rotation = 0.0;
+        let mut rotation_origin = (0.0, 0.0);
         let mut background_color = Rgb::new(0xcc, 0xcc, 0xcc);
+        let mut foreground_color = None;
 
         for row in json.rows {
-            for i in &row.0 {
+            for (index, i) in row.0.iter().enumerate() {
                 match i {
                     PhysicalKeyEnum::Meta(meta) => {
                         debug!("Key metadata {:?}", meta);
+                        rotation = meta.r.unwrap_or(rotation);
+                        // Setting the rotation origin also moves to it
+                        if meta.rx.is_some() || meta.ry.is_some() {
+                            rotation_origin = (
+                                meta.rx.unwrap_or(rotation_origin.0),
+                                meta.ry.map_or(rotation_origin.1, |ry| -ry),
+                            );
+                            physical.x = rotation_origin.0;
+                            physical.y = rotation_origin.1;
+                        }
                         physical.x += meta.x;
                         physical.y -= meta.y;
                         physical.w = meta.w.unwrap_or(physical.w);
                         physical.h = meta.h.unwrap_or(physical.h);
+                        x2 = meta.x2.or(x2);
+                        y2 = meta.y2.or(y2);
+                        w2 = meta.w2.or(w2);
+                        h2 = meta.h2.or(h2);
                         background_color = meta.c.unwrap_or(background_color);
+                        if let Some(t) = &meta.t {
+                            // Colors of each legend, one per line
+                            foreground_color = t.lines().next().and_then(Rgb::parse);
+                        }
                     }
                     PhysicalKeyEnum::Name(name) => {
+                        if row_i >= MAX_LOGICAL_DIMENSION || col_i >= MAX_LOGICAL_DIMENSION {
+                            return Err(LayoutError::Json {
+                                file: "physical.json".into(),
+                                path: format!("rows[{}][{}]", row_i, index),
+                                err: de::Error::custom(format!(
+                                    "more than {} rows or columns of keys",
+                                    MAX_LOGICAL_DIMENSION
+                                )),
+                            });
+                        }
+
+                        let rect2 = if x2.is_some() || y2.is_some() || w2.is_some() || h2.is_some()
+                        {
+                            Some(Rect::new(
+                                physical.x + x2.unwrap_or(0.0),
+                                physical.y - y2.unwrap_or(0.0),
+                                w2.unwrap_or(physical.w),
+                                h2.unwrap_or(physical.h),
+                            ))
+                        } else {
+                            None
+                        };
+
+                        let logical = (row_i as u8, col_i as u8);
                         keys.push(PhysicalLayoutKey {
-                            logical: (row_i as u8, col_i as u8),
-                            physical,
+                            logical,
+                            logical_name: logical_name(logical),
+                            physical: KeyShape {
+                                rect: physical,
+                                rect2,
+                                rotation,
+                                rotation_origin,
+                            },
                             physical_name: name.clone(),
                             background_color,
+                            foreground_color,
                         });
 
                         physical.x += physical.w;
 
                         physical.w = 1.0;
                         physical.h = 1.0;
+                        x2 = None;
+                        y2 = None;
+                        w2 = None;
+                        h2 = None;
 
                         col_i += 1;
                     }
                 }
             }
 
-            physical.x = 0.0;
+            physical.x = rotation_origin.0;
             physical.y -= 1.0;
 
             col_i = 0;
             row_i += 1;
         }
 
-        Self {
+        Ok(Self {
             meta: json.meta,
             keys,
+        })
+    }
+
+    /// Generate a grid of 1u keys from the logical names in `layout.json`,
+    /// for layouts without a `physical.json`
+    ///
+    /// Keys are labeled with their scancode in the first layer of `default`.
+    pub fn synthesize(name: &str, layout: &HashMap<String, (u8, u8)>, default: &KeyMap) -> Self {
+        // Rows and columns sorted by logical name, like `K0A` or `LA1`
+        let mut rows = BTreeMap::<(u32, String), BTreeMap<(u32, String), String>>::new();
+        for logical_name in layout.keys() {
+            let (row, col) = split_logical_name(logical_name);
+            rows.entry(row)
+                .or_default()
+                .insert(col, logical_name.clone());
+        }
+
+        let mut keys = Vec::new();
+        for (row_i, row) in rows.values().enumerate() {
+            for (col_i, logical_name) in row.values().enumerate() {
+                let physical_name = default
+                    .map
+                    .get(logical_name)
+                    .and_then(|scancodes| scancodes.first())
+                    .cloned()
+                    .unwrap_or_default();
+                keys.push(PhysicalLayoutKey {
+                    logical: (row_i as u8, col_i as u8),
+                    logical_name: logical_name.clone(),
+                    physical: KeyShape::new(Rect::new(col_i as f64, -(row_i as f64), 1.0, 1.0)),
+                    physical_name,
+                    background_color: Rgb::new(0xcc, 0xcc, 0xcc),
+                    foreground_color: None,
+                });
+            }
+        }
+
+        Self {
+            meta: PhysicalLayoutMeta {
+                name: name.to_string(),
+                author: String::new(),
+                pressed_color: Rgb::new(0x20, 0x20, 0x20),
+            },
+            keys,
         }
     }
+
+    /// Keys of a VIA definition, named by KLE position like `physical.json`
+    /// and labeled with their matrix position
+    pub fn from_via_keys(name: &str, via_keys: &[ViaKey]) -> Self {
+        let keys = via_keys
+            .iter()
+            .map(|key| {
+                let (row, col) = key.matrix_position;
+                let logical = (key.kle_position.0 as u8, key.kle_position.1 as u8);
+                PhysicalLayoutKey {
+                    logical,
+                    logical_name: logical_name(logical),
+                    physical: KeyShape::new(key.rect),
+                    physical_name: format!("{}, {}", row, col),
+                    background_color: Rgb::new(0xcc, 0xcc, 0xcc),
+                    foreground_color: None,
+                }
+            })
+            .collect();
+
+        Self {
+            meta: PhysicalLayoutMeta {
+                name: name.to_string(),
+                author: String::new(),
+                pressed_color: Rgb::new(0x20, 0x20, 0x20),
+            },
+            keys,
+        }
+    }
+}
+
+/// Logical name (something like K01, where 0 is the row and 1 is the column)
+pub(super) fn logical_name(logical: (u8, u8)) -> String {
+    let row_char = char::from_digit(logical.0 as u32, 36).expect("Failed to convert row to char");
+    let col_char = char::from_digit(logical.1 as u32, 36).expect("Failed to convert col to char");
+    format!("K{}{}", row_char, col_char).to_uppercase()
 }
 
-pub(crate) struct PhysicalLayoutKey {
+/// Split logical name into sort keys for row and column
+///
+/// Names like `K0A` have a base 36 digit each for row and column. Otherwise,
+/// names like `LA12` have letters for the row and a number for the column.
+fn split_logical_name(name: &str) -> ((u32, String), (u32, String)) {
+    let chars = name.chars().collect::<Vec<_>>();
+    if chars.len() == 3 && chars[0] == 'K' {
+        if let (Some(row), Some(col)) = (chars[1].to_digit(36), chars[2].to_digit(36)) {
+            return ((row, String::new()), (col, String::new()));
+        }
+    }
+
+    let digits = name
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(name.len());
+    let (row, col) = name.split_at(digits);
+    match col.parse() {
+        Ok(col) => ((0, row.to_string()), (col, String::new())),
+        Err(_) => ((0, row.to_string()), (0, col.to_string())),
+    }
+}
+
+/// Key from `physical.json`
+#[derive(Clone, Debug)]
+pub struct PhysicalLayoutKey {
+    /// Logical position (row, column)
     pub logical: (u8, u8),
-    pub physical: Rect,
+    logical_name: String,
+    /// Physical position and shape
+    pub physical: KeyShape,
+    /// Physical key name (what is printed on the keycap)
     pub physical_name: String,
+    /// Background color
     pub background_color: Rgb,
+    /// Legend color, if set in `physical.json`
+    pub foreground_color: Option<Rgb>,
+}
+
+impl PhysicalLayoutKey {
+    /// Logical name (something like K01, where 0 is the row and 1 is the column)
+    pub fn logical_name(&self) -> String {
+        self.logical_name.clone()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -86,13 +274,38 @@ struct PhysicalLayoutJson {
 #[derive(Debug, Deserialize)]
 struct PhysicalRow(Vec<PhysicalKeyEnum>);
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug)]
 enum PhysicalKeyEnum {
     Name(String),
     Meta(PhysicalKeyMeta),
 }
 
+// Not `#[serde(untagged)]`, so errors in key metadata point at the field
+impl<'de> Deserialize<'de> for PhysicalKeyEnum {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PhysicalKeyVisitor;
+
+        impl<'de> Visitor<'de> for PhysicalKeyVisitor {
+            type Value = PhysicalKeyEnum;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a key name or key metadata")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(PhysicalKeyEnum::Name(value.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                PhysicalKeyMeta::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(PhysicalKeyEnum::Meta)
+            }
+        }
+
+        deserializer.deserialize_any(PhysicalKeyVisitor)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct PhysicalKeyMeta {
     #[serde(default)]
@@ -101,5 +314,13 @@ struct PhysicalKeyMeta {
     y: f64,
     w: Option<f64>,
     h: Option<f64>,
+    x2: Option<f64>,
+    y2: Option<f64>,
+    w2: Option<f64>,
+    h2: Option<f64>,
+    r: Option<f64>,
+    rx: Option<f64>,
+    ry: Option<f64>,
     c: Option<Rgb>,
+    t: Option<String>,
 }
@@ -10,24 +10,75 @@
 //! backend.refresh();
 //! # Ok::<(), String>(())
 //! ```
+//!
+//! Without the default `glib` feature, only `AsyncBackend` and `AsyncBoard`
+//! are available, for use with any executor:
+//!
+//! ```no_run
+//! use futures::{executor::block_on, prelude::*};
+//! use system76_keyboard_configurator_backend::{AsyncBackend, BackendEvent};
+//!
+//! let (backend, mut events) = AsyncBackend::new()?;
+//! block_on(async {
+//!     backend.refresh().await?;
+//!     while let Some(event) = events.next().await {
+//!         if let BackendEvent::BoardAdded(board) = event {
+//!             println!("{}", board.model());
+//!         }
+//!     }
+//!     Ok::<(), String>(())
+//! })?;
+//! # Ok::<(), String>(())
+//! ```
 
 #[macro_use]
 extern crate log;
 
+mod async_backend;
+#[cfg(feature = "glib")]
 mod backend;
+#[cfg(feature = "glib")]
 mod board;
+mod builder;
 mod color;
 mod daemon;
+#[cfg(all(target_os = "linux", feature = "dbus-service"))]
+mod dbus_service;
 mod deref_cell;
+#[cfg(feature = "glib")]
 mod key;
+mod keycode;
 mod keymap;
+#[cfg(feature = "glib")]
 mod layer;
 mod layout;
+mod matrix;
 mod mode;
+#[cfg(feature = "openrgb")]
+mod openrgb;
+#[cfg(all(feature = "openrgb", feature = "glib"))]
+mod openrgb_service;
+mod qmk;
 mod rect;
+#[cfg(any(
+    all(target_os = "linux", feature = "dbus-service"),
+    all(feature = "openrgb", feature = "glib")
+))]
+mod service;
+mod shape;
+mod translate;
+mod via;
 
-use crate::daemon::*;
+pub use crate::daemon::{BoardId, DaemonServer, EcEmulator};
+#[cfg(all(target_os = "linux", feature = "dbus-service"))]
+pub use crate::dbus_service::*;
+#[cfg(feature = "openrgb")]
+pub use crate::openrgb::*;
+#[cfg(all(feature = "openrgb", feature = "glib"))]
+pub use crate::openrgb_service::*;
 pub use crate::{
-    backend::*, board::*, color::*, deref_cell::*, key::*, keymap::*, layer::*, layout::*, mode::*,
-    rect::*,
+    async_backend::*, builder::*, color::*, deref_cell::*, keycode::*, keymap::*, layout::*,
+    matrix::*, mode::*, qmk::*, rect::*, shape::*, translate::*, via::*,
 };
+#[cfg(feature = "glib")]
+pub use crate::{backend::*, board::*, key::*, layer::*};
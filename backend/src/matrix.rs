@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// Largest number of rows or columns, since the EC and VIA report them as bytes
+pub const MAX_MATRIX_DIMENSION: usize = 255;
+
+/// Number of bytes holding a `rows` by `cols` matrix, one bit per position
+fn data_len(rows: usize, cols: usize) -> usize {
+    (rows * cols + 7) / 8
+}
+
+/// State of a keyboard's switch matrix, with a bit for each position, in row
+/// major order
+///
+/// Construction checks the data covers every position, so `get` never reads
+/// past it. It is serialized as its size and list of pressed positions.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(try_from = "MatrixData", into = "MatrixData")]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Box<[u8]>,
+}
+
+impl Matrix {
+    /// Matrix from `data` with a bit for each position, starting at the least
+    /// significant bit of the first byte
+    ///
+    /// Fails if `data` is too short. Bytes and bits past the end of the matrix
+    /// are ignored.
+    pub fn new(rows: usize, cols: usize, data: &[u8]) -> Result<Self, String> {
+        if rows > MAX_MATRIX_DIMENSION || cols > MAX_MATRIX_DIMENSION {
+            return Err(format!(
+                "Matrix of {}x{} is larger than {}x{}",
+                rows, cols, MAX_MATRIX_DIMENSION, MAX_MATRIX_DIMENSION
+            ));
+        }
+        let len = data_len(rows, cols);
+        if data.len() < len {
+            return Err(format!(
+                "Matrix of {}x{} needs {} bytes, but has {}",
+                rows,
+                cols,
+                len,
+                data.len()
+            ));
+        }
+        let mut data = Box::<[u8]>::from(&data[..len]);
+        let bits = rows * cols % 8;
+        if bits != 0 {
+            data[len - 1] &= (1 << bits) - 1;
+        }
+        Ok(Self { rows, cols, data })
+    }
+
+    /// Matrix from the reply of an EC, with the number of rows and columns in
+    /// its first two bytes
+    pub fn from_ec_data(data: &[u8]) -> Result<Self, String> {
+        match data {
+            [rows, cols, data @ ..] => Self::new(usize::from(*rows), usize::from(*cols), data),
+            _ => Err("Matrix data missing size".to_string()),
+        }
+    }
+
+    /// Matrix with `pressed` positions set, failing if one is out of range
+    pub fn from_pressed<I: IntoIterator<Item = (usize, usize)>>(
+        rows: usize,
+        cols: usize,
+        pressed: I,
+    ) -> Result<Self, String> {
+        let mut matrix = Self::new(rows, cols, &vec![0; data_len(rows, cols)])?;
+        for (row, col) in pressed {
+            if row >= rows || col >= cols {
+                return Err(format!(
+                    "Matrix position ({}, {}) is outside of {}x{}",
+                    row, col, rows, cols
+                ));
+            }
+            let i = row * cols + col;
+            matrix.data[i / 8] |= 1 << (i % 8);
+        }
+        Ok(matrix)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Whether the key at `row` and `col` is pressed, or `None` if out of range
+    pub fn get(&self, row: usize, col: usize) -> Option<bool> {
+        if row < self.rows && col < self.cols {
+            let i = row * self.cols + col;
+            Some((self.data[i / 8] & (1 << (i % 8))) != 0)
+        } else {
+            None
+        }
+    }
+
+    /// Positions of pressed keys, as `(row, col)` in row major order
+    pub fn pressed(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let cols = self.cols;
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte != 0)
+            .flat_map(|(i, byte)| {
+                (0..8)
+                    .filter(move |bit| byte & (1 << bit) != 0)
+                    .map(move |bit| i * 8 + bit)
+            })
+            .map(move |i| (i / cols, i % cols))
+    }
+
+    /// Keys released and then pressed between `self` and `new`, in row major
+    /// order
+    ///
+    /// Positions outside of one of the matrices count as not pressed in it.
+    pub fn diff<'a>(&'a self, new: &'a Self) -> impl Iterator<Item = MatrixEvent> + 'a {
+        let released = self
+            .pressed()
+            .filter(move |&(row, col)| new.get(row, col) != Some(true))
+            .map(|(row, col)| MatrixEvent::Released(row, col));
+        let pressed = new
+            .pressed()
+            .filter(move |&(row, col)| self.get(row, col) != Some(true))
+            .map(|(row, col)| MatrixEvent::Pressed(row, col));
+        released.chain(pressed)
+    }
+}
+
+/// Change of a key between two matrices, at `(row, col)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixEvent {
+    Pressed(usize, usize),
+    Released(usize, usize),
+}
+
+impl MatrixEvent {
+    pub fn position(self) -> (usize, usize) {
+        match self {
+            Self::Pressed(row, col) | Self::Released(row, col) => (row, col),
+        }
+    }
+
+    pub fn is_pressed(self) -> bool {
+        matches!(self, Self::Pressed(..))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct MatrixData {
+    rows: usize,
+    cols: usize,
+    pressed: Vec<(usize, usize)>,
+}
+
+impl TryFrom<MatrixData> for Matrix {
+    type Error = String;
+
+    fn try_from(data: MatrixData) -> Result<Self, String> {
+        Self::from_pressed(data.rows, data.cols, data.pressed)
+    }
+}
+
+impl From<Matrix> for MatrixData {
+    fn from(matrix: Matrix) -> Self {
+        Self {
+            rows: matrix.rows,
+            cols: matrix.cols,
+            pressed: matrix.pressed().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn matrix_events() {
+        let old = Matrix::from_pressed(2, 3, vec![(0, 1), (1, 2)]).unwrap();
+        let new = Matrix::from_pressed(2, 3, vec![(0, 1), (1, 0)]).unwrap();
+        assert_eq!(
+            old.diff(&new).collect::<Vec<_>>(),
+            vec![MatrixEvent::Released(1, 2), MatrixEvent::Pressed(1, 0)]
+        );
+        assert_eq!(old.diff(&old).count(), 0);
+        assert_eq!(
+            Matrix::default().diff(&old).collect::<Vec<_>>(),
+            vec![MatrixEvent::Pressed(0, 1), MatrixEvent::Pressed(1, 2)]
+        );
+    }
+
+    #[test]
+    fn matrix_invalid() {
+        assert!(Matrix::new(6, 14, &[0; 10]).is_err());
+        assert!(Matrix::new(256, 1, &[0; 32]).is_err());
+        assert!(Matrix::from_ec_data(&[6]).is_err());
+        assert!(Matrix::from_ec_data(&[6, 14, 0]).is_err());
+        assert!(Matrix::from_pressed(6, 14, vec![(6, 0)]).is_err());
+        assert!(
+            serde_json::from_str::<Matrix>(r#"{"rows":1,"cols":1,"pressed":[[0,1]]}"#).is_err()
+        );
+        assert!(serde_json::from_str::<Matrix>(r#"{"rows":1,"cols":1,"data":[255]}"#).is_err());
+    }
+
+    #[test]
+    fn matrix_serde() {
+        let matrix = Matrix::from_ec_data(&[2, 3, 0b1010_0001, 0xFF]).unwrap();
+        assert_eq!(matrix.pressed().collect::<Vec<_>>(), vec![(0, 0), (1, 2)]);
+        let json = serde_json::to_string(&matrix).unwrap();
+        assert_eq!(json, r#"{"rows":2,"cols":3,"pressed":[[0,0],[1,2]]}"#);
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap(), matrix);
+    }
+
+    proptest! {
+        #[test]
+        fn matrix_any_data(
+            rows in 0..300usize,
+            cols in 0..300usize,
+            data in prop::collection::vec(any::<u8>(), 0..64),
+        ) {
+            let valid = rows <= MAX_MATRIX_DIMENSION
+                && cols <= MAX_MATRIX_DIMENSION
+                && rows * cols <= data.len() * 8;
+            let matrix = Matrix::new(rows, cols, &data);
+            prop_assert_eq!(matrix.is_ok(), valid);
+            if let Ok(matrix) = matrix {
+                let pressed = matrix.pressed().collect::<Vec<_>>();
+                for &(row, col) in &pressed {
+                    prop_assert_eq!(matrix.get(row, col), Some(true));
+                }
+                prop_assert_eq!(Matrix::from_pressed(rows, cols, pressed), Ok(matrix.clone()));
+                prop_assert_eq!(Matrix::default().diff(&matrix).count(), matrix.pressed().count());
+                prop_assert_eq!(matrix.get(rows, 0), None);
+                prop_assert_eq!(matrix.get(0, cols), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,690 @@
+//! OpenRGB SDK server, presenting boards as keyboard devices to OpenRGB
+//!
+//! Clients connect over TCP, normally to `localhost:6742`. Each device has a
+//! single "Direct" mode, and colors set by clients are passed on as
+//! `OpenRgbUpdate`s, which `OpenRgbService` applies to boards of a `Backend`.
+//!
+//! See <https://gitlab.com/CalcProgrammer1/OpenRGB/-/wikis/OpenRGB-SDK-Documentation>
+
+use futures::channel::mpsc;
+use std::sync::mpsc as std_mpsc;
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{BoardId, Rgb};
+
+pub const OPENRGB_PORT: u16 = 6742;
+
+/// Highest protocol version supported, which adds the vendor string
+const PROTOCOL_VERSION: u32 = 1;
+/// Larger packets are rejected, instead of allocating a buffer for them
+const MAX_PACKET_SIZE: u32 = 1 << 20;
+const HEADER_SIZE: usize = 16;
+/// Clients that don't read what is written to them for this long are dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+const REQUEST_CONTROLLER_COUNT: u32 = 0;
+const REQUEST_CONTROLLER_DATA: u32 = 1;
+const REQUEST_PROTOCOL_VERSION: u32 = 40;
+const SET_CLIENT_NAME: u32 = 50;
+const DEVICE_LIST_UPDATED: u32 = 100;
+const RGBCONTROLLER_UPDATELEDS: u32 = 1050;
+const RGBCONTROLLER_UPDATEZONELEDS: u32 = 1051;
+const RGBCONTROLLER_UPDATESINGLELED: u32 = 1052;
+const RGBCONTROLLER_SETCUSTOMMODE: u32 = 1100;
+const RGBCONTROLLER_UPDATEMODE: u32 = 1101;
+
+const DEVICE_TYPE_KEYBOARD: u32 = 5;
+const ZONE_TYPE_SINGLE: u32 = 0;
+const ZONE_TYPE_MATRIX: u32 = 2;
+const MODE_FLAG_HAS_PER_LED_COLOR: u32 = 1 << 5;
+const MODE_COLORS_PER_LED: u32 = 1;
+/// Matrix map entry for a position without an LED
+const NO_LED: u32 = 0xFFFF_FFFF;
+
+/// Positions of the LEDs of a zone, in rows
+#[derive(Clone, Debug)]
+pub struct OpenRgbMatrix {
+    pub width: usize,
+    pub height: usize,
+    /// LED index within the zone at each position, row by row
+    pub map: Vec<Option<usize>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpenRgbZone {
+    pub name: String,
+    /// Number of LEDs, which follow those of the previous zones
+    pub leds: usize,
+    /// Layout of a keyboard zone. Zones without one are a single light.
+    pub matrix: Option<OpenRgbMatrix>,
+}
+
+/// Device presented to clients
+#[derive(Clone, Debug)]
+pub struct OpenRgbDevice {
+    pub board: BoardId,
+    pub name: String,
+    pub vendor: String,
+    pub description: String,
+    pub serial: String,
+    pub zones: Vec<OpenRgbZone>,
+    /// Name of each LED, in zone order
+    pub leds: Vec<String>,
+    /// Current color of each LED
+    pub colors: Vec<Rgb>,
+}
+
+/// Change requested by a client
+#[derive(Clone, Debug)]
+pub enum OpenRgbUpdate {
+    /// New colors, by LED index
+    Colors {
+        board: BoardId,
+        colors: Vec<(usize, Rgb)>,
+    },
+    /// The "Direct" mode was selected, so per-LED colors should be shown
+    Direct(BoardId),
+}
+
+struct Shared {
+    devices: Vec<OpenRgbDevice>,
+    /// Connected clients, for sending `DEVICE_LIST_UPDATED`
+    clients: Vec<Client>,
+}
+
+/// Connected client, with packets written in order by a thread of its own, so
+/// a client that doesn't read them doesn't hold up others
+struct Client {
+    peer: SocketAddr,
+    stream: TcpStream,
+    packets: std_mpsc::Sender<Vec<u8>>,
+}
+
+/// OpenRGB SDK server, handling clients on other threads
+///
+/// The server stops when dropped.
+pub struct OpenRgbServer {
+    shared: Arc<Mutex<Shared>>,
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl OpenRgbServer {
+    /// Listen on `addr`, such as `("127.0.0.1", OPENRGB_PORT)`
+    ///
+    /// Updates requested by clients are sent to the returned receiver.
+    pub fn new<A: ToSocketAddrs>(
+        addr: A,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<OpenRgbUpdate>), String> {
+        let listener =
+            TcpListener::bind(addr).map_err(|err| format!("Failed to listen: {}", err))?;
+        let addr = listener.local_addr().map_err(|err| err.to_string())?;
+        let shared = Arc::new(Mutex::new(Shared {
+            devices: Vec::new(),
+            clients: Vec::new(),
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::unbounded();
+
+        let thread_shared = shared.clone();
+        let thread_stopped = stopped.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        error!("Failed to accept OpenRGB client: {}", err);
+                        continue;
+                    }
+                };
+                if let Err(err) = add_client(stream, &thread_shared, &sender) {
+                    error!("Failed to add OpenRGB client: {}", err);
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                shared,
+                addr,
+                stopped,
+            },
+            receiver,
+        ))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replace the devices, and tell clients to request them again
+    pub fn set_devices(&self, devices: Vec<OpenRgbDevice>) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.devices = devices;
+        for client in &shared.clients {
+            // Errors are handled by the thread reading from the client
+            let _ = client.packets.send(packet(0, DEVICE_LIST_UPDATED, &[]));
+        }
+    }
+
+    /// Update the colors of a device, after they changed elsewhere
+    ///
+    /// Clients see them the next time they request the device.
+    pub fn set_colors(&self, board: BoardId, colors: Vec<Rgb>) {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(device) = shared.devices.iter_mut().find(|i| i.board == board) {
+            if device.colors.len() == colors.len() {
+                device.colors = colors;
+            }
+        }
+    }
+}
+
+impl Drop for OpenRgbServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the thread waiting for connections
+        let _ = TcpStream::connect(self.addr);
+        for client in &self.shared.lock().unwrap().clients {
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn add_client(
+    stream: TcpStream,
+    shared: &Arc<Mutex<Shared>>,
+    sender: &mpsc::UnboundedSender<OpenRgbUpdate>,
+) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    info!("OpenRGB client connected from {}", peer);
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let (packets, packet_receiver) = std_mpsc::channel::<Vec<u8>>();
+    let mut write_stream = stream.try_clone()?;
+    shared.lock().unwrap().clients.push(Client {
+        peer,
+        stream: stream.try_clone()?,
+        packets: packets.clone(),
+    });
+
+    // Ends once the client is removed and the reading thread is done
+    thread::spawn(move || {
+        for packet in packet_receiver {
+            if let Err(err) = write_stream.write_all(&packet) {
+                error!("Failed to write to OpenRGB client {}: {}", peer, err);
+                let _ = write_stream.shutdown(Shutdown::Both);
+                break;
+            }
+        }
+    });
+
+    let shared = shared.clone();
+    let sender = sender.clone();
+    thread::spawn(move || {
+        if let Err(err) = handle_client(stream, &packets, &shared, &sender) {
+            if err.kind() != io::ErrorKind::UnexpectedEof {
+                error!("OpenRGB client {}: {}", peer, err);
+            }
+        }
+        info!("OpenRGB client {} disconnected", peer);
+        let mut shared = shared.lock().unwrap();
+        shared.clients.retain(|client| client.peer != peer);
+    });
+    Ok(())
+}
+
+fn handle_client(
+    mut stream: TcpStream,
+    packets: &std_mpsc::Sender<Vec<u8>>,
+    shared: &Mutex<Shared>,
+    sender: &mpsc::UnboundedSender<OpenRgbUpdate>,
+) -> io::Result<()> {
+    let mut protocol = 0;
+    loop {
+        let mut header = [0; HEADER_SIZE];
+        stream.read_exact(&mut header)?;
+        if &header[..4] != b"ORGB" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid magic"));
+        }
+        let mut reader = Reader(&header[4..]);
+        // The header is long enough for these
+        let device = reader.u32().unwrap();
+        let id = reader.u32().unwrap();
+        let size = reader.u32().unwrap();
+        if size > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("packet of {} bytes is too large", size),
+            ));
+        }
+        let mut data = vec![0; size as usize];
+        stream.read_exact(&mut data)?;
+
+        let reply = handle_packet(
+            &mut shared.lock().unwrap().devices,
+            &mut protocol,
+            device,
+            id,
+            &data,
+            sender,
+        );
+        if let Some(reply) = reply {
+            // Only fails if the writing thread stopped, after shutting down
+            // the stream
+            let _ = packets.send(packet(device, id, &reply));
+        }
+    }
+}
+
+/// Handle a request, returning the data of the reply if it has one
+fn handle_packet(
+    devices: &mut [OpenRgbDevice],
+    protocol: &mut u32,
+    device: u32,
+    id: u32,
+    data: &[u8],
+    sender: &mpsc::UnboundedSender<OpenRgbUpdate>,
+) -> Option<Vec<u8>> {
+    let mut reader = Reader(data);
+    match id {
+        REQUEST_CONTROLLER_COUNT => Some((devices.len() as u32).to_le_bytes().to_vec()),
+        REQUEST_PROTOCOL_VERSION => {
+            *protocol = reader.u32().unwrap_or(0).min(PROTOCOL_VERSION);
+            Some(PROTOCOL_VERSION.to_le_bytes().to_vec())
+        }
+        REQUEST_CONTROLLER_DATA => {
+            let protocol = reader.u32().unwrap_or(*protocol).min(PROTOCOL_VERSION);
+            let device = devices.get(device as usize)?;
+            Some(controller_data(device, protocol))
+        }
+        SET_CLIENT_NAME => {
+            let name = data.split(|x| *x == 0).next().unwrap_or_default();
+            info!("OpenRGB client name: {}", String::from_utf8_lossy(name));
+            None
+        }
+        RGBCONTROLLER_UPDATELEDS => {
+            let device = devices.get_mut(device as usize)?;
+            reader.u32()?;
+            let colors = reader.colors()?;
+            set_colors(device, 0, colors, sender);
+            None
+        }
+        RGBCONTROLLER_UPDATEZONELEDS => {
+            let device = devices.get_mut(device as usize)?;
+            reader.u32()?;
+            let zone = reader.u32()? as usize;
+            let colors = reader.colors()?;
+            if zone >= device.zones.len() {
+                warn!("OpenRGB client set colors of invalid zone {}", zone);
+                return None;
+            }
+            let start = device.zones[..zone].iter().map(|zone| zone.leds).sum();
+            let colors = colors.into_iter().take(device.zones[zone].leds).collect();
+            set_colors(device, start, colors, sender);
+            None
+        }
+        RGBCONTROLLER_UPDATESINGLELED => {
+            let device = devices.get_mut(device as usize)?;
+            let led = usize::try_from(reader.u32()?).ok()?;
+            let color = reader.rgb()?;
+            set_colors(device, led, vec![color], sender);
+            None
+        }
+        RGBCONTROLLER_SETCUSTOMMODE | RGBCONTROLLER_UPDATEMODE => {
+            // "Direct" is the only mode
+            let device = devices.get(device as usize)?;
+            let _ = sender.unbounded_send(OpenRgbUpdate::Direct(device.board));
+            None
+        }
+        _ => {
+            debug!("Ignoring OpenRGB packet {}", id);
+            None
+        }
+    }
+}
+
+/// Set colors of LEDs starting at `start`, ignoring those past the end
+fn set_colors(
+    device: &mut OpenRgbDevice,
+    start: usize,
+    colors: Vec<Rgb>,
+    sender: &mpsc::UnboundedSender<OpenRgbUpdate>,
+) {
+    let colors = colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| (start + i, color))
+        .filter(|(i, _)| *i < device.colors.len())
+        .collect::<Vec<_>>();
+    for (i, color) in &colors {
+        device.colors[*i] = *color;
+    }
+    if !colors.is_empty() {
+        let _ = sender.unbounded_send(OpenRgbUpdate::Colors {
+            board: device.board,
+            colors,
+        });
+    }
+}
+
+fn packet(device: u32, id: u32, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_SIZE + data.len());
+    packet.extend_from_slice(b"ORGB");
+    put_u32(&mut packet, device);
+    put_u32(&mut packet, id);
+    put_u32(&mut packet, data.len() as u32);
+    packet.extend_from_slice(data);
+    packet
+}
+
+/// Description of a device, as in `RGBController::GetDeviceDescription`
+fn controller_data(device: &OpenRgbDevice, protocol: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u32(&mut data, 0); // Size, set at the end
+    put_u32(&mut data, DEVICE_TYPE_KEYBOARD);
+    put_string(&mut data, &device.name);
+    if protocol >= 1 {
+        put_string(&mut data, &device.vendor);
+    }
+    put_string(&mut data, &device.description);
+    put_string(&mut data, ""); // Version
+    put_string(&mut data, &device.serial);
+    put_string(&mut data, ""); // Location
+
+    put_u16(&mut data, 1);
+    put_u32(&mut data, 0); // Active mode
+    put_string(&mut data, "Direct");
+    put_u32(&mut data, 0); // Value
+    put_u32(&mut data, MODE_FLAG_HAS_PER_LED_COLOR);
+    for _ in 0..6 {
+        // Speed and color limits, speed, and direction
+        put_u32(&mut data, 0);
+    }
+    put_u32(&mut data, MODE_COLORS_PER_LED);
+    put_u16(&mut data, 0); // Mode colors
+
+    put_u16(&mut data, device.zones.len() as u16);
+    for zone in &device.zones {
+        put_string(&mut data, &zone.name);
+        let zone_type = match zone.matrix {
+            Some(_) => ZONE_TYPE_MATRIX,
+            None => ZONE_TYPE_SINGLE,
+        };
+        put_u32(&mut data, zone_type);
+        for _ in 0..3 {
+            // Minimum, maximum, and current number of LEDs
+            put_u32(&mut data, zone.leds as u32);
+        }
+        match &zone.matrix {
+            Some(matrix) => {
+                put_u16(&mut data, (8 + matrix.map.len() * 4) as u16);
+                put_u32(&mut data, matrix.height as u32);
+                put_u32(&mut data, matrix.width as u32);
+                for led in &matrix.map {
+                    put_u32(&mut data, led.map_or(NO_LED, |led| led as u32));
+                }
+            }
+            None => put_u16(&mut data, 0),
+        }
+    }
+
+    put_u16(&mut data, device.leds.len() as u16);
+    for (i, led) in device.leds.iter().enumerate() {
+        put_string(&mut data, led);
+        put_u32(&mut data, i as u32);
+    }
+
+    put_u16(&mut data, device.colors.len() as u16);
+    for color in &device.colors {
+        data.extend_from_slice(&[color.r, color.g, color.b, 0]);
+    }
+
+    let size = data.len() as u32;
+    data[..4].copy_from_slice(&size.to_le_bytes());
+    data
+}
+
+fn put_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Length, including a null terminator, and the string
+fn put_string(data: &mut Vec<u8>, value: &str) {
+    put_u16(data, value.len() as u16 + 1);
+    data.extend_from_slice(value.as_bytes());
+    data.push(0);
+}
+
+/// Reads little endian values, returning `None` past the end
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn rgb(&mut self) -> Option<Rgb> {
+        let bytes = self.bytes(4)?;
+        Some(Rgb::new(bytes[0], bytes[1], bytes[2]))
+    }
+
+    /// Count, then colors
+    fn colors(&mut self) -> Option<Vec<Rgb>> {
+        let count = self.u16()?;
+        (0..count).map(|_| self.rgb()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::{Daemon, DaemonDummy};
+    use futures::executor::block_on_stream;
+    use std::time::Instant;
+
+    fn device(board: BoardId) -> OpenRgbDevice {
+        OpenRgbDevice {
+            board,
+            name: "Launch".to_string(),
+            vendor: "System76".to_string(),
+            description: "system76/launch_1".to_string(),
+            serial: String::new(),
+            zones: vec![OpenRgbZone {
+                name: "Keyboard".to_string(),
+                leds: 2,
+                matrix: Some(OpenRgbMatrix {
+                    width: 2,
+                    height: 2,
+                    map: vec![Some(0), None, None, Some(1)],
+                }),
+            }],
+            leds: vec!["Key: Esc".to_string(), "Key: Enter".to_string()],
+            colors: vec![Rgb::new(0, 0, 0); 2],
+        }
+    }
+
+    struct Client(TcpStream);
+
+    impl Client {
+        fn send(&mut self, device: u32, id: u32, data: &[u8]) {
+            self.0.write_all(&packet(device, id, data)).unwrap();
+        }
+
+        fn recv(&mut self) -> (u32, u32, Vec<u8>) {
+            let mut header = [0; HEADER_SIZE];
+            self.0.read_exact(&mut header).unwrap();
+            assert_eq!(&header[..4], b"ORGB");
+            let mut reader = Reader(&header[4..]);
+            let device = reader.u32().unwrap();
+            let id = reader.u32().unwrap();
+            let mut data = vec![0; reader.u32().unwrap() as usize];
+            self.0.read_exact(&mut data).unwrap();
+            (device, id, data)
+        }
+    }
+
+    fn string(reader: &mut Reader) -> String {
+        let len = reader.u16().unwrap() as usize;
+        let bytes = reader.bytes(len).unwrap();
+        assert_eq!(bytes[len - 1], 0);
+        String::from_utf8(bytes[..len - 1].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn local_client() {
+        let (server, receiver) = OpenRgbServer::new(("127.0.0.1", 0)).unwrap();
+        let board = DaemonDummy::new(vec!["system76/launch_1".to_string()])
+            .boards()
+            .unwrap()[0];
+        server.set_devices(vec![device(board)]);
+        let mut updates = block_on_stream(receiver);
+        let mut client = Client(TcpStream::connect(server.local_addr()).unwrap());
+
+        client.send(0, REQUEST_PROTOCOL_VERSION, &3u32.to_le_bytes());
+        let (_, id, data) = client.recv();
+        assert_eq!(id, REQUEST_PROTOCOL_VERSION);
+        assert_eq!(Reader(&data).u32(), Some(PROTOCOL_VERSION));
+
+        client.send(0, REQUEST_CONTROLLER_COUNT, &[]);
+        assert_eq!(Reader(&client.recv().2).u32(), Some(1));
+
+        client.send(0, REQUEST_CONTROLLER_DATA, &1u32.to_le_bytes());
+        let (device, id, data) = client.recv();
+        assert_eq!((device, id), (0, REQUEST_CONTROLLER_DATA));
+        let mut reader = Reader(&data);
+        assert_eq!(reader.u32(), Some(data.len() as u32));
+        assert_eq!(reader.u32(), Some(DEVICE_TYPE_KEYBOARD));
+        assert_eq!(string(&mut reader), "Launch");
+        assert_eq!(string(&mut reader), "System76");
+        assert_eq!(string(&mut reader), "system76/launch_1");
+        for _ in 0..3 {
+            string(&mut reader);
+        }
+        assert_eq!(reader.u16(), Some(1));
+        assert_eq!(reader.u32(), Some(0));
+        assert_eq!(string(&mut reader), "Direct");
+        reader.bytes(4 * 9 + 2).unwrap();
+        assert_eq!(reader.u16(), Some(1));
+        assert_eq!(string(&mut reader), "Keyboard");
+        assert_eq!(reader.u32(), Some(ZONE_TYPE_MATRIX));
+        reader.bytes(4 * 3).unwrap();
+        assert_eq!(reader.u16(), Some(8 + 4 * 4));
+        assert_eq!((reader.u32(), reader.u32()), (Some(2), Some(2)));
+        let map = (0..4).map(|_| reader.u32().unwrap()).collect::<Vec<_>>();
+        assert_eq!(map, [0, NO_LED, NO_LED, 1]);
+        assert_eq!(reader.u16(), Some(2));
+        assert_eq!(string(&mut reader), "Key: Esc");
+        reader.bytes(4).unwrap();
+        assert_eq!(string(&mut reader), "Key: Enter");
+        reader.bytes(4).unwrap();
+        assert_eq!(reader.colors().map(|colors| colors.len()), Some(2));
+        assert!(reader.0.is_empty());
+
+        // Colors are forwarded, and seen by later requests
+        let mut data = Vec::new();
+        put_u32(&mut data, 0);
+        put_u16(&mut data, 2);
+        data.extend_from_slice(&[255, 0, 0, 0, 0, 0, 255, 0]);
+        client.send(0, RGBCONTROLLER_UPDATELEDS, &data);
+        match updates.next() {
+            Some(OpenRgbUpdate::Colors {
+                board: update_board,
+                colors,
+            }) => {
+                assert_eq!(update_board, board);
+                let colors = colors
+                    .iter()
+                    .map(|(i, c)| (*i, c.r, c.g, c.b))
+                    .collect::<Vec<_>>();
+                assert_eq!(colors, [(0, 255, 0, 0), (1, 0, 0, 255)]);
+            }
+            update => panic!("unexpected update {:?}", update),
+        }
+
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0, 255, 0, 0]);
+        client.send(0, RGBCONTROLLER_UPDATESINGLELED, &data);
+        match updates.next() {
+            Some(OpenRgbUpdate::Colors { colors, .. }) => {
+                assert_eq!(colors.len(), 1);
+                assert_eq!((colors[0].0, colors[0].1.g), (1, 255));
+            }
+            update => panic!("unexpected update {:?}", update),
+        }
+
+        client.send(0, RGBCONTROLLER_SETCUSTOMMODE, &[]);
+        match updates.next() {
+            Some(OpenRgbUpdate::Direct(update_board)) => assert_eq!(update_board, board),
+            update => panic!("unexpected update {:?}", update),
+        }
+
+        // Clients are told when devices change
+        server.set_devices(Vec::new());
+        assert_eq!(client.recv().1, DEVICE_LIST_UPDATED);
+        client.send(0, REQUEST_CONTROLLER_COUNT, &[]);
+        assert_eq!(Reader(&client.recv().2).u32(), Some(0));
+
+        // Clients are disconnected when the server stops
+        drop(server);
+        let mut buf = [0; 1];
+        assert_eq!(client.0.read(&mut buf).unwrap_or(0), 0);
+        assert!(updates.next().is_none());
+    }
+
+    #[test]
+    fn slow_client() {
+        let (server, _receiver) = OpenRgbServer::new(("127.0.0.1", 0)).unwrap();
+        let board = DaemonDummy::new(vec!["system76/launch_1".to_string()])
+            .boards()
+            .unwrap()[0];
+        server.set_devices(vec![device(board)]);
+
+        // A client that stops reading doesn't hold up others, once writing
+        // to it blocks
+        let mut slow = TcpStream::connect(server.local_addr()).unwrap();
+        thread::spawn(move || {
+            let request = packet(0, REQUEST_CONTROLLER_DATA, &[]);
+            while slow.write_all(&request).is_ok() {}
+        });
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        let mut client = Client(TcpStream::connect(server.local_addr()).unwrap());
+        client.send(0, REQUEST_CONTROLLER_COUNT, &[]);
+        assert_eq!(Reader(&client.recv().2).u32(), Some(1));
+        server.set_devices(Vec::new());
+        assert_eq!(client.recv().1, DEVICE_LIST_UPDATED);
+        assert!(start.elapsed() < WRITE_TIMEOUT);
+    }
+}
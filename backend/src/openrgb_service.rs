@@ -0,0 +1,310 @@
+use futures::prelude::*;
+use glib::clone;
+use std::{
+    collections::HashMap,
+    net::ToSocketAddrs,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    service::ServiceHandlers, Backend, Board, BoardId, Hs, Key, Mode, OpenRgbDevice, OpenRgbMatrix,
+    OpenRgbServer, OpenRgbUpdate, OpenRgbZone, Rgb,
+};
+
+/// Keys with LEDs, in the order of the LEDs of their OpenRGB device
+fn led_keys(board: &Board) -> Vec<&Key> {
+    board
+        .keys()
+        .iter()
+        .filter(|key| !key.leds.is_empty())
+        .collect()
+}
+
+fn key_color(key: &Key) -> Rgb {
+    key.color().map_or(Rgb::new(0, 0, 0), Hs::to_rgb)
+}
+
+fn colors(board: &Board) -> Vec<Rgb> {
+    let keys = led_keys(board);
+    if keys.is_empty() {
+        board
+            .layers()
+            .iter()
+            .take(1)
+            .map(|layer| layer.color().to_rgb())
+            .collect()
+    } else {
+        keys.into_iter().map(key_color).collect()
+    }
+}
+
+/// Device for a board, with a matrix zone of keys with LEDs in `leds.json`,
+/// or a single zone for boards without per-key LEDs
+fn device(board: &Board) -> Option<OpenRgbDevice> {
+    let keys = led_keys(board);
+    let (zone, leds) = if !keys.is_empty() {
+        let height = keys.iter().map(|key| key.logical.0).max().unwrap() as usize + 1;
+        let width = keys.iter().map(|key| key.logical.1).max().unwrap() as usize + 1;
+        let mut map = vec![None; width * height];
+        for (i, key) in keys.iter().enumerate() {
+            map[key.logical.0 as usize * width + key.logical.1 as usize] = Some(i);
+        }
+        let zone = OpenRgbZone {
+            name: "Keyboard".to_string(),
+            leds: keys.len(),
+            matrix: Some(OpenRgbMatrix { width, height, map }),
+        };
+        let leds = keys
+            .iter()
+            .map(|key| format!("Key: {}", key.physical_name.replace('\n', " ")))
+            .collect();
+        (zone, leds)
+    } else if !board.layers().is_empty() {
+        let zone = OpenRgbZone {
+            name: "Backlight".to_string(),
+            leds: 1,
+            matrix: None,
+        };
+        (zone, vec!["Backlight".to_string()])
+    } else {
+        return None;
+    };
+
+    let vendor = if board.model().starts_with("system76/") {
+        "System76"
+    } else {
+        ""
+    };
+    Some(OpenRgbDevice {
+        board: board.board(),
+        name: board.layout().meta.display_name.clone(),
+        vendor: vendor.to_string(),
+        description: board.model().to_string(),
+        serial: String::new(),
+        zones: vec![zone],
+        leds,
+        colors: colors(board),
+    })
+}
+
+fn find_board(backend: &Backend, id: BoardId) -> Result<Board, String> {
+    backend
+        .boards()
+        .into_iter()
+        .find(|board| board.board() == id)
+        .ok_or_else(|| "No board".to_string())
+}
+
+async fn set_colors(board: &Board, colors: HashMap<usize, Rgb>) -> Result<(), String> {
+    let keys = led_keys(board);
+    if keys.is_empty() {
+        // Single zone, shown by every layer
+        if let Some(rgb) = colors.get(&0) {
+            for layer in board.layers() {
+                layer.set_color(rgb.to_hs_lossy()).await?;
+            }
+        }
+        return Ok(());
+    }
+    for (i, rgb) in colors {
+        let key = match keys.get(i) {
+            Some(key) => key,
+            None => continue,
+        };
+        let color = match rgb {
+            Rgb { r: 0, g: 0, b: 0 } => None,
+            _ => Some(rgb.to_hs_lossy()),
+        };
+        if key.color() != color {
+            key.set_color(color).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Show per-key colors on every layer
+async fn set_direct(board: &Board) -> Result<(), String> {
+    if led_keys(board).is_empty() || !board.layout().meta.has_mode {
+        return Ok(());
+    }
+    let per_key = Mode::from_id("PER_KEY").unwrap();
+    for layer in board.layers() {
+        if let Some((mode, speed)) = layer.mode() {
+            if !mode.is_per_key() {
+                layer.set_mode(per_key, speed).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct ServiceInner {
+    backend: Backend,
+    server: OpenRgbServer,
+}
+
+impl ServiceInner {
+    fn update_devices(&self, removed: Option<&Board>) {
+        let devices = self
+            .backend
+            .boards()
+            .iter()
+            .filter(|board| Some(*board) != removed)
+            .filter_map(device)
+            .collect();
+        self.server.set_devices(devices);
+    }
+
+    /// Apply updates from clients, merging those that arrived since the last
+    async fn handle_updates(
+        weak: Weak<Self>,
+        mut updates: impl Stream<Item = OpenRgbUpdate> + Unpin,
+    ) {
+        let mut pending = Vec::new();
+        while let Some(update) = updates.next().await {
+            pending.push(update);
+            while let Some(Some(update)) = updates.next().now_or_never() {
+                pending.push(update);
+            }
+
+            let inner = match weak.upgrade() {
+                Some(inner) => inner,
+                None => break,
+            };
+            let mut colors = HashMap::<BoardId, HashMap<usize, Rgb>>::new();
+            let mut direct = Vec::new();
+            for update in pending.drain(..) {
+                match update {
+                    OpenRgbUpdate::Colors { board, colors: c } => {
+                        colors.entry(board).or_default().extend(c);
+                    }
+                    OpenRgbUpdate::Direct(board) => direct.push(board),
+                }
+            }
+            for board in direct {
+                let res = match find_board(&inner.backend, board) {
+                    Ok(board) => set_direct(&board).await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = res {
+                    error!("Failed to set mode from OpenRGB: {}", err);
+                }
+            }
+            for (board, colors) in colors {
+                let res = match find_board(&inner.backend, board) {
+                    Ok(board) => set_colors(&board, colors).await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = res {
+                    error!("Failed to set colors from OpenRGB: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Service presenting the boards of a `Backend` to OpenRGB
+///
+/// Updates from clients are applied on the default main context, which must be
+/// running. The service stops when dropped.
+pub struct OpenRgbService {
+    inner: Rc<ServiceInner>,
+    _handlers: ServiceHandlers,
+}
+
+impl OpenRgbService {
+    /// Listen on `addr`, normally `("127.0.0.1", OPENRGB_PORT)`
+    pub fn new<A: ToSocketAddrs>(backend: &Backend, addr: A) -> Result<Self, String> {
+        let (server, updates) = OpenRgbServer::new(addr)?;
+        let inner = Rc::new(ServiceInner {
+            backend: backend.clone(),
+            server,
+        });
+
+        let weak = Rc::downgrade(&inner);
+        let handlers = ServiceHandlers::new(
+            backend,
+            clone!(@strong weak => move |board| {
+                let handler_id = board.connect_leds_changed(
+                    clone!(@strong weak, @weak board => move || {
+                        if let Some(inner) = weak.upgrade() {
+                            inner.server.set_colors(board.board(), colors(&board));
+                        }
+                    }),
+                );
+                if let Some(inner) = weak.upgrade() {
+                    inner.update_devices(None);
+                }
+                vec![handler_id]
+            }),
+            clone!(@strong weak => move |board| {
+                if let Some(inner) = weak.upgrade() {
+                    inner.update_devices(Some(board));
+                }
+            }),
+        );
+
+        glib::MainContext::default().spawn_local(ServiceInner::handle_updates(weak, updates));
+
+        Ok(Self {
+            inner,
+            _handlers: handlers,
+        })
+    }
+
+    pub fn server(&self) -> &OpenRgbServer {
+        &self.inner.server
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::{Duration, Instant},
+    };
+
+    fn packet(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut packet = b"ORGB".to_vec();
+        packet.extend_from_slice(&0u32.to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    #[test]
+    fn backlight_client() {
+        let context = glib::MainContext::default();
+        let backend = Backend::new_dummy(vec!["system76/darp6".to_string()]).unwrap();
+        context.block_on(backend.refresh_async()).unwrap();
+        while context.pending() {
+            context.iteration(false);
+        }
+        let service = OpenRgbService::new(&backend, ("127.0.0.1", 0)).unwrap();
+        let mut client = TcpStream::connect(service.server().local_addr()).unwrap();
+
+        // RGBCONTROLLER_UPDATELEDS, setting the single LED of the backlight zone
+        let mut data = 0u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&[255, 0, 0, 0]);
+        client.write_all(&packet(1050, &data)).unwrap();
+
+        let layer = backend.boards()[0].layers()[0].clone();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while layer.color().to_rgb() != Rgb::new(255, 0, 0) {
+            assert!(Instant::now() < deadline, "color not set");
+            context.iteration(false);
+        }
+
+        // Clients are disconnected when the service is dropped
+        drop(service);
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use crate::{KeyMap, Keycode, Layout};
+
+/// QMK keycode names (without `KC_` prefix) that differ from ours, and short
+/// aliases QMK Configurator uses, mapped to the full QMK names; shared with
+/// `layouts.py`
+#[derive(Deserialize)]
+struct QmkJson {
+    mapping: HashMap<String, String>,
+    aliases: HashMap<String, String>,
+}
+
+static QMK_JSON: Lazy<QmkJson> = Lazy::new(|| {
+    let qmk_json = include_str!("../../layouts/qmk.json");
+    serde_json::from_str(qmk_json).unwrap()
+});
+static FROM_QMK: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
+    QMK_JSON
+        .mapping
+        .iter()
+        .map(|(qmk, ours)| (qmk.as_str(), ours.as_str()))
+        .collect()
+});
+static TO_QMK: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
+    QMK_JSON
+        .mapping
+        .iter()
+        .map(|(qmk, ours)| (ours.as_str(), qmk.as_str()))
+        .collect()
+});
+static ALIASES: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
+    QMK_JSON
+        .aliases
+        .iter()
+        .map(|(alias, qmk)| (alias.as_str(), qmk.as_str()))
+        .collect()
+});
+
+/// Number of layers with `LAYER_ACCESS_` and `LAYER_TOGGLE_` names
+const NAMED_LAYERS: u8 = 4;
+
+/// Convert a QMK keycode name, like `KC_ESC` or `MO(1)`, to our scancode name
+pub fn scancode_name_from_qmk(qmk: &str) -> Option<String> {
+    let qmk = qmk.trim();
+    match qmk {
+        "XXXXXXX" => return Some("NONE".to_string()),
+        "_______" => return Some("ROLL_OVER".to_string()),
+        "RESET" | "QK_BOOT" => return Some("RESET".to_string()),
+        _ => {}
+    }
+
+    // Raw keycodes are written the same way
+    if qmk.starts_with("0x") {
+        return Some(qmk.to_string());
+    }
+
+    if qmk.ends_with(')') {
+        let open = qmk.find('(')?;
+        let layer = qmk[open + 1..qmk.len() - 1].trim().parse::<u8>();
+        // Only the first layers have names in `keymap.json`
+        return match (&qmk[..open], layer) {
+            ("MO", Ok(1)) => Some("FN".to_string()),
+            ("MO", Ok(layer)) if layer < NAMED_LAYERS => {
+                Some(format!("LAYER_ACCESS_{}", layer + 1))
+            }
+            ("TG", Ok(layer)) if layer < NAMED_LAYERS => {
+                Some(format!("LAYER_TOGGLE_{}", layer + 1))
+            }
+            _ => Keycode::map_base_names(qmk, scancode_name_from_qmk),
+        };
+    }
+
+    if qmk.starts_with("RGB_") {
+        let qmk = ALIASES.get(qmk).unwrap_or(&qmk);
+        return Some(FROM_QMK.get(qmk).unwrap_or(qmk).to_string());
+    }
+
+    if !qmk.starts_with("KC_") {
+        return None;
+    }
+    let name = &qmk[3..];
+    let name = ALIASES.get(name).unwrap_or(&name);
+    Some(FROM_QMK.get(name).unwrap_or(name).to_string())
+}
+
+/// Convert our scancode name to a QMK keycode name
+pub fn scancode_name_to_qmk(name: &str) -> String {
+    if name == "RESET" || name.starts_with("0x") {
+        return name.to_string();
+    } else if name == "FN" {
+        return "MO(1)".to_string();
+    } else if name.starts_with("LAYER_ACCESS_") || name.starts_with("LAYER_TOGGLE_") {
+        let function = if name.starts_with("LAYER_ACCESS_") {
+            "MO"
+        } else {
+            "TG"
+        };
+        if let Ok(layer) = name[13..].parse::<u8>() {
+            return format!("{}({})", function, layer.saturating_sub(1));
+        }
+    }
+
+    if name.ends_with(')') {
+        if let Some(qmk) = Keycode::map_base_names(name, |base| Some(scancode_name_to_qmk(base))) {
+            return qmk;
+        }
+    }
+
+    let qmk = TO_QMK.get(name).unwrap_or(&name);
+    if qmk.starts_with("RGB_") {
+        qmk.to_string()
+    } else {
+        format!("KC_{}", qmk)
+    }
+}
+
+/// Keymap in the `keymap.json` format of QMK Configurator
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QmkKeyMap {
+    #[serde(default = "qmk_version_default")]
+    pub version: u8,
+    /// Keyboard name, like `system76/launch_1`
+    pub keyboard: String,
+    /// Name of the keymap
+    pub keymap: String,
+    /// Name of the `LAYOUT` macro keys are ordered by
+    pub layout: String,
+    /// Keycode names of each layer, in layout order
+    pub layers: Vec<Vec<String>>,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+fn qmk_version_default() -> u8 {
+    1
+}
+
+impl QmkKeyMap {
+    /// Parse QMK keymap from json file
+    pub fn from_reader<R: Read>(rdr: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(rdr)
+    }
+
+    /// Parse QMK keymap from json string
+    pub fn from_str(s: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(s)
+    }
+
+    /// Write QMK keymap to json file, pretty printed
+    pub fn to_writer_pretty<W: Write>(&self, wtr: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(wtr, self)
+    }
+}
+
+impl KeyMap {
+    /// Convert to QMK Configurator format, with keys in physical layout order
+    pub fn to_qmk(&self, layout: &Layout) -> QmkKeyMap {
+        let layers = (0..layout.meta.num_layers as usize)
+            .map(|layer| {
+                layout
+                    .physical
+                    .keys
+                    .iter()
+                    .map(|key| {
+                        match self
+                            .map
+                            .get(&key.logical_name())
+                            .and_then(|scancodes| scancodes.get(layer))
+                        {
+                            Some(name) => scancode_name_to_qmk(name),
+                            None => "KC_NO".to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        QmkKeyMap {
+            version: 1,
+            keyboard: self.model.clone(),
+            keymap: "system76-keyboard-configurator".to_string(),
+            layout: "LAYOUT".to_string(),
+            layers,
+            author: String::new(),
+            notes: String::new(),
+        }
+    }
+
+    /// Convert from QMK Configurator format, with keys in physical layout order
+    ///
+    /// LED settings are taken from the default keymap of `layout`, as are
+    /// layers not present in `qmk`. Fails if `qmk` is for another keyboard.
+    pub fn from_qmk(qmk: &QmkKeyMap, layout: &Layout) -> Result<Self, String> {
+        let num_layers = layout.meta.num_layers as usize;
+        let keys = &layout.physical.keys;
+
+        if qmk.keyboard != layout.default.model {
+            return Err(format!(
+                "QMK keymap is for '{}', not '{}'",
+                qmk.keyboard, layout.default.model
+            ));
+        }
+
+        if qmk.layers.len() > num_layers {
+            return Err(format!(
+                "QMK keymap has {} layers, but keyboard only supports {}",
+                qmk.layers.len(),
+                num_layers
+            ));
+        }
+
+        let mut keymap = layout.default.clone();
+        for (layer, qmk_layer) in qmk.layers.iter().enumerate() {
+            if qmk_layer.len() != keys.len() {
+                return Err(format!(
+                    "QMK keymap layer {} has {} keys, expected {}",
+                    layer,
+                    qmk_layer.len(),
+                    keys.len()
+                ));
+            }
+            for (key, qmk_name) in keys.iter().zip(qmk_layer) {
+                let name = scancode_name_from_qmk(qmk_name)
+                    .filter(|name| layout.scancode_from_name(name).is_some())
+                    .ok_or_else(|| format!("Unsupported QMK keycode '{}'", qmk_name))?;
+                let scancodes = keymap.map.entry(key.logical_name()).or_default();
+                scancodes.resize(num_layers, "ROLL_OVER".to_string());
+                scancodes[layer] = name;
+            }
+        }
+
+        Ok(keymap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qmk_names_round_trip() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        for scancode in 0..=u16::MAX {
+            let name = layout.scancode_name(scancode);
+            let qmk = scancode_name_to_qmk(&name);
+            assert_eq!(scancode_name_from_qmk(&qmk), Some(name));
+        }
+    }
+
+    #[test]
+    fn qmk_aliases() {
+        assert_eq!(scancode_name_from_qmk("KC_ESC").unwrap(), "ESC");
+        assert_eq!(scancode_name_from_qmk("KC_BSPC").unwrap(), "BKSP");
+        assert_eq!(scancode_name_from_qmk("KC_TRNS").unwrap(), "ROLL_OVER");
+        assert_eq!(scancode_name_from_qmk("KC_LCTL").unwrap(), "LEFT_CTRL");
+        assert_eq!(scancode_name_from_qmk("MO(1)").unwrap(), "FN");
+        assert_eq!(scancode_name_from_qmk("TG(2)").unwrap(), "LAYER_TOGGLE_3");
+        assert_eq!(scancode_name_from_qmk("RGB_TOG").unwrap(), "KBD_TOGGLE");
+        assert_eq!(
+            scancode_name_from_qmk("RGB_MOD").unwrap(),
+            "RGB_MODE_FORWARD"
+        );
+        assert_eq!(scancode_name_from_qmk("LT(1, KC_A)").unwrap(), "LT(1, A)");
+        assert_eq!(
+            scancode_name_from_qmk("LCTL(LSFT(KC_ESC))").unwrap(),
+            "LCTL(LSFT(ESC))"
+        );
+        assert_eq!(scancode_name_from_qmk("MO(5)").unwrap(), "MO(5)");
+        assert_eq!(scancode_name_from_qmk("LT(1, MO(2))"), None);
+    }
+
+    #[test]
+    fn qmk_keymap_round_trip() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        let qmk = layout.default.to_qmk(&layout);
+        assert_eq!(qmk.layers.len(), 4);
+        assert_eq!(qmk.layers[0][0], "KC_ESCAPE");
+        let keymap = KeyMap::from_qmk(&qmk, &layout).unwrap();
+        assert_eq!(keymap.map, layout.default.map);
+    }
+
+    #[test]
+    fn qmk_keymap_other_keyboard() {
+        let launch = Layout::from_board("system76/launch_1").unwrap();
+        let darp = Layout::from_board("system76/darp7").unwrap();
+        let qmk = launch.default.to_qmk(&launch);
+        assert_eq!(
+            KeyMap::from_qmk(&qmk, &darp).unwrap_err(),
+            "QMK keymap is for 'system76/launch_1', not 'system76/darp7'"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Rect {
     pub x: f64,
@@ -15,4 +17,84 @@ impl Rect {
     pub fn contains(&self, x: f64, y: f64) -> bool {
         (self.x..=self.x + self.w).contains(&x) && (self.y..=self.y + self.h).contains(&y)
     }
+
+    /// Outline of the area covered by `rects`, as a polygon for each
+    /// separate part
+    ///
+    /// Uses the same coordinates as `contains`. Polygons go clockwise with
+    /// `y` increasing downwards, and only have points where they turn.
+    pub fn outline(rects: &[Rect]) -> Vec<Vec<(f64, f64)>> {
+        fn grid(values: impl Iterator<Item = f64>) -> Vec<f64> {
+            let mut values = values.collect::<Vec<_>>();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            values.dedup();
+            values
+        }
+        let xs = grid(rects.iter().flat_map(|r| vec![r.x, r.x + r.w]));
+        let ys = grid(rects.iter().flat_map(|r| vec![r.y, r.y + r.h]));
+
+        // Whether the grid cell with top left corner `(xs[i], ys[j])` is covered
+        let inside = |i: isize, j: isize| {
+            if i < 0 || j < 0 || i as usize + 1 >= xs.len() || j as usize + 1 >= ys.len() {
+                return false;
+            }
+            let (i, j) = (i as usize, j as usize);
+            let x = (xs[i] + xs[i + 1]) / 2.;
+            let y = (ys[j] + ys[j + 1]) / 2.;
+            rects.iter().any(|r| r.contains(x, y))
+        };
+
+        // Edges between covered and uncovered cells, by grid point they start at
+        let mut edges = BTreeMap::<(isize, isize), Vec<(isize, isize)>>::new();
+        for i in 0..xs.len() as isize {
+            for j in 0..ys.len() as isize {
+                if !inside(i, j) {
+                    continue;
+                }
+                let mut edge = |start, end| edges.entry(start).or_default().push(end);
+                if !inside(i, j - 1) {
+                    edge((i, j), (i + 1, j));
+                }
+                if !inside(i + 1, j) {
+                    edge((i + 1, j), (i + 1, j + 1));
+                }
+                if !inside(i, j + 1) {
+                    edge((i + 1, j + 1), (i, j + 1));
+                }
+                if !inside(i - 1, j) {
+                    edge((i, j + 1), (i, j));
+                }
+            }
+        }
+
+        let mut polygons = Vec::new();
+        while let Some(&start) = edges.keys().next() {
+            let mut points = vec![start];
+            let mut point = start;
+            loop {
+                let ends = edges.get_mut(&point).unwrap();
+                let end = ends.pop().unwrap();
+                if ends.is_empty() {
+                    edges.remove(&point);
+                }
+                if end == start {
+                    break;
+                }
+                points.push(end);
+                point = end;
+            }
+
+            let len = points.len();
+            let corners = (0..len)
+                .filter(|k| {
+                    let prev = points[(k + len - 1) % len];
+                    let next = points[(k + 1) % len];
+                    prev.0 != next.0 && prev.1 != next.1
+                })
+                .map(|k| (xs[points[k].0 as usize], ys[points[k].1 as usize]))
+                .collect();
+            polygons.push(corners);
+        }
+        polygons
+    }
 }
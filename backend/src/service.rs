@@ -0,0 +1,81 @@
+// Signal handling shared by services presenting the boards of a `Backend`
+
+use glib::{prelude::*, SignalHandlerId};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{Backend, Board, BoardId};
+
+type BoardHandlerIds = Rc<RefCell<HashMap<BoardId, (Board, Vec<SignalHandlerId>)>>>;
+
+/// Signal handlers of a service, on a `Backend` and each of its boards, which
+/// are disconnected when dropped
+pub(crate) struct ServiceHandlers {
+    backend: Backend,
+    handler_ids: Vec<SignalHandlerId>,
+    board_handler_ids: BoardHandlerIds,
+}
+
+impl ServiceHandlers {
+    /// Call `added` for each board of `backend`, now and as boards are added,
+    /// and `removed` as they are removed
+    ///
+    /// Handlers that `added` returns are disconnected from the board when it
+    /// is removed.
+    pub fn new<A, R>(backend: &Backend, added: A, removed: R) -> Self
+    where
+        A: Fn(&Board) -> Vec<SignalHandlerId> + 'static,
+        R: Fn(&Board) + 'static,
+    {
+        let board_handler_ids = BoardHandlerIds::default();
+        let add_board = Rc::new({
+            let board_handler_ids = board_handler_ids.clone();
+            move |board: Board| {
+                let ids = added(&board);
+                board_handler_ids
+                    .borrow_mut()
+                    .insert(board.board(), (board, ids));
+            }
+        });
+
+        let handler_ids = vec![
+            backend.connect_board_added({
+                let add_board = add_board.clone();
+                move |board| add_board(board)
+            }),
+            backend.connect_board_removed({
+                let board_handler_ids = board_handler_ids.clone();
+                move |board| {
+                    let entry = board_handler_ids.borrow_mut().remove(&board.board());
+                    if let Some((board, ids)) = entry {
+                        for id in ids {
+                            board.disconnect(id);
+                        }
+                    }
+                    removed(&board);
+                }
+            }),
+        ];
+        for board in backend.boards() {
+            add_board(board);
+        }
+
+        Self {
+            backend: backend.clone(),
+            handler_ids,
+            board_handler_ids,
+        }
+    }
+}
+
+impl Drop for ServiceHandlers {
+    fn drop(&mut self) {
+        for handler_id in self.handler_ids.drain(..) {
+            self.backend.disconnect(handler_id);
+        }
+        for (_, (board, ids)) in self.board_handler_ids.borrow_mut().drain() {
+            for id in ids {
+                board.disconnect(id);
+            }
+        }
+    }
+}
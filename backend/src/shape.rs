@@ -0,0 +1,153 @@
+use std::iter;
+
+use crate::Rect;
+
+/// Physical position and shape of a key, in key widths
+///
+/// Like in `physical.json`, `y` decreases going down and rectangles are
+/// positioned by their top left corner. Rotated keys are described by their
+/// rectangles before rotation.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyShape {
+    /// Main rectangle
+    pub rect: Rect,
+    /// Second rectangle of ISO Enter and stepped keys
+    pub rect2: Option<Rect>,
+    /// Rotation in degrees, clockwise
+    pub rotation: f64,
+    /// Point the key is rotated around
+    pub rotation_origin: (f64, f64),
+}
+
+impl KeyShape {
+    /// Unrotated key with a single rectangle
+    pub fn new(rect: Rect) -> Self {
+        Self {
+            rect,
+            rect2: None,
+            rotation: 0.,
+            rotation_origin: (0., 0.),
+        }
+    }
+
+    /// Rectangles making up the key, before rotation
+    pub fn rects(&self) -> impl Iterator<Item = Rect> {
+        iter::once(self.rect).chain(self.rect2)
+    }
+
+    fn rotate_by(&self, degrees: f64, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (origin_x, origin_y) = self.rotation_origin;
+        let (dx, dy) = (x - origin_x, y - origin_y);
+        // Clockwise, with y increasing upwards
+        (
+            origin_x + dx * cos + dy * sin,
+            origin_y - dx * sin + dy * cos,
+        )
+    }
+
+    /// Apply rotation of the key to the point `(x, y)`
+    pub fn rotate(&self, x: f64, y: f64) -> (f64, f64) {
+        self.rotate_by(self.rotation, x, y)
+    }
+
+    /// Test if `(x, y)` is a point in the key
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (x, y) = self.rotate_by(-self.rotation, x, y);
+        self.rects().any(|rect| {
+            (rect.x..=rect.x + rect.w).contains(&x) && (rect.y - rect.h..=rect.y).contains(&y)
+        })
+    }
+
+    /// Smallest unrotated rectangle containing the rotated key
+    pub fn bounds(&self) -> Rect {
+        let corners = self
+            .rects()
+            .flat_map(|rect| {
+                vec![
+                    (rect.x, rect.y),
+                    (rect.x + rect.w, rect.y),
+                    (rect.x, rect.y - rect.h),
+                    (rect.x + rect.w, rect.y - rect.h),
+                ]
+            })
+            .map(|(x, y)| self.rotate(x, y))
+            .collect::<Vec<_>>();
+        let min_x = corners.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let max_x = corners
+            .iter()
+            .map(|p| p.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let min_y = corners.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max_y = corners
+            .iter()
+            .map(|p| p.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+        Rect::new(min_x, max_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// Center of the main rectangle, after rotation
+    pub fn center(&self) -> (f64, f64) {
+        let rect = &self.rect;
+        self.rotate(rect.x + rect.w / 2., rect.y - rect.h / 2.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn rotated_shape() {
+        let shape = KeyShape {
+            rect: Rect::new(1., 0., 2., 1.),
+            rect2: None,
+            rotation: 90.,
+            rotation_origin: (1., 0.),
+        };
+        // Pointing down from the origin after rotating clockwise
+        assert_close(shape.rotate(3., 0.), (1., -2.));
+        assert_close(shape.center(), (0.5, -1.));
+        assert!(shape.contains(0.5, -1.5));
+        assert!(!shape.contains(2., -0.5));
+
+        let bounds = shape.bounds();
+        assert_close((bounds.x, bounds.y), (0., 0.));
+        assert_close((bounds.w, bounds.h), (1., 2.));
+    }
+
+    #[test]
+    fn iso_enter_outline() {
+        // ISO Enter, in screen coordinates
+        let rects = [Rect::new(0.25, 0., 1.25, 2.), Rect::new(0., 0., 1.5, 1.)];
+        assert_eq!(
+            Rect::outline(&rects),
+            vec![vec![
+                (0., 0.),
+                (1.5, 0.),
+                (1.5, 2.),
+                (0.25, 2.),
+                (0.25, 1.),
+                (0., 1.),
+            ]]
+        );
+
+        let shape = KeyShape {
+            rect: Rect::new(0.25, 0., 1.25, 2.),
+            rect2: Some(Rect::new(0., 0., 1.5, 1.)),
+            rotation: 0.,
+            rotation_origin: (0., 0.),
+        };
+        assert!(shape.contains(0.1, -0.5));
+        assert!(!shape.contains(0.1, -1.5));
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{layout::parse_raw_scancode, KeyMap, Layout, PhysicalLayoutKey};
+
+/// Maximum distance, in key widths, between key centers matched by position
+const MAX_POSITION_DISTANCE: f64 = 0.5;
+
+/// How a key of the target layout was matched to the source layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyMatch {
+    /// Same legend printed on the keycap
+    Legend,
+    /// Closest key at the same position
+    Position,
+}
+
+/// Result of `KeyMap::translate`
+#[derive(Clone, Debug)]
+pub struct Translation {
+    /// Keymap for the target layout
+    pub keymap: KeyMap,
+    /// Target keys and the source key and method they were matched with
+    pub matched: Vec<(String, String, KeyMatch)>,
+    /// Source keys not corresponding to any target key, and thus dropped
+    pub unmapped_source: Vec<String>,
+    /// Target keys not corresponding to any source key, set to the target default
+    pub unmapped_target: Vec<String>,
+    /// Scancodes the target doesn't support, as `(target key, layer, scancode)`,
+    /// replaced with the target default
+    pub dropped_scancodes: Vec<(String, usize, String)>,
+}
+
+impl Translation {
+    /// `true` if every key and scancode was carried over
+    pub fn is_complete(&self) -> bool {
+        self.unmapped_source.is_empty()
+            && self.unmapped_target.is_empty()
+            && self.dropped_scancodes.is_empty()
+    }
+}
+
+fn normalize_legend(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Center of key, in key widths, with y increasing downwards
+fn key_center(key: &PhysicalLayoutKey) -> (f64, f64) {
+    let (x, y) = key.physical.center();
+    (x, -y)
+}
+
+/// Match keys of `to` to keys of `from`, as indexes into the physical key lists
+fn match_keys(from: &Layout, to: &Layout) -> Vec<Option<(usize, KeyMatch)>> {
+    let from_keys = &from.physical.keys;
+    let to_keys = &to.physical.keys;
+
+    let mut matches = vec![None; to_keys.len()];
+    let mut used = HashSet::new();
+
+    // Keys sharing a legend, like left and right shift, are paired in the
+    // order they appear in the layout.
+    let mut by_legend = HashMap::<String, Vec<usize>>::new();
+    for (i, key) in from_keys.iter().enumerate() {
+        let legend = normalize_legend(&key.physical_name);
+        if !legend.is_empty() {
+            by_legend.entry(legend).or_default().push(i);
+        }
+    }
+    for list in by_legend.values_mut() {
+        list.reverse();
+    }
+    for (i, key) in to_keys.iter().enumerate() {
+        let legend = normalize_legend(&key.physical_name);
+        if let Some(j) = by_legend.get_mut(&legend).and_then(Vec::pop) {
+            matches[i] = Some((j, KeyMatch::Legend));
+            used.insert(j);
+        }
+    }
+
+    for (i, key) in to_keys.iter().enumerate() {
+        if matches[i].is_some() {
+            continue;
+        }
+        let (x, y) = key_center(key);
+        let nearest = from_keys
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !used.contains(j))
+            .map(|(j, from_key)| {
+                let (from_x, from_y) = key_center(from_key);
+                (j, (from_x - x).hypot(from_y - y))
+            })
+            .filter(|(_, distance)| *distance <= MAX_POSITION_DISTANCE)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let Some((j, _)) = nearest {
+            matches[i] = Some((j, KeyMatch::Position));
+            used.insert(j);
+        }
+    }
+
+    matches
+}
+
+impl KeyMap {
+    /// Translate keymap for layout `from` to one for layout `to`
+    ///
+    /// Keys are matched by the legend printed on the keycap, then by physical
+    /// position. Anything that can't be translated is taken from the default
+    /// keymap of `to`, and listed in the returned `Translation`.
+    pub fn translate(&self, from: &Layout, to: &Layout) -> Translation {
+        let num_layers = to.meta.num_layers as usize;
+        let matches = match_keys(from, to);
+
+        let mut keymap = to.default.clone();
+        keymap.map.clear();
+        keymap.key_leds.clear();
+        let mut matched = Vec::new();
+        let mut unmapped_target = Vec::new();
+        let mut dropped_scancodes = Vec::new();
+        let mut used_source = HashSet::new();
+        // Raw scancodes only mean the same thing on the same kind of firmware
+        let same_firmware = from.is_qmk() == to.is_qmk();
+        let supported = |scancode: &str| {
+            to.scancode_from_name(scancode).is_some()
+                && (same_firmware || parse_raw_scancode(scancode).is_none())
+        };
+
+        for (to_key, key_match) in to.physical.keys.iter().zip(matches) {
+            let name = to_key.logical_name();
+            let default = to.default.map.get(&name).cloned().unwrap_or_default();
+            let default_scancode = |layer: usize| {
+                default
+                    .get(layer)
+                    .cloned()
+                    .unwrap_or_else(|| "NONE".to_string())
+            };
+
+            let (from_name, key_match) = match key_match {
+                Some((j, key_match)) => (from.physical.keys[j].logical_name(), key_match),
+                None => {
+                    let scancodes = (0..num_layers).map(default_scancode).collect();
+                    keymap.map.insert(name.clone(), scancodes);
+                    unmapped_target.push(name);
+                    continue;
+                }
+            };
+
+            let from_scancodes = self.map.get(&from_name).cloned().unwrap_or_default();
+            let scancodes = (0..num_layers)
+                .map(|layer| match from_scancodes.get(layer) {
+                    Some(scancode) if supported(scancode) => scancode.clone(),
+                    Some(scancode) => {
+                        dropped_scancodes.push((name.clone(), layer, scancode.clone()));
+                        default_scancode(layer)
+                    }
+                    None => default_scancode(layer),
+                })
+                .collect();
+            keymap.map.insert(name.clone(), scancodes);
+
+            let has_leds = to.leds.get(&name).map_or(false, |leds| !leds.is_empty());
+            if to.meta.has_mode && has_leds {
+                if let Some(hs) = self.key_leds.get(&from_name) {
+                    keymap.key_leds.insert(name.clone(), *hs);
+                }
+            }
+
+            used_source.insert(from_name.clone());
+            matched.push((name, from_name, key_match));
+        }
+
+        let num_led_layers = if to.meta.has_per_layer { num_layers } else { 1 };
+        keymap.layers = (0..num_led_layers)
+            .filter_map(|i| self.layers.get(i).or_else(|| to.default.layers.get(i)))
+            .cloned()
+            .collect();
+
+        let mut unmapped_source = self
+            .map
+            .keys()
+            .filter(|k| !used_source.contains(*k))
+            .cloned()
+            .collect::<Vec<_>>();
+        unmapped_source.sort();
+
+        Translation {
+            keymap,
+            matched,
+            unmapped_source,
+            unmapped_target,
+            dropped_scancodes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_same_layout() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        let translation = layout.default.translate(&layout, &layout);
+        assert!(translation.is_complete());
+        assert_eq!(translation.keymap.map, layout.default.map);
+        assert_eq!(translation.keymap.key_leds, layout.default.key_leds);
+    }
+
+    #[test]
+    fn translate_launch_to_laptop() {
+        let from = Layout::from_board("system76/launch_1").unwrap();
+        let to = Layout::from_board("system76/darp6").unwrap();
+        let mut keymap = from.default.clone();
+        // Esc -> Caps
+        keymap.map.get_mut("K00").unwrap()[0] = "CAPS".to_string();
+        // Layer toggles only exist in QMK firmware
+        keymap.map.get_mut("K01").unwrap()[1] = "LAYER_TOGGLE_1".to_string();
+        // Raw QMK scancodes mean something else to the EC
+        keymap.map.get_mut("K02").unwrap()[0] = "0x5C01".to_string();
+
+        let translation = keymap.translate(&from, &to);
+        assert_eq!(translation.keymap.model, "system76/darp6");
+        assert!(translation.keymap.validate(&to).is_empty());
+
+        let esc = to
+            .physical
+            .keys
+            .iter()
+            .find(|k| k.physical_name == "Esc")
+            .unwrap()
+            .logical_name();
+        assert_eq!(translation.keymap.map[&esc][0], "CAPS");
+
+        let f1 = to
+            .physical
+            .keys
+            .iter()
+            .find(|k| k.physical_name == "F1")
+            .unwrap()
+            .logical_name();
+        assert!(translation
+            .dropped_scancodes
+            .contains(&(f1, 1, "LAYER_TOGGLE_1".to_string())));
+
+        let (f2, _, _) = translation
+            .matched
+            .iter()
+            .find(|(_, from_name, _)| from_name == "K02")
+            .unwrap();
+        assert!(translation
+            .dropped_scancodes
+            .contains(&(f2.clone(), 0, "0x5C01".to_string())));
+    }
+}
@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+};
+
+use crate::{Layout, Rect};
+
+/// Keyboard definition in the JSON format used by VIA and Vial
+///
+/// The electrical map is described by a KLE layout, where each key's legend
+/// is its `"row,col"` position in the matrix.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ViaDefinition {
+    pub name: String,
+    /// USB vendor ID, as a hex string like `"0x3384"`
+    pub vendor_id: String,
+    /// USB product ID, as a hex string like `"0x0001"`
+    pub product_id: String,
+    pub matrix: ViaMatrix,
+    pub layouts: ViaLayouts,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ViaMatrix {
+    pub rows: u8,
+    pub cols: u8,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ViaLayouts {
+    /// KLE rows
+    pub keymap: Vec<Vec<KleItem>>,
+}
+
+/// Item of a KLE row; properties apply to the key following them
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum KleItem {
+    Key(String),
+    Properties(KleProperties),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct KleProperties {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub x: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub y: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub w: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub h: Option<f64>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_zero(value: &f64) -> bool {
+    *value == 0.
+}
+
+impl ViaDefinition {
+    /// Parse definition from json file
+    pub fn from_reader<R: Read>(rdr: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(rdr)
+    }
+
+    /// Parse definition from json string
+    pub fn from_str(s: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(s)
+    }
+
+    /// Write definition to json file, pretty printed
+    pub fn to_writer_pretty<W: Write>(&self, wtr: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(wtr, self)
+    }
+
+    /// USB vendor and product IDs
+    pub fn usb_id(&self) -> Result<(u16, u16), String> {
+        let parse = |id: &str| {
+            let hex = id.trim_start_matches("0x").trim_start_matches("0X");
+            u16::from_str_radix(hex, 16).map_err(|_| format!("Invalid USB ID '{}'", id))
+        };
+        Ok((parse(&self.vendor_id)?, parse(&self.product_id)?))
+    }
+
+    /// Matrix position in the first label of a key's legend, like `"0,1"`
+    fn parse_position(&self, legend: &str) -> Result<(u8, u8), String> {
+        // Legends may contain other labels on following lines
+        let label = legend.lines().next().unwrap_or("");
+        let mut parts = label.splitn(2, ',');
+        let mut parse = || {
+            parts
+                .next()
+                .and_then(|s| s.trim().parse::<u8>().ok())
+                .ok_or_else(|| format!("Invalid matrix position '{}'", label))
+        };
+        let row = parse()?;
+        let col = parse()?;
+        if row >= self.matrix.rows || col >= self.matrix.cols {
+            return Err(format!("Matrix position '{}' out of range", label));
+        }
+        Ok((row, col))
+    }
+
+    /// Matrix positions of keys, in KLE order
+    pub fn matrix_positions(&self) -> Result<Vec<(u8, u8)>, String> {
+        let mut positions = Vec::new();
+        for item in self.layouts.keymap.iter().flatten() {
+            if let KleItem::Key(legend) = item {
+                positions.push(self.parse_position(legend)?);
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Keys with their physical position, in KLE order
+    ///
+    /// Keys of alternative layout options, which VIA labels with an option
+    /// and choice like `"0,1"` on the fourth line of the legend, are skipped
+    /// unless they are the first choice.
+    pub fn keys(&self) -> Result<Vec<ViaKey>, String> {
+        let mut keys = Vec::new();
+        // KLE cursor position; y increases downwards, unlike `Rect`
+        let mut cursor_y = -1.;
+        for (kle_row, row) in self.layouts.keymap.iter().enumerate() {
+            let mut cursor_x = 0.;
+            cursor_y += 1.;
+            let mut properties = KleProperties::default();
+            let mut kle_col = 0;
+            for item in row {
+                match item {
+                    KleItem::Properties(props) => properties = props.clone(),
+                    KleItem::Key(legend) => {
+                        cursor_x += properties.x;
+                        cursor_y += properties.y;
+                        let w = properties.w.unwrap_or(1.);
+                        let h = properties.h.unwrap_or(1.);
+                        let rect = Rect::new(cursor_x, -cursor_y, w, h);
+                        cursor_x += w;
+                        properties = KleProperties::default();
+
+                        let choice = legend
+                            .lines()
+                            .nth(3)
+                            .and_then(|option| option.split(',').nth(1))
+                            .map(str::trim);
+                        if choice.is_some() && choice != Some("0") {
+                            continue;
+                        }
+
+                        keys.push(ViaKey {
+                            kle_position: (kle_row, kle_col),
+                            matrix_position: self.parse_position(legend)?,
+                            rect,
+                        });
+                        kle_col += 1;
+                    }
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Key of a `ViaDefinition`
+#[derive(Clone, Debug)]
+pub struct ViaKey {
+    /// Row and column in the KLE layout
+    pub kle_position: (usize, usize),
+    /// Row and column in the key matrix
+    pub matrix_position: (u8, u8),
+    pub rect: Rect,
+}
+
+impl Layout {
+    /// Generate a VIA definition, from `physical.json` and `layout.json`
+    pub fn via_definition(&self, vendor_id: u16, product_id: u16) -> Result<ViaDefinition, String> {
+        let (mut rows, mut cols) = (0u16, 0u16);
+        for (row, col) in self.layout.values() {
+            rows = rows.max(u16::from(*row) + 1);
+            cols = cols.max(u16::from(*col) + 1);
+        }
+        // VIA sizes are bytes, so there can't be a row or column 255
+        let too_large = |_| format!("Matrix of {}x{} is too large for VIA", rows, cols);
+        let (rows, cols) = (
+            u8::try_from(rows).map_err(too_large)?,
+            u8::try_from(cols).map_err(too_large)?,
+        );
+
+        let mut keymap = Vec::<Vec<KleItem>>::new();
+        let mut kle_row = None;
+        // KLE cursor position; y increases downwards, unlike `Rect`
+        let (mut cursor_x, mut cursor_y) = (0., -1.);
+        for key in &self.physical.keys {
+            if kle_row != Some(key.logical.0) {
+                kle_row = Some(key.logical.0);
+                keymap.push(Vec::new());
+                cursor_x = 0.;
+                cursor_y += 1.;
+            }
+            let row = keymap.last_mut().unwrap();
+
+            let rect = &key.physical.rect;
+            let properties = KleProperties {
+                x: rect.x - cursor_x,
+                y: -rect.y - cursor_y,
+                w: Some(rect.w).filter(|w| *w != 1.),
+                h: Some(rect.h).filter(|h| *h != 1.),
+            };
+            if properties != KleProperties::default() {
+                row.push(KleItem::Properties(properties));
+            }
+
+            let label = match self.layout.get(&key.logical_name()) {
+                Some((row, col)) => format!("{},{}", row, col),
+                None => {
+                    error!("Key {} missing from layout.json", key.logical_name());
+                    String::new()
+                }
+            };
+            row.push(KleItem::Key(label));
+
+            cursor_x = rect.x + rect.w;
+            cursor_y = -rect.y;
+        }
+
+        Ok(ViaDefinition {
+            name: self.physical.meta.name.clone(),
+            vendor_id: format!("0x{:04X}", vendor_id),
+            product_id: format!("0x{:04X}", product_id),
+            matrix: ViaMatrix { rows, cols },
+            layouts: ViaLayouts { keymap },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn via_definition_matrix() {
+        for i in crate::layouts() {
+            let layout = Layout::from_board(i).unwrap();
+            let definition = layout.via_definition(0x3384, 0x0001).unwrap();
+            let json = serde_json::to_string(&definition).unwrap();
+            let definition = ViaDefinition::from_str(&json).unwrap();
+            let expected = layout
+                .physical
+                .keys
+                .iter()
+                .map(|key| layout.layout[&key.logical_name()])
+                .collect::<Vec<_>>();
+            assert_eq!(definition.matrix_positions().unwrap(), expected, "{}", i);
+        }
+    }
+
+    #[test]
+    fn via_definition_too_large() {
+        let mut layout = Layout::from_board("system76/launch_1").unwrap();
+        layout.layout.insert("K00".to_string(), (255, 0));
+        assert!(layout.via_definition(0x3384, 0x0001).is_err());
+        layout.layout.insert("K00".to_string(), (254, 0));
+        assert_eq!(
+            layout.via_definition(0x3384, 0x0001).unwrap().matrix.rows,
+            255
+        );
+    }
+
+    #[test]
+    fn via_definition_kle() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        let definition = layout.via_definition(0x3384, 0x0001).unwrap();
+        assert_eq!(definition.vendor_id, "0x3384");
+        assert_eq!(definition.product_id, "0x0001");
+        assert_eq!(
+            definition.layouts.keymap.len(),
+            layout
+                .physical
+                .keys
+                .iter()
+                .map(|key| key.logical.0)
+                .max()
+                .unwrap() as usize
+                + 1
+        );
+        assert_eq!(
+            definition.layouts.keymap[0][0],
+            KleItem::Key("0,0".to_string())
+        );
+    }
+
+    #[test]
+    fn via_definition_keys() {
+        let layout = Layout::from_board("system76/launch_1").unwrap();
+        let definition = layout.via_definition(0x3384, 0x0001).unwrap();
+        assert_eq!(definition.usb_id(), Ok((0x3384, 0x0001)));
+        let keys = definition.keys().unwrap();
+        assert_eq!(keys.len(), layout.physical.keys.len());
+        for (key, physical_key) in keys.iter().zip(&layout.physical.keys) {
+            let rect = &physical_key.physical.rect;
+            assert_eq!(
+                (key.rect.x, key.rect.y, key.rect.w, key.rect.h),
+                (rect.x, rect.y, rect.w, rect.h)
+            );
+            assert_eq!(
+                key.matrix_position,
+                layout.layout[&physical_key.logical_name()]
+            );
+        }
+
+        // Only the first choice of a layout option is used
+        let definition = ViaDefinition::from_str(
+            r#"{
+                "name": "Test",
+                "vendorId": "0xFEED",
+                "productId": "0x0000",
+                "matrix": {"rows": 1, "cols": 3},
+                "layouts": {"keymap": [["0,0", "0,1\n\n\n0,0", {"x": -1}, "0,2\n\n\n0,1"]]}
+            }"#,
+        )
+        .unwrap();
+        let keys = definition.keys().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1].matrix_position, (0, 1));
+        assert_eq!(keys[1].kle_position, (0, 1));
+    }
+}
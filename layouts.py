@@ -10,61 +10,13 @@ import sys
 import tempfile
 from typing import List, Tuple, Dict
 
-QMK_MAPPING = {
-    'APPLICATION': 'APP',
-    'AUDIO_MUTE': 'MUTE',
-    'AUDIO_VOL_DOWN': 'VOLUME_DOWN',
-    'AUDIO_VOL_UP': 'VOLUME_UP',
-    'BSLASH': 'BACKSLASH',
-    'BSPACE': 'BKSP',
-    'CAPSLOCK': 'CAPS',
-    'DELETE': 'DEL',
-    'DOT': 'PERIOD',
-    'EQUAL': 'EQUALS',
-    'ESCAPE': 'ESC',
-    'GRAVE': 'TICK',
-    'KP_0': 'NUM_0',
-    'KP_1': 'NUM_1',
-    'KP_2': 'NUM_2',
-    'KP_3': 'NUM_3',
-    'KP_4': 'NUM_4',
-    'KP_5': 'NUM_5',
-    'KP_6': 'NUM_6',
-    'KP_7': 'NUM_7',
-    'KP_8': 'NUM_8',
-    'KP_9': 'NUM_9',
-    'KP_ASTERISK': 'NUM_ASTERISK',
-    'KP_COMMA': 'NUM_COMMA',
-    'KP_DOT': 'NUM_PERIOD',
-    'KP_ENTER': 'NUM_ENTER',
-    'KP_EQUAL': 'NUM_EQUALS',
-    'KP_MINUS': 'NUM_MINUS',
-    'KP_PLUS': 'NUM_PLUS',
-    'KP_SLASH': 'NUM_SLASH',
-    'LALT': 'LEFT_ALT',
-    'LBRACKET': 'BRACE_OPEN',
-    'LCTRL': 'LEFT_CTRL',
-    'LGUI': 'LEFT_SUPER',
-    'LSHIFT': 'LEFT_SHIFT',
-    'NO': 'NONE',
-    'MEDIA_NEXT_TRACK': 'MEDIA_NEXT',
-    'MEDIA_PLAY_PAUSE': 'PLAY_PAUSE',
-    'MEDIA_PREV_TRACK': 'MEDIA_PREV',
-    'NUMLOCK': 'NUM_LOCK',
-    'PGDOWN': 'PGDN',
-    'PSCREEN': 'PRINT_SCREEN',
-    'RALT': 'RIGHT_ALT',
-    'RBRACKET': 'BRACE_CLOSE',
-    'RCTRL': 'RIGHT_CTRL',
-    'RGB_TOG': 'KBD_TOGGLE',
-    'RGB_VAD': 'KBD_DOWN',
-    'RGB_VAI': 'KBD_UP',
-    'RGUI': 'RIGHT_SUPER',
-    'RSHIFT': 'RIGHT_SHIFT',
-    'SCOLON': 'SEMICOLON',
-    'SYSTEM_SLEEP': 'SUSPEND',
-    'TRANSPARENT': 'ROLL_OVER',
-}
+# QMK keycode names that differ from ours, and short aliases, shared with
+# `backend/src/qmk.rs`
+with open(os.path.join(os.path.dirname(os.path.abspath(__file__)), 'layouts/qmk.json')) as f:
+    QMK_JSON = json.load(f)
+QMK_MAPPING = QMK_JSON['mapping']
+# Aliases from quantum_keycodes.h
+QMK_RGB_ALIASES = {alias: keycode for alias, keycode in QMK_JSON['aliases'].items() if alias.startswith('RGB_')}
 
 ALIAS_RE = '#define\s+KC_([A-Z_]*)\s+KC_([A-Z_]+]*)\s*$'
 
@@ -90,6 +42,8 @@ def extract_scancodes(ecdir: str, is_qmk: bool) -> Tuple[List[Tuple[str, int]],
         mapping.update({alias: QMK_MAPPING.get(keycode, keycode) for alias, keycode in define_aliases})
         for (alias, keycode) in define_aliases:
             mapping[alias] = QMK_MAPPING.get(keycode, keycode)
+        # Aliases from quantum_keycodes.h
+        mapping.update(QMK_RGB_ALIASES)
     else:
         includes = [f"{ecdir}/src/common/include/common/keymap.h"]
         common_keymap_h = open(includes[0]).read()
@@ -230,11 +184,14 @@ def gen_leds_json(path: str, leds: Dict[str, List[int]]) -> None:
     with open(path, 'w') as f:
        json.dump(leds, f, indent=2)
 
-def gen_default_json(path: str, board: str, keymap: Dict[str, List[str]], is_qmk: bool) -> None:
+def gen_default_json(path: str, board: str, keymap: Dict[str, List[str]], leds: Dict[str, List[int]], is_qmk: bool) -> None:
     "Generate default.json file"
 
     with open(path, 'w') as f:
-        if is_qmk:
+        if is_qmk and not leds:
+            key_leds = {}
+            layers = [{"mode": (7, 127), "brightness": 176, "color": (142, 255)}]
+        elif is_qmk:
             key_leds = {k: None for k in keymap.keys()}
             layers = [
                 {"mode": (7, 127), "brightness": 176, "color": (142, 255)},
@@ -274,7 +231,7 @@ def generate_layout_dir(ecdir: str, board: str, is_qmk: bool) -> None:
     gen_layout_json(f'{layoutdir}/layout.json', physical, physical2)
     gen_leds_json(f'{layoutdir}/leds.json', leds)
     gen_keymap_json(f'{layoutdir}/keymap.json', scancodes)
-    gen_default_json(f'{layoutdir}/default.json', board, default_keymap, is_qmk)
+    gen_default_json(f'{layoutdir}/default.json', board, default_keymap, leds, is_qmk)
 
 
 parser = argparse.ArgumentParser()
@@ -0,0 +1,536 @@
+use glib::clone;
+use serde_json::{json, Value};
+use std::{cell::RefCell, collections::HashMap, fs::File, rc::Rc, str::FromStr, time::Duration};
+
+use backend::{
+    Backend, BackendBuilder, BackendSource, Board, Key, KeyMap, KeyMapImportOptions, Mode,
+    QmkKeyMap, Rgb, ViaDefinition,
+};
+
+const USAGE: &str = "\
+Usage: system76-keyboard-configurator [OPTIONS] COMMAND
+
+Commands:
+    list                        List connected keyboards
+    info                        Show the layout and LED settings of a keyboard
+    get-key KEY                 Show the scancodes of a key, by logical name
+    set-key KEY SCANCODE        Set the scancode of a key
+    export [FILE]               Write the keymap, to standard output by default
+    import FILE                 Load a keymap, from `export` or QMK Configurator
+    leds set                    Change LED settings of a layer
+    matrix watch                Print pressed keys whenever they change
+    serve                       Publish keyboards on the session D-Bus
+    openrgb                     Serve keyboards to OpenRGB on localhost
+    help                        Show this message
+
+Options:
+    --board BOARD               Keyboard to use, by model or index in `list`
+    --backends SOURCES          Where to find keyboards, in priority order,
+                                separated by commas: root, direct, pkexec,
+                                s76power, sysfs, or socket:PATH
+    --fake-keyboard MODELS      Use fake keyboards, separated by commas
+    --via FILES                 Also use VIA keyboards with these definitions,
+                                separated by commas
+    --layer LAYER               Layer to use, counting from 0
+    --mode MODE                 LED mode, like `SOLID_COLOR`
+    --speed SPEED               LED animation speed, from 0 to 255
+    --color COLOR               LED color, like `#ff0000`
+    --brightness BRIGHTNESS     LED brightness
+
+Output is JSON, for scripting.
+";
+
+const COMMANDS: &[&str] = &[
+    "list", "info", "get-key", "set-key", "export", "import", "leds", "matrix", "serve", "openrgb",
+    "help",
+];
+
+/// Options that take a value
+const OPTIONS: &[&str] = &[
+    "board",
+    "backends",
+    "fake-keyboard",
+    "via",
+    "layer",
+    "mode",
+    "speed",
+    "color",
+    "brightness",
+];
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg.starts_with("--") {
+                let name = &arg[2..];
+                if !OPTIONS.contains(&name) {
+                    return Err(format!("Unknown option '{}'", arg));
+                }
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("Missing value for '{}'", arg))?;
+                options.insert(name.to_string(), value.clone());
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn option<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        self.options
+            .get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid value '{}' for --{}", value, name))
+            })
+            .transpose()
+    }
+
+    /// Layer from `--layer`, checked against the layers of `board`
+    fn layer(&self, board: &Board) -> Result<Option<usize>, String> {
+        let num_layers = board.layout().meta.num_layers as usize;
+        match self.option::<usize>("layer")? {
+            Some(layer) if layer >= num_layers => Err(format!(
+                "Layer {} does not exist, '{}' has {} layers",
+                layer,
+                board.model(),
+                num_layers
+            )),
+            layer => Ok(layer),
+        }
+    }
+}
+
+/// `true` if `args` are a command for `run`, rather than options of the GUI
+pub fn is_command(args: &[String]) -> bool {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--") && OPTIONS.contains(&&arg[2..]) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            return COMMANDS.contains(&arg.as_str());
+        }
+    }
+    false
+}
+
+/// Run a command line command, returning the exit code
+pub fn run(args: &[String]) -> i32 {
+    match run_command(args) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
+}
+
+fn run_command(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    let command = args
+        .positional
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    if command == ["help"] {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    let sources = match (
+        args.options.get("fake-keyboard"),
+        args.options.get("backends"),
+    ) {
+        (Some(models), _) => vec![BackendSource::Dummy(
+            models.split(',').map(str::to_string).collect(),
+        )],
+        (None, Some(sources)) => BackendSource::parse_list(sources)?,
+        (None, None) => BackendSource::defaults(),
+    };
+    let mut builder = sources
+        .into_iter()
+        .fold(BackendBuilder::new(), BackendBuilder::source);
+    if let Some(paths) = args.options.get("via") {
+        let definitions = paths
+            .split(',')
+            .map(|path| {
+                let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+                ViaDefinition::from_reader(file).map_err(|err| format!("{}: {}", path, err))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        builder = builder.source(BackendSource::Via(definitions));
+    }
+    let backend = builder.build()?;
+    let boards = load_boards(&backend)?;
+    let board = || select_board(&boards, args.options.get("board"));
+
+    let output = match command.as_slice() {
+        ["list"] => list(&boards),
+        ["info"] => info(&board()?),
+        ["get-key", key] => {
+            let board = board()?;
+            get_key(&board, key, args.layer(&board)?)?
+        }
+        ["set-key", key, scancode] => {
+            let board = board()?;
+            let layer = args.layer(&board)?.unwrap_or(0);
+            set_key(&board, key, layer, scancode)?
+        }
+        ["export"] => json_value(&board()?.export_keymap())?,
+        ["export", path] => {
+            let file = File::create(path).map_err(|err| format!("Failed to open file: {}", err))?;
+            board()?
+                .export_keymap()
+                .to_writer_pretty(file)
+                .map_err(|err| format!("Failed to export keymap: {}", err))?;
+            return Ok(());
+        }
+        ["import", path] => import(&board()?, path)?,
+        ["leds", "set"] => leds_set(&board()?, &args)?,
+        ["matrix", "watch"] => return matrix_watch(&backend, &board()?),
+        ["serve"] => return serve(&backend),
+        ["openrgb"] => return serve_openrgb(&backend),
+        _ => return Err(format!("Invalid command\n\n{}", USAGE)),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    Ok(())
+}
+
+fn json_value<T: serde::Serialize>(value: &T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|err| err.to_string())
+}
+
+/// Wait for connected boards to load, without a GTK main loop
+fn load_boards(backend: &Backend) -> Result<Vec<Board>, String> {
+    let context = glib::MainContext::default();
+    context.block_on(backend.refresh_async())?;
+    // Handle `board-added` signals queued by the refresh
+    while context.pending() {
+        context.iteration(false);
+    }
+    Ok(backend.boards())
+}
+
+/// Board chosen with `--board`, or the only connected board
+fn select_board(boards: &[Board], name: Option<&String>) -> Result<Board, String> {
+    match name {
+        Some(name) => {
+            if let Ok(index) = name.parse::<usize>() {
+                return boards
+                    .get(index)
+                    .cloned()
+                    .ok_or_else(|| format!("No keyboard with index {}", index));
+            }
+            boards
+                .iter()
+                .find(|board| board.model() == name)
+                .cloned()
+                .ok_or_else(|| format!("No keyboard with model '{}'", name))
+        }
+        None => match boards {
+            [board] => Ok(board.clone()),
+            [] => Err("No keyboards found".to_string()),
+            _ => Err("Multiple keyboards found, choose one with --board".to_string()),
+        },
+    }
+}
+
+fn find_key<'a>(board: &'a Board, name: &str) -> Result<&'a Key, String> {
+    board
+        .keys()
+        .iter()
+        .find(|key| key.logical_name == name)
+        .ok_or_else(|| format!("No key '{}' on '{}'", name, board.model()))
+}
+
+fn list(boards: &[Board]) -> Value {
+    boards
+        .iter()
+        .enumerate()
+        .map(|(index, board)| {
+            json!({
+                "index": index,
+                "model": board.model(),
+                "name": board.layout().meta.display_name,
+                "generic": board.layout().is_generic(),
+                "fake": board.is_fake(),
+            })
+        })
+        .collect()
+}
+
+fn layer_info(board: &Board, layer: usize) -> Value {
+    let settings = &board.layers()[layer];
+    let mode = settings.mode();
+    json!({
+        "layer": layer,
+        "mode": mode.map(|(mode, _)| mode.id),
+        "speed": mode.map(|(_, speed)| speed),
+        "brightness": settings.brightness(),
+        "color": settings.color().to_rgb().to_string(),
+    })
+}
+
+fn info(board: &Board) -> Value {
+    let meta = &board.layout().meta;
+    json!({
+        "model": board.model(),
+        "name": meta.display_name,
+        "layers": meta.num_layers,
+        "keys": board.keys().iter().map(|key| &key.logical_name).collect::<Vec<_>>(),
+        "has_mode": meta.has_mode,
+        "has_per_layer": meta.has_per_layer,
+        "has_matrix": board.has_matrix(),
+        "max_brightness": board.max_brightness(),
+        "leds": (0..board.layers().len()).map(|layer| layer_info(board, layer)).collect::<Vec<_>>(),
+    })
+}
+
+fn get_key(board: &Board, name: &str, layer: Option<usize>) -> Result<Value, String> {
+    let key = find_key(board, name)?;
+    let layers = match layer {
+        Some(layer) => vec![layer],
+        None => (0..board.layout().meta.num_layers as usize).collect(),
+    };
+    let scancodes = layers
+        .into_iter()
+        .filter_map(|layer| {
+            let (scancode, scancode_name) = key.get_scancode(layer)?;
+            Some(json!({
+                "layer": layer,
+                "scancode": scancode,
+                "name": scancode_name,
+            }))
+        })
+        .collect::<Vec<_>>();
+    Ok(json!({
+        "key": key.logical_name,
+        "physical_name": key.physical_name,
+        "scancodes": scancodes,
+    }))
+}
+
+fn set_key(board: &Board, name: &str, layer: usize, scancode: &str) -> Result<Value, String> {
+    let key = find_key(board, name)?;
+    glib::MainContext::default().block_on(key.set_scancode(layer, scancode))?;
+    get_key(board, name, Some(layer))
+}
+
+fn import(board: &Board, path: &str) -> Result<Value, String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|err| format!("Failed to open file: {}", err))?;
+    let mut keymap = match KeyMap::from_str(&json) {
+        Ok(keymap) => keymap,
+        Err(err) => match QmkKeyMap::from_str(&json) {
+            Ok(qmk) => KeyMap::from_qmk(&qmk, board.layout())?,
+            Err(_) => return Err(format!("Failed to parse keymap: {}", err)),
+        },
+    };
+
+    if keymap.model != board.model() {
+        let from = backend::Layout::from_board(&keymap.model).map_err(|err| err.to_string())?;
+        let translation = keymap.translate(&from, board.layout());
+        for key in &translation.unmapped_source {
+            eprintln!("warning: key {} of '{}' was dropped", key, keymap.model);
+        }
+        for (key, layer, scancode) in &translation.dropped_scancodes {
+            eprintln!(
+                "warning: scancode {} of key {} on layer {} was dropped",
+                scancode, key, layer
+            );
+        }
+        keymap = translation.keymap;
+    }
+
+    let issues = keymap.validate(board.layout());
+    if !issues.is_empty() {
+        let issues = issues.iter().map(ToString::to_string).collect::<Vec<_>>();
+        return Err(format!("Invalid keymap:\n{}", issues.join("\n")));
+    }
+
+    let context = glib::MainContext::default();
+    context.block_on(async {
+        board
+            .import_keymap(&keymap, &KeyMapImportOptions::default())
+            .await;
+        board.led_save().await
+    })?;
+    json_value(&board.export_keymap())
+}
+
+fn leds_set(board: &Board, args: &Args) -> Result<Value, String> {
+    // Without per-layer settings, the only layer applies to the whole keyboard
+    let layer = match args.layer(board)? {
+        Some(_) if !board.layout().meta.has_per_layer => {
+            return Err(format!(
+                "'{}' has no per-layer LED settings, so --layer can't be used",
+                board.model()
+            ));
+        }
+        layer => layer.unwrap_or(0),
+    };
+    let settings = &board.layers()[layer];
+
+    let mode = match args.options.get("mode") {
+        Some(id) => Some(Mode::from_id(id).ok_or_else(|| format!("Unknown mode '{}'", id))?),
+        None => None,
+    };
+    let speed = args.option::<u8>("speed")?;
+    let color = match args.options.get("color") {
+        Some(color) => Some(Rgb::parse(color).ok_or_else(|| format!("Invalid color '{}'", color))?),
+        None => None,
+    };
+    let brightness = args.option::<i32>("brightness")?;
+    if let Some(brightness) = brightness {
+        if brightness < 0 || brightness > board.max_brightness() {
+            return Err(format!(
+                "Brightness must be between 0 and {}",
+                board.max_brightness()
+            ));
+        }
+    }
+
+    if (mode.is_some() || speed.is_some()) && !board.layout().meta.has_mode {
+        return Err(format!("'{}' does not support LED modes", board.model()));
+    }
+
+    let context = glib::MainContext::default();
+    context.block_on(async {
+        if mode.is_some() || speed.is_some() {
+            let (current_mode, current_speed) = settings.mode().unwrap_or((&Mode::all()[0], 128));
+            settings
+                .set_mode(mode.unwrap_or(current_mode), speed.unwrap_or(current_speed))
+                .await?;
+        }
+        if let Some(color) = color {
+            settings.set_color(color.to_hs_lossy()).await?;
+        }
+        if let Some(brightness) = brightness {
+            settings.set_brightness(brightness).await?;
+        }
+        board.led_save().await
+    })?;
+
+    Ok(layer_info(board, layer))
+}
+
+/// Print a line of JSON with the pressed keys each time they change, until
+/// the board is removed
+fn matrix_watch(backend: &Backend, board: &Board) -> Result<(), String> {
+    if !board.has_matrix() {
+        return Err(format!(
+            "'{}' does not support reading the matrix",
+            board.model()
+        ));
+    }
+
+    let main_loop = glib::MainLoop::new(None, false);
+    let last_pressed = Rc::new(RefCell::new(None));
+    board.connect_matrix_changed(clone!(@weak board => move || {
+        let pressed = board
+            .keys()
+            .iter()
+            .filter(|key| key.pressed())
+            .map(|key| key.logical_name.clone())
+            .collect::<Vec<_>>();
+        let mut last_pressed = last_pressed.borrow_mut();
+        if last_pressed.as_ref() != Some(&pressed) {
+            println!("{}", json!({ "pressed": pressed }));
+            *last_pressed = Some(pressed);
+        }
+    }));
+    board.connect_removed(clone!(@strong main_loop => move || main_loop.quit()));
+
+    backend.set_matrix_get_rate(Some(Duration::from_millis(50)));
+    main_loop.run();
+    Ok(())
+}
+
+/// Keep `_service` running, refreshing the boards it presents, until killed
+#[cfg(any(
+    all(target_os = "linux", feature = "dbus-service"),
+    feature = "openrgb"
+))]
+fn run_service<T>(backend: &Backend, _service: T) -> Result<(), String> {
+    glib::timeout_add_seconds_local(
+        1,
+        clone!(@weak backend => @default-return glib::Continue(false), move || {
+            backend.refresh();
+            glib::Continue(true)
+        }),
+    );
+    glib::MainLoop::new(None, false).run();
+    Ok(())
+}
+
+/// Publish boards on the session bus, until killed
+#[cfg(all(target_os = "linux", feature = "dbus-service"))]
+fn serve(backend: &Backend) -> Result<(), String> {
+    let service = backend::DbusService::new_session(backend)?;
+    run_service(backend, service)
+}
+
+#[cfg(not(all(target_os = "linux", feature = "dbus-service")))]
+fn serve(_backend: &Backend) -> Result<(), String> {
+    Err("Built without D-Bus support, enable the `dbus-service` feature".to_string())
+}
+
+/// Serve boards to OpenRGB clients, until killed
+#[cfg(feature = "openrgb")]
+fn serve_openrgb(backend: &Backend) -> Result<(), String> {
+    let service = backend::OpenRgbService::new(backend, ("127.0.0.1", backend::OPENRGB_PORT))?;
+    run_service(backend, service)
+}
+
+#[cfg(not(feature = "openrgb"))]
+fn serve_openrgb(_backend: &Backend) -> Result<(), String> {
+    Err("Built without OpenRGB support, enable the `openrgb` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_command() {
+        assert!(is_command(&strings(&["list"])));
+        assert!(is_command(&strings(&[
+            "--board",
+            "system76/launch_1",
+            "info"
+        ])));
+        assert!(is_command(&strings(&["--fake-keyboard", "help", "help"])));
+        assert!(!is_command(&strings(&[])));
+        assert!(!is_command(&strings(&["--debug-layers"])));
+        assert!(!is_command(&strings(&["-k", "system76/launch_1"])));
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::parse(&strings(&["leds", "--speed", "10", "set"])).unwrap();
+        assert_eq!(args.positional, ["leds", "set"]);
+        assert_eq!(args.option::<u8>("speed"), Ok(Some(10)));
+        assert_eq!(args.option::<u8>("brightness"), Ok(None));
+        assert!(Args::parse(&strings(&["info", "--unknown", "1"])).is_err());
+        assert!(Args::parse(&strings(&["info", "--board"])).is_err());
+    }
+}
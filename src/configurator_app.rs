@@ -3,12 +3,13 @@ use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use std::cell::Cell;
 
-use crate::{about_dialog, MainWindow, Page};
-use backend::DerefCell;
+use crate::{about_dialog, MainWindow};
+use backend::{BackendSource, DerefCell};
 
 #[derive(Default)]
 pub struct ConfiguratorAppInner {
     phony_board_names: DerefCell<Vec<String>>,
+    backend_sources: DerefCell<Vec<BackendSource>>,
     debug_layers: Cell<bool>,
     launch_test: Cell<bool>,
 }
@@ -34,6 +35,14 @@ impl ObjectImpl for ConfiguratorAppInner {
             "",
             None,
         );
+        app.add_main_option(
+            "backends",
+            glib::Char::new('\0').unwrap(),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::String,
+            "",
+            None,
+        );
         app.add_main_option(
             "debug-layers",
             glib::Char::new('\0').unwrap(),
@@ -67,7 +76,21 @@ impl ApplicationImpl for ConfiguratorAppInner {
             vec![]
         };
 
+        let backend_sources = if let Some(opt) = opts.lookup_value("backends", None) {
+            let value: String = opt.get().unwrap();
+            match BackendSource::parse_list(&value) {
+                Ok(sources) => sources,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return 1;
+                }
+            }
+        } else {
+            BackendSource::defaults()
+        };
+
         self.phony_board_names.set(board_names);
+        self.backend_sources.set(backend_sources);
         self.debug_layers.set(opts.contains("debug-layers"));
         self.launch_test.set(opts.contains("launch-test"));
         -1
@@ -84,7 +107,8 @@ impl ApplicationImpl for ConfiguratorAppInner {
         app.add_action(&about_action);
         app.set_accels_for_action("kbd.import", &["<Primary>o"]);
         app.set_accels_for_action("kbd.export", &["<Primary>e"]);
-        for (i, _) in Page::iter_all().enumerate() {
+        // Number keys select the first pages, which vary with the number of layers
+        for i in 0..9 {
             app.set_accels_for_action(&format!("kbd.page{}", i), &[&format!("<Primary>{}", i + 1)]);
         }
     }
@@ -122,6 +146,10 @@ impl ConfiguratorApp {
         &self.inner().phony_board_names
     }
 
+    pub fn backend_sources(&self) -> &[BackendSource] {
+        &self.inner().backend_sources
+    }
+
     pub fn debug_layers(&self) -> bool {
         self.inner().debug_layers.get()
     }
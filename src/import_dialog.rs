@@ -0,0 +1,205 @@
+use cascade::cascade;
+use gtk::prelude::*;
+use std::collections::HashSet;
+
+use backend::{Board, Issue, KeyMapImportOptions, Translation};
+use widgets::SelectedKeys;
+
+/// Ask which parts of a keymap to import. Returns `None` if canceled.
+pub fn import_options_dialog<W: IsA<gtk::Window>>(
+    parent: &W,
+    board: &Board,
+    selected: &SelectedKeys,
+) -> Option<KeyMapImportOptions> {
+    let layer_checks = (0..board.layout().meta.num_layers)
+        .map(|i| {
+            cascade! {
+                gtk::CheckButton::with_label(&format!("Layer {}", i + 1));
+                ..set_active(true);
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let key_leds_check = cascade! {
+        gtk::CheckButton::with_label("Key LED colors");
+        ..set_active(board.layout().meta.has_mode);
+        ..set_sensitive(board.layout().meta.has_mode);
+    };
+
+    let layer_settings_check = cascade! {
+        gtk::CheckButton::with_label("Layer LED settings");
+        ..set_active(true);
+    };
+
+    let selected_check = cascade! {
+        gtk::CheckButton::with_label("Only selected keys");
+        ..set_sensitive(!selected.is_empty());
+    };
+
+    let vbox = cascade! {
+        gtk::Box::new(gtk::Orientation::Vertical, 8);
+        ..set_property_margin(24);
+        ..add(&cascade! {
+            gtk::Label::new(Some("<b>Layers</b>"));
+            ..set_use_markup(true);
+            ..set_halign(gtk::Align::Start);
+        });
+    };
+    for check in &layer_checks {
+        vbox.add(check);
+    }
+    cascade! {
+        &vbox;
+        ..add(&cascade! {
+            gtk::Label::new(Some("<b>Include</b>"));
+            ..set_use_markup(true);
+            ..set_halign(gtk::Align::Start);
+        });
+        ..add(&key_leds_check);
+        ..add(&layer_settings_check);
+        ..add(&selected_check);
+        ..show_all();
+    };
+
+    let dialog = cascade! {
+        gtk::Dialog::with_buttons(Some("Import Layout"), Some(parent), gtk::DialogFlags::MODAL | gtk::DialogFlags::USE_HEADER_BAR, &[("Cancel", gtk::ResponseType::Cancel), ("Import", gtk::ResponseType::Accept)]);
+        ..set_default_response(gtk::ResponseType::Accept);
+    };
+    dialog.get_content_area().add(&vbox);
+
+    let response = dialog.run();
+    dialog.close();
+    if response != gtk::ResponseType::Accept {
+        return None;
+    }
+
+    let layers = layer_checks
+        .iter()
+        .enumerate()
+        .filter(|(_, check)| check.get_active())
+        .map(|(i, _)| i)
+        .collect::<HashSet<_>>();
+
+    let keys = if selected_check.get_active() {
+        let keys = board.keys();
+        Some(
+            selected
+                .iter()
+                .map(|i| keys[*i].logical_name.clone())
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    Some(KeyMapImportOptions {
+        layers: Some(layers),
+        key_leds: key_leds_check.get_active(),
+        layer_settings: layer_settings_check.get_active(),
+        keys,
+    })
+}
+
+/// Show problems found validating a keymap. Returns `true` to import anyway.
+pub fn keymap_issues_dialog<W: IsA<gtk::Window>>(parent: &W, issues: &[Issue]) -> bool {
+    let text = issues
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    confirm_report_dialog(
+        parent,
+        "Keymap Problems",
+        concat!(
+            "This keymap does not match the keyboard. ",
+            "Parts that don't apply will be skipped."
+        ),
+        &text,
+        "Import Anyway",
+    )
+}
+
+/// Show what translating a keymap from another model changes. Returns `true`
+/// to continue importing the translated keymap.
+pub fn translation_dialog<W: IsA<gtk::Window>>(
+    parent: &W,
+    from_name: &str,
+    translation: &Translation,
+) -> bool {
+    let mut lines = Vec::new();
+    for key in &translation.unmapped_source {
+        lines.push(format!("Key {} has no match on this keyboard", key));
+    }
+    for key in &translation.unmapped_target {
+        lines.push(format!("Key {} keeps its default setting", key));
+    }
+    for (key, layer, scancode) in &translation.dropped_scancodes {
+        lines.push(format!(
+            "Key {} on layer {}: '{}' is not supported by this keyboard",
+            key,
+            layer + 1,
+            scancode
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("All keys were matched".to_string());
+    }
+
+    confirm_report_dialog(
+        parent,
+        "Import from Another Model",
+        &format!(
+            "This keymap is for a {}. Keys will be matched by their legend or position.",
+            from_name
+        ),
+        &lines.join("\n"),
+        "Import",
+    )
+}
+
+fn confirm_report_dialog<W: IsA<gtk::Window>>(
+    parent: &W,
+    title: &str,
+    message: &str,
+    report: &str,
+    accept_label: &str,
+) -> bool {
+    let vbox = cascade! {
+        gtk::Box::new(gtk::Orientation::Vertical, 12);
+        ..set_property_margin(24);
+        ..add(&cascade! {
+            gtk::Label::new(Some(message));
+            ..set_line_wrap(true);
+            ..set_max_width_chars(60);
+            ..set_halign(gtk::Align::Start);
+        });
+        ..add(&cascade! {
+            gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+            ..set_property_hscrollbar_policy(gtk::PolicyType::Never);
+            ..set_min_content_height(200);
+            ..add(&cascade! {
+                gtk::Label::new(Some(report));
+                ..set_selectable(true);
+                ..set_halign(gtk::Align::Start);
+                ..set_valign(gtk::Align::Start);
+            });
+        });
+        ..show_all();
+    };
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(title),
+        Some(parent),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::USE_HEADER_BAR,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            (accept_label, gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.get_content_area().add(&vbox);
+
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Accept
+}
@@ -1,19 +1,19 @@
 use cascade::cascade;
-use futures::{prelude::*, stream::FuturesUnordered};
 use glib::clone;
 use glib::object::WeakRef;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use std::{
     cell::{Cell, RefCell},
-    collections::HashMap,
-    fs::File,
-    pin::Pin,
+    fs::{self, File},
     str,
 };
 
-use crate::{show_error_dialog, Backlight, KeyboardLayer, MainWindow, Page, Picker, Testing};
-use backend::{Board, DerefCell, KeyMap, Layout, Mode};
+use crate::{
+    import_options_dialog, keymap_issues_dialog, show_error_dialog, translation_dialog, Backlight,
+    KeyboardLayer, MainWindow, Page, Picker, Testing,
+};
+use backend::{Board, DerefCell, KeyMap, KeyMapImportOptions, Layout, QmkKeyMap};
 use widgets::SelectedKeys;
 
 #[derive(Default)]
@@ -52,7 +52,7 @@ impl ObjectImpl for KeyboardInner {
 
                     debug!("{:?}", page);
                     let last_layer = keyboard.layer();
-                    keyboard.inner().page.set(page.unwrap_or(Page::Layer1));
+                    keyboard.inner().page.set(page.unwrap_or_default());
                     let layer = keyboard.layer();
                     if layer != last_layer {
                         keyboard.set_selected(keyboard.selected());
@@ -102,6 +102,12 @@ impl ObjectImpl for KeyboardInner {
                     keyboard.export();
                 ));
             });
+            ..add_action(&cascade! {
+                gio::SimpleAction::new("export-qmk", None);
+                ..connect_activate(clone!(@weak keyboard => move |_, _|
+                    keyboard.export_qmk();
+                ));
+            });
             ..add_action(&cascade! {
                 gio::SimpleAction::new("reset", None);
                 ..connect_activate(clone!(@weak keyboard => move |_, _|
@@ -183,22 +189,32 @@ impl Keyboard {
             keyboard.inner().testing.set(None);
         }
 
-        stack.add_titled(
-            &cascade! {
-                gtk::Box::new(gtk::Orientation::Vertical, 32);
-                ..add(&cascade! {
-                    gtk::Label::new(Some(concat!(
-                        "Select a key on the keymap to change its settings. ",
-                        "Your settings are automatically saved to firmware.")));
-                    ..set_line_wrap(true);
-                    ..set_max_width_chars(100);
-                    ..set_halign(gtk::Align::Center);
-                });
-                ..add(&*keyboard.inner().picker_box);
-            },
-            "keymap",
-            "Keymap",
-        );
+        let keymap_box = gtk::Box::new(gtk::Orientation::Vertical, 32);
+        if board.layout().is_generic() {
+            keymap_box.add(&cascade! {
+                gtk::Label::new(Some(concat!(
+                    "No layout is known for this keyboard. ",
+                    "Keys are shown in raw matrix order, labeled by row and column, ",
+                    "and may not match the physical keyboard.")));
+                ..get_style_context().add_class("error");
+                ..set_line_wrap(true);
+                ..set_max_width_chars(100);
+                ..set_halign(gtk::Align::Center);
+            });
+        }
+        keymap_box.add(&cascade! {
+            gtk::Label::new(Some(concat!(
+                "Select a key on the keymap to change its settings. ",
+                "Your settings are automatically saved to firmware.")));
+            ..set_line_wrap(true);
+            ..set_max_width_chars(100);
+            ..set_halign(gtk::Align::Center);
+        });
+        keymap_box.add(&*keyboard.inner().picker_box);
+        // Boards with only lighting have no layers to remap
+        if board.layout().meta.num_layers > 0 {
+            stack.add_titled(&keymap_box, "keymap", "Keymap");
+        }
 
         let backlight = cascade! {
             Backlight::new(board.clone());
@@ -257,7 +273,7 @@ impl Keyboard {
         }
     }
 
-    fn layout(&self) -> &Layout {
+    pub fn layout(&self) -> &Layout {
         &self.inner().board.layout()
     }
 
@@ -296,18 +312,7 @@ impl Keyboard {
         self.board().export_keymap()
     }
 
-    pub fn import_keymap(&self, keymap: KeyMap) {
-        // TODO: Ideally don't want this function to be O(Keys^2)
-
-        if keymap.model != self.board().model() {
-            show_error_dialog(
-                &self.window().unwrap(),
-                "Failed to import keymap",
-                format!("Keymap is for board '{}'", keymap.model),
-            );
-            return;
-        }
-
+    pub fn import_keymap(&self, keymap: KeyMap, options: KeyMapImportOptions) {
         let self_ = self.clone();
         glib::MainContext::default().spawn_local(async move {
             let _loader = self_.get_toplevel().and_then(|x| {
@@ -317,54 +322,9 @@ impl Keyboard {
                 )))
             });
 
-            // TODO: Make sure it doesn't panic with invalid json with invalid indexes?
-
-            let key_indices = self_
-                .board()
-                .keys()
-                .iter()
-                .enumerate()
-                .map(|(i, k)| (&k.logical_name, i))
-                .collect::<HashMap<_, _>>();
-
-            let futures = FuturesUnordered::<Pin<Box<dyn Future<Output = ()>>>>::new();
-
-            for (k, v) in &keymap.map {
-                for (layer, scancode_name) in v.iter().enumerate() {
-                    let n = key_indices[&k];
-                    futures.push(Box::pin(self_.keymap_set(n, layer, scancode_name)));
-                }
-            }
-
-            for (k, hs) in &keymap.key_leds {
-                let res = self_.board().keys()[key_indices[&k]].set_color(*hs);
-                futures.push(Box::pin(async move {
-                    if let Err(err) = res.await {
-                        error!("Failed to key LED: {}", err);
-                    }
-                }));
-            }
+            self_.board().import_keymap(&keymap, &options).await;
 
-            for (i, keymap_layer) in keymap.layers.iter().enumerate() {
-                let layer = &self_.board().layers()[i];
-                futures.push(Box::pin(async move {
-                    if let Some((mode, speed)) = keymap_layer.mode {
-                        if let Err(err) =
-                            layer.set_mode(Mode::from_index(mode).unwrap(), speed).await
-                        {
-                            error!("Failed to set layer mode: {}", err)
-                        }
-                    }
-                    if let Err(err) = layer.set_brightness(keymap_layer.brightness).await {
-                        error!("Failed to set layer brightness: {}", err)
-                    }
-                    if let Err(err) = layer.set_color(keymap_layer.color).await {
-                        error!("Failed to set layer color: {}", err)
-                    }
-                }));
-            }
-
-            futures.collect::<()>().await;
+            self_.set_selected(self_.selected());
         });
     }
 
@@ -382,9 +342,9 @@ impl Keyboard {
 
         if chooser.run() == gtk::ResponseType::Accept {
             let path = chooser.get_filename().unwrap();
-            match File::open(&path) {
-                Ok(file) => match KeyMap::from_reader(file) {
-                    Ok(keymap) => self.import_keymap(keymap),
+            match fs::read_to_string(&path) {
+                Ok(json) => match self.parse_keymap(&json) {
+                    Ok(keymap) => self.import_with_dialogs(keymap),
                     Err(err) => {
                         show_error_dialog(&self.window().unwrap(), "Failed to import keymap", err)
                     }
@@ -394,6 +354,48 @@ impl Keyboard {
         }
     }
 
+    /// Parse a Configurator keymap, or failing that, a QMK Configurator keymap
+    fn parse_keymap(&self, json: &str) -> Result<KeyMap, String> {
+        let err = match KeyMap::from_str(json) {
+            Ok(keymap) => return Ok(keymap),
+            Err(err) => err,
+        };
+        match QmkKeyMap::from_str(json) {
+            Ok(qmk) => KeyMap::from_qmk(&qmk, self.layout()),
+            Err(_) => Err(err.to_string()),
+        }
+    }
+
+    fn import_with_dialogs(&self, keymap: KeyMap) {
+        let window = self.window().unwrap();
+
+        let keymap = if keymap.model != self.board().model() {
+            let from = match Layout::from_board(&keymap.model) {
+                Ok(from) => from,
+                Err(err) => {
+                    show_error_dialog(&window, "Failed to import keymap", err);
+                    return;
+                }
+            };
+            let translation = keymap.translate(&from, self.layout());
+            if !translation_dialog(&window, &from.meta.display_name, &translation) {
+                return;
+            }
+            translation.keymap
+        } else {
+            keymap
+        };
+
+        let issues = keymap.validate(self.layout());
+        if !issues.is_empty() && !keymap_issues_dialog(&window, &issues) {
+            return;
+        }
+
+        if let Some(options) = import_options_dialog(&window, self.board(), &self.selected()) {
+            self.import_keymap(keymap, options);
+        }
+    }
+
     fn export(&self) {
         let filter = cascade! {
             gtk::FileFilter::new();
@@ -432,20 +434,50 @@ impl Keyboard {
         }
     }
 
+    fn export_qmk(&self) {
+        let filter = cascade! {
+            gtk::FileFilter::new();
+            ..set_name(Some("json"));
+            ..add_pattern("*.json");
+        };
+
+        let chooser = cascade! {
+            gtk::FileChooserNative::new::<gtk::Window>(Some("Export QMK Keymap"), None, gtk::FileChooserAction::Save, Some("Export"), Some("Cancel"));
+            ..add_filter(&filter);
+            ..set_current_name("keymap.json");
+            ..set_do_overwrite_confirmation(true);
+        };
+
+        if chooser.run() == gtk::ResponseType::Accept {
+            let path = chooser.get_filename().unwrap();
+            let qmk = self.export_keymap().to_qmk(self.layout());
+
+            match File::create(&path) {
+                Ok(file) => match qmk.to_writer_pretty(file) {
+                    Ok(()) => {}
+                    Err(err) => {
+                        show_error_dialog(&self.window().unwrap(), "Failed to export keymap", err)
+                    }
+                },
+                Err(err) => show_error_dialog(&self.window().unwrap(), "Failed to open file", err),
+            }
+        }
+    }
+
     fn reset(&self) {
-        self.import_keymap(self.layout().default.clone());
+        self.import_keymap(
+            self.layout().default.clone(),
+            KeyMapImportOptions::default(),
+        );
     }
 
     fn add_pages(&self, debug_layers: bool) {
         let layer_stack = &*self.inner().layer_stack;
 
-        for (i, page) in Page::iter_all().enumerate() {
+        let num_layers = self.layout().meta.num_layers.into();
+        for (i, page) in Page::iter_all(num_layers).enumerate() {
             if !debug_layers && page.is_debug() {
                 continue;
-            } else if let Some(layer) = page.layer() {
-                if layer >= self.layout().meta.num_layers.into() {
-                    continue;
-                }
             }
 
             let keyboard_layer = cascade! {
@@ -466,7 +498,7 @@ impl Keyboard {
                     .flags(glib::BindingFlags::SYNC_CREATE)
                     .build();
             }
-            layer_stack.add_titled(&keyboard_layer, page.name(), page.name());
+            layer_stack.add_titled(&keyboard_layer, &page.name(), &page.name());
 
             self.inner().action_group.add_action(&cascade! {
                 gio::SimpleAction::new(&format!("page{}", i), None);
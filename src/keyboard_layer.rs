@@ -3,14 +3,11 @@ use glib::clone;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use once_cell::unsync::OnceCell;
-use std::{
-    cell::{Cell, RefCell},
-    f64::consts::PI,
-};
+use std::cell::{Cell, RefCell};
 
 use crate::{Page, TestingColors};
 use backend::{Board, DerefCell, Key, Rect, Rgb};
-use widgets::SelectedKeys;
+use widgets::{rotate_key, rounded_outline, SelectedKeys};
 
 const SCALE: f64 = 64.;
 const MARGIN: f64 = 2.;
@@ -118,9 +115,22 @@ impl WidgetImpl for KeyboardLayerInner {
         let testing_colors = self.testing_colors.borrow();
 
         for (i, k) in widget.keys().iter().enumerate() {
-            let Rect { x, y, w, h } = widget.key_position(&k);
-
+            let offset = widget.key_offset(&k);
+            let rects = k
+                .physical
+                .rects()
+                .map(|rect| {
+                    let mut rect = scale_rect(&rect);
+                    rect.x += offset.0;
+                    rect.y += offset.1;
+                    rect
+                })
+                .collect::<Vec<_>>();
+            let Rect { x, y, w, h } = rects[0];
+
+            let mut fg = k.foreground_color.map(Rgb::to_floats);
             let mut bg = if let Some(rgb) = testing_colors.0.get(&i) {
+                fg = None;
                 rgb
             } else {
                 &k.background_color
@@ -129,13 +139,14 @@ impl WidgetImpl for KeyboardLayerInner {
 
             if k.pressed() {
                 bg = self.board.layout().pressed_color().to_floats();
+                fg = None;
             }
 
-            let fg = if (bg.0 + bg.1 + bg.2) / 3. >= 0.5 {
+            let fg = fg.unwrap_or(if (bg.0 + bg.1 + bg.2) / 3. >= 0.5 {
                 (0., 0., 0.)
             } else {
                 (1., 1., 1.)
-            };
+            });
 
             let mut text_alpha = 1.;
             let mut bg_alpha = 1.;
@@ -147,13 +158,9 @@ impl WidgetImpl for KeyboardLayerInner {
                 }
             }
 
-            // Rounded rectangle
-            cr.new_sub_path();
-            cr.arc(x + w - RADIUS, y + RADIUS, RADIUS, -0.5 * PI, 0.);
-            cr.arc(x + w - RADIUS, y + h - RADIUS, RADIUS, 0., 0.5 * PI);
-            cr.arc(x + RADIUS, y + h - RADIUS, RADIUS, 0.5 * PI, PI);
-            cr.arc(x + RADIUS, y + RADIUS, RADIUS, PI, 1.5 * PI);
-            cr.close_path();
+            cr.save();
+            rotate_key(cr, &k.physical, SCALE, offset);
+            rounded_outline(cr, &rects, RADIUS);
 
             cr.set_source_rgba(bg.0, bg.1, bg.2, bg_alpha);
             cr.fill_preserve();
@@ -176,6 +183,7 @@ impl WidgetImpl for KeyboardLayerInner {
             cr.move_to(x, y + (h - text_height) / 2.);
             cr.set_source_rgba(fg.0, fg.1, fg.2, text_alpha);
             pangocairo::show_layout(cr, &layout);
+            cr.restore();
         }
 
         Inhibit(false)
@@ -189,10 +197,11 @@ impl WidgetImpl for KeyboardLayerInner {
         }
 
         let pos = evt.get_position();
-        let pressed = widget
-            .keys()
-            .iter()
-            .position(|k| widget.key_position(&k).contains(pos.0, pos.1));
+        let pressed = widget.keys().iter().position(|k| {
+            let offset = widget.key_offset(&k);
+            k.physical
+                .contains((pos.0 - offset.0) / SCALE, -(pos.1 - offset.1) / SCALE)
+        });
 
         if let Some(pressed) = pressed {
             let shift = evt.get_state().contains(gdk::ModifierType::SHIFT_MASK);
@@ -319,12 +328,7 @@ impl KeyboardLayer {
     }
 
     fn key_position_wide(&self, k: &Key) -> Rect {
-        Rect {
-            x: (k.physical.x * SCALE) + MARGIN,
-            y: -(k.physical.y * SCALE) + MARGIN,
-            w: (k.physical.w * SCALE) - MARGIN * 2.,
-            h: (k.physical.h * SCALE) - MARGIN * 2.,
-        }
+        scale_rect(&k.physical.bounds())
     }
 
     fn key_position_narrow(&self, k: &Key) -> Rect {
@@ -346,4 +350,21 @@ impl KeyboardLayer {
         pos.x += (self.get_allocated_width() - width) as f64 / 2.;
         pos
     }
+
+    /// Offset of a key from its position in the wide layout
+    fn key_offset(&self, k: &Key) -> (f64, f64) {
+        let wide = self.key_position_wide(k);
+        let pos = self.key_position(k);
+        (pos.x - wide.x, pos.y - wide.y)
+    }
+}
+
+/// Rectangle in key widths to pixels, leaving a margin between keys
+fn scale_rect(rect: &Rect) -> Rect {
+    Rect {
+        x: (rect.x * SCALE) + MARGIN,
+        y: -(rect.y * SCALE) + MARGIN,
+        w: (rect.w * SCALE) - MARGIN * 2.,
+        h: (rect.h * SCALE) - MARGIN * 2.,
+    }
 }
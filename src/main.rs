@@ -8,8 +8,10 @@ use std::process;
 
 mod about_dialog;
 mod backlight;
+mod cli;
 mod configurator_app;
 mod error_dialog;
+mod import_dialog;
 mod keyboard;
 mod keyboard_layer;
 mod main_window;
@@ -20,8 +22,8 @@ mod testing;
 
 pub use self::configurator_app::run;
 use self::{
-    backlight::*, configurator_app::*, error_dialog::*, keyboard::*, keyboard_layer::*,
-    main_window::*, page::*, picker::*, shortcuts_window::*, testing::*,
+    backlight::*, configurator_app::*, error_dialog::*, import_dialog::*, keyboard::*,
+    keyboard_layer::*, main_window::*, page::*, picker::*, shortcuts_window::*, testing::*,
 };
 
 fn main() {
@@ -33,11 +35,45 @@ fn main() {
     .init();
 
     let args = env::args().collect::<Vec<_>>();
-    for arg in args.iter().skip(1) {
+    let mut iter = args.iter().skip(1);
+    #[cfg(unix)]
+    let (mut socket, mut socket_group) = (None, None);
+    while let Some(arg) = iter.next() {
         if arg.as_str() == "--daemon" {
             backend::run_daemon();
         }
+        // `--daemon-socket PATH`, which members of `--daemon-socket-group GROUP`
+        // can also connect to
+        #[cfg(unix)]
+        match arg.as_str() {
+            "--daemon-socket" => socket = Some(daemon_arg(arg, iter.next())),
+            "--daemon-socket-group" => socket_group = Some(daemon_arg(arg, iter.next())),
+            _ => {}
+        }
+    }
+    #[cfg(unix)]
+    if let Some(path) = socket {
+        if let Err(err) = backend::run_daemon_socket(path.as_ref(), socket_group) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
+    if cli::is_command(&args[1..]) {
+        process::exit(cli::run(&args[1..]));
     }
 
     process::exit(crate::run());
 }
+
+#[cfg(unix)]
+fn daemon_arg<'a>(arg: &str, value: Option<&'a String>) -> &'a str {
+    match value {
+        Some(value) => value,
+        None => {
+            eprintln!("{} requires a value", arg);
+            process::exit(1);
+        }
+    }
+}
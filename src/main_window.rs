@@ -5,7 +5,7 @@ use gtk::subclass::prelude::*;
 use std::{cell::RefCell, time::Duration};
 
 use crate::{shortcuts_window, ConfiguratorApp, Keyboard, KeyboardLayer, Page, Picker};
-use backend::{Backend, Board, DerefCell};
+use backend::{Backend, BackendBuilder, BackendSource, Board, DerefCell};
 
 pub struct Loader(MainWindow, gtk::Box);
 
@@ -66,6 +66,7 @@ impl ObjectImpl for MainWindowInner {
                 gio::Menu::new();
                 ..append(Some("Import Layout"), Some("kbd.import"));
                 ..append(Some("Export Layout"), Some("kbd.export"));
+                ..append(Some("Export QMK Keymap"), Some("kbd.export-qmk"));
                 ..append(Some("Reset Layout"), Some("kbd.reset"));
             });
             ..append_section(None, &cascade! {
@@ -205,7 +206,7 @@ impl MainWindow {
         app.add_window(&window);
 
         let backend = cascade! {
-            daemon();
+            daemon(app.backend_sources().to_vec(), app.phony_board_names().to_vec());
             ..connect_board_loading(clone!(@weak window => move || {
                 let loader = window.display_loader("Keyboard(s) detected. Loading...");
                 *window.inner().board_loading.borrow_mut() = Some(loader);
@@ -232,15 +233,6 @@ impl MainWindow {
             });
         }));
 
-        let phony_board_names = app.phony_board_names().to_vec();
-        if !phony_board_names.is_empty() {
-            let backend = Backend::new_dummy(phony_board_names).unwrap();
-            backend.connect_board_added(
-                clone!(@weak window => move |board| window.add_keyboard(board)),
-            );
-            backend.refresh();
-        }
-
         window.inner().backend.set(backend);
         glib::timeout_add_seconds_local(
             1,
@@ -311,7 +303,7 @@ impl MainWindow {
             }));
         };
         let keyboard_layer = cascade! {
-            KeyboardLayer::new(Page::Layer1, keyboard.board().clone());
+            KeyboardLayer::new(Page::default(), keyboard.board().clone());
             ..set_halign(gtk::Align::Center);
         };
         let keyboard_box = cascade! {
@@ -377,19 +369,12 @@ impl MainWindow {
     }
 }
 
-#[cfg(target_os = "linux")]
-fn daemon() -> Backend {
-    if unsafe { libc::geteuid() == 0 } {
-        info!("Already running as root");
-        Backend::new()
-    } else {
-        info!("Not running as root, spawning daemon with pkexec");
-        Backend::new_pkexec()
-    }
-    .expect("Failed to create server")
-}
-
-#[cfg(not(target_os = "linux"))]
-fn daemon() -> Backend {
-    Backend::new().expect("Failed to create server")
+/// Backend for boards of `sources`, and fake boards with `board_names`
+pub fn daemon(sources: Vec<BackendSource>, board_names: Vec<String>) -> Backend {
+    sources
+        .into_iter()
+        .fold(BackendBuilder::new(), BackendBuilder::source)
+        .dummy(board_names)
+        .build()
+        .expect("Failed to create server")
 }
@@ -3,10 +3,8 @@ use backend::Key;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Page {
-    Layer1,
-    Layer2,
-    Layer3,
-    Layer4,
+    /// Keymap of a layer, counting from 0
+    Layer(usize),
     Keycaps,
     Logical,
     Electrical,
@@ -14,25 +12,19 @@ pub enum Page {
 }
 
 impl Page {
-    pub fn name(&self) -> &'static str {
+    pub fn name(&self) -> String {
         match self {
-            Self::Layer1 => "Layer 1",
-            Self::Layer2 => "Layer 2",
-            Self::Layer3 => "Layer 3",
-            Self::Layer4 => "Layer 4",
-            Self::Keycaps => "Keycaps",
-            Self::Logical => "Logical",
-            Self::Electrical => "Electrical",
-            Self::Leds => "LEDs",
+            Self::Layer(layer) => format!("Layer {}", layer + 1),
+            Self::Keycaps => "Keycaps".to_string(),
+            Self::Logical => "Logical".to_string(),
+            Self::Electrical => "Electrical".to_string(),
+            Self::Leds => "LEDs".to_string(),
         }
     }
 
     pub fn layer(&self) -> Option<usize> {
         match self {
-            Self::Layer1 => Some(0),
-            Self::Layer2 => Some(1),
-            Self::Layer3 => Some(2),
-            Self::Layer4 => Some(3),
+            Self::Layer(layer) => Some(*layer),
             _ => None,
         }
     }
@@ -44,24 +36,20 @@ impl Page {
         )
     }
 
-    pub fn iter_all() -> impl Iterator<Item = Self> {
-        vec![
-            Self::Layer1,
-            Self::Layer2,
-            Self::Layer3,
-            Self::Layer4,
+    /// All pages of a keyboard with `num_layers` layers
+    pub fn iter_all(num_layers: usize) -> impl Iterator<Item = Self> {
+        (0..num_layers).map(Self::Layer).chain(vec![
             Self::Keycaps,
             Self::Logical,
             Self::Electrical,
             Self::Leds,
-        ]
-        .into_iter()
+        ])
     }
 
     pub fn get_label(&self, key: &Key) -> String {
         match self {
-            Page::Layer1 | Page::Layer2 | Page::Layer3 | Page::Layer4 => {
-                let scancode_name = key.get_scancode(self.layer().unwrap()).unwrap().1;
+            Page::Layer(layer) => {
+                let scancode_name = key.get_scancode(*layer).unwrap().1;
                 SCANCODE_LABELS
                     .get(&scancode_name)
                     .unwrap_or(&scancode_name)
@@ -77,6 +65,6 @@ impl Page {
 
 impl Default for Page {
     fn default() -> Self {
-        Self::Layer1
+        Self::Layer(0)
     }
 }
@@ -6,12 +6,14 @@ use once_cell::sync::Lazy;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::Keyboard;
-use backend::DerefCell;
+use backend::{DerefCell, Keycode};
 
+mod picker_composite;
 mod picker_group;
 mod picker_json;
 mod picker_key;
 
+use picker_composite::PickerComposite;
 use picker_group::PickerGroup;
 use picker_json::picker_json;
 use picker_key::PickerKey;
@@ -44,6 +46,7 @@ pub static SCANCODE_LABELS: Lazy<HashMap<String, String>> = Lazy::new(|| {
 #[derive(Default)]
 pub struct PickerInner {
     groups: DerefCell<Vec<PickerGroup>>,
+    composite: DerefCell<Rc<PickerComposite>>,
     keys: DerefCell<HashMap<String, Rc<PickerKey>>>,
     keyboard: RefCell<Option<Keyboard>>,
     selected: RefCell<Option<String>>,
@@ -91,14 +94,20 @@ impl ObjectImpl for PickerInner {
             group.vbox.set_parent(picker);
         }
 
+        let composite = PickerComposite::new();
+        composite.vbox.set_parent(picker);
+
         self.keys.set(keys);
         self.groups.set(groups);
+        self.composite.set(composite);
 
         cascade! {
             picker;
             ..connect_signals();
             ..show_all();
         };
+        // Shown once there is a keyboard
+        self.composite.vbox.hide();
     }
 }
 
@@ -113,7 +122,8 @@ impl WidgetImpl for PickerInner {
             .iter()
             .map(|x| x.vbox.get_preferred_width().1)
             .max()
-            .unwrap();
+            .unwrap()
+            .max(self.composite.vbox.get_preferred_width().1);
         let natural_width = self
             .groups
             .chunks(3)
@@ -139,7 +149,12 @@ impl WidgetImpl for PickerInner {
                     .unwrap()
             })
             .sum::<i32>()
-            + (rows.len() as i32 - 1) * VSPACING;
+            + (rows.len() as i32 - 1) * VSPACING
+            + if self.composite.vbox.get_visible() {
+                VSPACING + self.composite.vbox.get_preferred_height().1
+            } else {
+                0
+            };
 
         (height, height)
     }
@@ -181,6 +196,17 @@ impl WidgetImpl for PickerInner {
                 .unwrap()
                 + VSPACING;
         }
+
+        let composite = &self.composite.vbox;
+        if composite.get_visible() {
+            let width = composite.get_preferred_width().1;
+            composite.size_allocate(&gtk::Allocation {
+                x: (allocation.width - width) / 2,
+                y,
+                width,
+                height: composite.get_preferred_height().1,
+            });
+        }
     }
 
     fn realize(&self, widget: &Self::Type) {
@@ -214,6 +240,7 @@ impl ContainerImpl for PickerInner {
         for group in self.groups.iter() {
             cb.call(group.vbox.upcast_ref());
         }
+        cb.call(self.composite.vbox.upcast_ref());
     }
 
     fn remove(&self, _obj: &Self::Type, child: &gtk::Widget) {
@@ -242,27 +269,67 @@ impl Picker {
                 let button = &key.gtk;
                 let name = key.name.to_string();
                 button.connect_clicked(clone!(@weak picker => @default-panic, move |_| {
-                    let kb = match picker.inner().keyboard.borrow().clone() {
-                        Some(kb) => kb,
-                        None => {
-                            return;
-                        }
-                    };
-                    let layer = kb.layer();
-
-                    info!("Clicked {} layer {:?}", name, layer);
-                    let selected = kb.selected();
-                    if selected.len() == 1 {
-                        let i = *selected.iter().next().unwrap();
-                        if let Some(layer) = layer {
-                            glib::MainContext::default().spawn_local(clone!(@strong kb, @strong name => async move {
-                                kb.keymap_set(i, layer, &name).await;
-                            }));
-                        }
-                    }
+                    picker.key_clicked(name.clone());
                 }));
             }
         }
+
+        let composite: &Rc<PickerComposite> = &self.inner().composite;
+        composite.apply.connect_clicked(
+            clone!(@weak picker, @weak composite => @default-panic, move |_| {
+                let kb = match picker.inner().keyboard.borrow().clone() {
+                    Some(kb) => kb,
+                    None => {
+                        return;
+                    }
+                };
+                let name = composite
+                    .keycode()
+                    .and_then(|keycode| keycode.to_u16())
+                    .and_then(|scancode| kb.layout().scancode_to_name(scancode));
+                match name {
+                    Some(name) => picker.key_clicked(name),
+                    None => error!("Invalid keycode {:?}", composite.keycode()),
+                }
+            }),
+        );
+        composite.raw_apply.connect_clicked(
+            clone!(@weak picker, @weak composite => @default-panic, move |_| {
+                let kb = match picker.inner().keyboard.borrow().clone() {
+                    Some(kb) => kb,
+                    None => {
+                        return;
+                    }
+                };
+                match composite.raw_scancode() {
+                    Some(scancode) => picker.key_clicked(kb.layout().scancode_name(scancode)),
+                    None => error!("Invalid raw scancode"),
+                }
+            }),
+        );
+    }
+
+    fn key_clicked(&self, name: String) {
+        let kb = match self.inner().keyboard.borrow().clone() {
+            Some(kb) => kb,
+            None => {
+                return;
+            }
+        };
+        let layer = kb.layer();
+
+        info!("Clicked {} layer {:?}", name, layer);
+        let selected = kb.selected();
+        if selected.len() == 1 {
+            let i = *selected.iter().next().unwrap();
+            if let Some(layer) = layer {
+                glib::MainContext::default().spawn_local(
+                    clone!(@strong kb, @strong name => async move {
+                        kb.keymap_set(i, layer, &name).await;
+                    }),
+                );
+            }
+        }
     }
 
     fn get_button(&self, scancode_name: &str) -> Option<&gtk::Button> {
@@ -281,6 +348,9 @@ impl Picker {
                     key.gtk.set_visible(visible);
                 }
             }
+            let composite = &self.inner().composite;
+            composite.set_layout(kb.layout());
+            composite.vbox.show();
             kb.set_picker(Some(&self));
         }
         *self.inner().keyboard.borrow_mut() = keyboard;
@@ -301,6 +371,13 @@ impl Picker {
             if let Some(button) = self.get_button(selected) {
                 button.get_style_context().add_class("selected");
             }
+            if let Some(kb) = &*self.inner().keyboard.borrow() {
+                if let Some(scancode) = kb.layout().scancode_from_name(selected) {
+                    let composite = &self.inner().composite;
+                    composite.set_keycode(Keycode::from_u16(scancode));
+                    composite.set_raw_scancode(scancode);
+                }
+            }
         }
     }
 
@@ -0,0 +1,247 @@
+use cascade::cascade;
+use glib::clone;
+use gtk::prelude::*;
+use std::rc::Rc;
+
+use super::SCANCODE_LABELS;
+use backend::{Keycode, Layout, Mods};
+
+/// Kinds of composite keycode, by combo box id
+static KINDS: &[(&str, &str)] = &[
+    ("mods", "Key with modifiers"),
+    ("mod-tap", "Modifiers when held, key when tapped"),
+    ("layer-tap", "Layer when held, key when tapped"),
+    ("one-shot-mods", "Modifiers for next key"),
+    ("momentary", "Layer while held"),
+    ("toggle", "Toggle layer"),
+    ("to", "Switch to layer"),
+    ("default", "Set default layer"),
+    ("one-shot-layer", "Layer for next key"),
+    ("tap-toggle", "Layer while held, toggle when tapped"),
+];
+
+/// Builds QMK keycodes combining modifiers, layers, and a basic key, or raw
+/// scancodes for any keyboard
+pub(super) struct PickerComposite {
+    pub(super) vbox: gtk::Box,
+    composite_box: gtk::Box,
+    kind: gtk::ComboBoxText,
+    mods: Vec<(Mods, gtk::CheckButton)>,
+    right: gtk::CheckButton,
+    base: gtk::ComboBoxText,
+    layer: gtk::SpinButton,
+    pub(super) apply: gtk::Button,
+    raw: gtk::Entry,
+    pub(super) raw_apply: gtk::Button,
+}
+
+impl PickerComposite {
+    pub(super) fn new() -> Rc<Self> {
+        let label = cascade! {
+            gtk::Label::new(Some("Advanced keys"));
+            ..set_attributes(Some(&cascade! {
+                pango::AttrList::new();
+                ..insert(pango::Attribute::new_weight(pango::Weight::Bold));
+            } ));
+            ..set_halign(gtk::Align::Start);
+            ..set_margin_bottom(8);
+        };
+
+        let kind = gtk::ComboBoxText::new();
+        for (id, name) in KINDS {
+            kind.append(Some(*id), name);
+        }
+
+        let mods = vec![
+            (Mods::CTRL, gtk::CheckButton::with_label("Ctrl")),
+            (Mods::SHIFT, gtk::CheckButton::with_label("Shift")),
+            (Mods::ALT, gtk::CheckButton::with_label("Alt")),
+            (Mods::GUI, gtk::CheckButton::with_label("Super")),
+        ];
+        let right = gtk::CheckButton::with_label("Right side");
+
+        let base = gtk::ComboBoxText::new();
+        let layer = cascade! {
+            gtk::SpinButton::with_range(0., 0., 1.);
+            ..set_digits(0);
+        };
+        let apply = gtk::Button::with_label("Apply");
+
+        let mods_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        for (_, check) in &mods {
+            mods_box.add(check);
+        }
+        mods_box.add(&right);
+
+        let raw = cascade! {
+            gtk::Entry::new();
+            ..set_placeholder_text(Some("0x0000"));
+            ..set_max_length(6);
+            ..set_width_chars(8);
+        };
+        let raw_apply = gtk::Button::with_label("Apply");
+
+        let composite_box = cascade! {
+            gtk::Box::new(gtk::Orientation::Vertical, 4);
+            ..add(&cascade! {
+                gtk::Box::new(gtk::Orientation::Horizontal, 8);
+                ..add(&kind);
+                ..add(&mods_box);
+            });
+            ..add(&cascade! {
+                gtk::Box::new(gtk::Orientation::Horizontal, 8);
+                ..add(&gtk::Label::new(Some("Key")));
+                ..add(&base);
+                // Counting from 0, like the `MO(1)` names of keycodes
+                ..add(&gtk::Label::new(Some("Layer (from 0)")));
+                ..add(&layer);
+                ..add(&apply);
+            });
+        };
+
+        let vbox = cascade! {
+            gtk::Box::new(gtk::Orientation::Vertical, 4);
+            ..add(&label);
+            ..add(&composite_box);
+            ..add(&cascade! {
+                gtk::Box::new(gtk::Orientation::Horizontal, 8);
+                ..add(&gtk::Label::new(Some("Raw scancode")));
+                ..add(&raw);
+                ..add(&raw_apply);
+            });
+        };
+
+        let composite = Rc::new(Self {
+            vbox,
+            composite_box,
+            kind,
+            mods,
+            right,
+            base,
+            layer,
+            apply,
+            raw,
+            raw_apply,
+        });
+
+        composite
+            .kind
+            .connect_changed(clone!(@weak composite => move |_| composite.update_sensitive()));
+        composite.kind.set_active_id(Some(KINDS[0].0));
+
+        composite
+    }
+
+    /// Only enable controls used by the selected kind of keycode
+    fn update_sensitive(&self) {
+        let kind = self.kind.get_active_id();
+        let kind = kind.as_deref().unwrap_or("");
+        let has_mods = matches!(kind, "mods" | "mod-tap" | "one-shot-mods");
+        let has_base = matches!(kind, "mods" | "mod-tap" | "layer-tap");
+        let has_layer = !has_mods || kind == "layer-tap";
+
+        for (_, check) in &self.mods {
+            check.set_sensitive(has_mods);
+        }
+        self.right.set_sensitive(has_mods);
+        self.base.set_sensitive(has_base);
+        self.layer.set_sensitive(has_layer);
+    }
+
+    /// Show basic keys and layers of `layout`, and only show composite
+    /// keycodes if it supports them
+    pub(super) fn set_layout(&self, layout: &Layout) {
+        self.composite_box.set_visible(layout.is_qmk());
+        self.base.remove_all();
+        for (code, name) in layout.basic_keycode_names() {
+            let label = SCANCODE_LABELS
+                .get(name)
+                .map_or(name, String::as_str)
+                .replace('\n', " ");
+            self.base.append(Some(&code.to_string()), &label);
+        }
+        self.base.set_active(Some(0));
+        self.layer
+            .set_range(0., f64::from(layout.meta.num_layers.max(1) - 1));
+    }
+
+    /// Keycode built from the current settings
+    pub(super) fn keycode(&self) -> Option<Keycode> {
+        let mut mods = Mods::default();
+        for (modifier, check) in &self.mods {
+            if check.get_active() {
+                mods |= *modifier;
+            }
+        }
+        if self.right.get_active() {
+            mods |= Mods::RIGHT;
+        }
+        let base = || self.base.get_active_id()?.parse::<u8>().ok();
+        let layer = self.layer.get_value_as_int() as u8;
+
+        Some(match self.kind.get_active_id()?.as_str() {
+            "mods" => Keycode::Mods(mods, base()?),
+            "mod-tap" => Keycode::ModTap(mods, base()?),
+            "layer-tap" => Keycode::LayerTap(layer, base()?),
+            "one-shot-mods" => Keycode::OneShotMods(mods),
+            "momentary" => Keycode::Momentary(layer),
+            "toggle" => Keycode::Toggle(layer),
+            "to" => Keycode::To(layer),
+            "default" => Keycode::DefaultLayer(layer),
+            "one-shot-layer" => Keycode::OneShotLayer(layer),
+            "tap-toggle" => Keycode::TapToggle(layer),
+            _ => return None,
+        })
+    }
+
+    /// Show the settings of `keycode`, if it is a composite keycode
+    pub(super) fn set_keycode(&self, keycode: Keycode) {
+        let (kind, mods, base, layer) = match keycode {
+            Keycode::Mods(mods, base) => ("mods", Some(mods), Some(base), None),
+            Keycode::ModTap(mods, base) => ("mod-tap", Some(mods), Some(base), None),
+            Keycode::LayerTap(layer, base) => ("layer-tap", None, Some(base), Some(layer)),
+            Keycode::OneShotMods(mods) => ("one-shot-mods", Some(mods), None, None),
+            Keycode::Momentary(layer) => ("momentary", None, None, Some(layer)),
+            Keycode::Toggle(layer) => ("toggle", None, None, Some(layer)),
+            Keycode::To(layer) => ("to", None, None, Some(layer)),
+            Keycode::DefaultLayer(layer) => ("default", None, None, Some(layer)),
+            Keycode::OneShotLayer(layer) => ("one-shot-layer", None, None, Some(layer)),
+            Keycode::TapToggle(layer) => ("tap-toggle", None, None, Some(layer)),
+            Keycode::Basic(_) | Keycode::Other(_) => return,
+        };
+
+        self.kind.set_active_id(Some(kind));
+        if let Some(mods) = mods {
+            for (modifier, check) in &self.mods {
+                check.set_active(mods.contains(*modifier));
+            }
+            self.right.set_active(mods.contains(Mods::RIGHT));
+        }
+        if let Some(base) = base {
+            self.base.set_active_id(Some(&base.to_string()));
+        }
+        if let Some(layer) = layer {
+            self.layer.set_value(f64::from(layer));
+        }
+    }
+
+    /// Raw scancode entered in hex, with or without a `0x` prefix
+    pub(super) fn raw_scancode(&self) -> Option<u16> {
+        let text = self.raw.get_text();
+        let text = text.trim();
+        let hex = if text.starts_with("0x") || text.starts_with("0X") {
+            &text[2..]
+        } else {
+            text
+        };
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        u16::from_str_radix(hex, 16).ok()
+    }
+
+    /// Show `scancode` in the raw scancode entry
+    pub(super) fn set_raw_scancode(&self, scancode: u16) {
+        self.raw.set_text(&format!("0x{:04X}", scancode));
+    }
+}
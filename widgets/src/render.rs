@@ -0,0 +1,339 @@
+use std::{fs::File, path::Path};
+
+use backend::{Board, KeyMap, KeyShape, Layout, PhysicalLayoutKey, Rect};
+
+const SCALE: f64 = 64.;
+const MARGIN: f64 = 2.;
+const RADIUS: f64 = 4.;
+const FONT: &str = "Sans 9";
+const SMALL_FONT: &str = "Sans 7";
+
+/// What to label keys with when rendering a keymap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderPage {
+    /// Scancodes of a layer, starting at 0
+    Layer(usize),
+    Keycaps,
+    Logical,
+    Electrical,
+    Leds,
+}
+
+/// Output format of `KeymapRenderer::render_to_file`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderFormat {
+    Svg,
+    Pdf,
+    Png,
+}
+
+impl RenderFormat {
+    /// Guess format from file extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "svg" => Some(Self::Svg),
+            "pdf" => Some(Self::Pdf),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+}
+
+/// Renders a keymap onto any cairo surface, without needing a display
+pub struct KeymapRenderer<'a> {
+    layout: &'a Layout,
+    keymap: KeyMap,
+    page: RenderPage,
+    legend_layers: Vec<usize>,
+    scancode_label: Box<dyn Fn(&str) -> String + 'a>,
+}
+
+impl<'a> KeymapRenderer<'a> {
+    pub fn new(layout: &'a Layout, keymap: KeyMap, page: RenderPage) -> Self {
+        Self {
+            layout,
+            keymap,
+            page,
+            legend_layers: Vec::new(),
+            scancode_label: Box::new(str::to_string),
+        }
+    }
+
+    /// Render the current keymap of `board`
+    pub fn for_board(board: &'a Board, page: RenderPage) -> Self {
+        Self::new(board.layout(), board.export_keymap(), page)
+    }
+
+    /// Also show scancodes of `layers` on each key, in smaller text
+    ///
+    /// Only used with `RenderPage::Layer`.
+    pub fn set_legend_layers(&mut self, layers: Vec<usize>) {
+        self.legend_layers = layers;
+    }
+
+    /// Set how scancode names are labeled; by default, the name itself
+    pub fn set_scancode_label<F: Fn(&str) -> String + 'a>(&mut self, label: F) {
+        self.scancode_label = Box::new(label);
+    }
+
+    /// Size of the rendered keymap, in points
+    pub fn size(&self) -> (f64, f64) {
+        self.layout
+            .physical_keys()
+            .iter()
+            .fold((0., 0.), |(width, height), key| {
+                let rect = key.physical.bounds();
+                (
+                    f64::max(width, (rect.x + rect.w) * SCALE),
+                    f64::max(height, (-rect.y + rect.h) * SCALE),
+                )
+            })
+    }
+
+    fn scancode(&self, key: &PhysicalLayoutKey, layer: usize) -> Option<&str> {
+        self.keymap
+            .map
+            .get(&key.logical_name())?
+            .get(layer)
+            .map(String::as_str)
+    }
+
+    /// Labels of a key; the first is the primary legend
+    fn labels(&self, key: &PhysicalLayoutKey) -> Vec<String> {
+        let logical_name = key.logical_name();
+        match self.page {
+            RenderPage::Layer(layer) => {
+                let mut labels = vec![self
+                    .scancode(key, layer)
+                    .map_or_else(String::new, |name| (self.scancode_label)(name))];
+                for legend_layer in &self.legend_layers {
+                    if *legend_layer == layer {
+                        continue;
+                    }
+                    match self.scancode(key, *legend_layer) {
+                        Some("NONE") | Some("ROLL_OVER") | None => {}
+                        Some(name) => labels.push(format!(
+                            "{}: {}",
+                            legend_layer + 1,
+                            (self.scancode_label)(name)
+                        )),
+                    }
+                }
+                labels
+            }
+            RenderPage::Keycaps => vec![key.physical_name.clone()],
+            RenderPage::Logical => vec![logical_name],
+            RenderPage::Electrical => vec![self
+                .layout
+                .electrical(&logical_name)
+                .map_or_else(String::new, |(output, input)| {
+                    format!("{}, {}", output, input)
+                })],
+            RenderPage::Leds => vec![self
+                .layout
+                .leds(&logical_name)
+                .iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")],
+        }
+    }
+
+    /// Draw keymap with its top left corner at the origin of `cr`
+    pub fn draw(&self, cr: &cairo::Context) {
+        let font = pango::FontDescription::from_string(FONT);
+        let small_font = pango::FontDescription::from_string(SMALL_FONT);
+
+        for key in self.layout.physical_keys() {
+            let rects = key
+                .physical
+                .rects()
+                .map(|rect| Rect {
+                    x: (rect.x * SCALE) + MARGIN,
+                    y: -(rect.y * SCALE) + MARGIN,
+                    w: (rect.w * SCALE) - MARGIN * 2.,
+                    h: (rect.h * SCALE) - MARGIN * 2.,
+                })
+                .collect::<Vec<_>>();
+            let Rect { x, y, w, h } = rects[0];
+
+            let bg = key.background_color.to_floats();
+            let fg = match key.foreground_color {
+                Some(color) => color.to_floats(),
+                None if (bg.0 + bg.1 + bg.2) / 3. >= 0.5 => (0., 0., 0.),
+                None => (1., 1., 1.),
+            };
+
+            let mut text_alpha = 1.;
+            let mut bg_alpha = 1.;
+            if let RenderPage::Layer(layer) = self.page {
+                if let Some("NONE") | Some("ROLL_OVER") = self.scancode(key, layer) {
+                    text_alpha = 0.5;
+                    bg_alpha = 0.75;
+                }
+            }
+
+            cr.save();
+            rotate_key(cr, &key.physical, SCALE, (0., 0.));
+            rounded_outline(cr, &rects, RADIUS);
+
+            cr.set_source_rgba(bg.0, bg.1, bg.2, bg_alpha);
+            cr.fill();
+
+            // Primary legend in the middle, other layers stacked below it
+            let layouts = self
+                .labels(key)
+                .iter()
+                .enumerate()
+                .filter_map(|(i, text)| {
+                    let layout = pangocairo::create_layout(cr)?;
+                    layout.set_font_description(Some(if i == 0 { &font } else { &small_font }));
+                    layout.set_width((w * pango::SCALE as f64) as i32);
+                    layout.set_alignment(pango::Alignment::Center);
+                    layout.set_text(text);
+                    Some(layout)
+                })
+                .collect::<Vec<_>>();
+            let text_height = layouts
+                .iter()
+                .map(|layout| layout.get_pixel_size().1 as f64)
+                .sum::<f64>();
+
+            let mut text_y = y + (h - text_height) / 2.;
+            for (i, layout) in layouts.iter().enumerate() {
+                let alpha = if i == 0 { text_alpha } else { 0.75 };
+                cr.new_path();
+                cr.move_to(x, text_y);
+                cr.set_source_rgba(fg.0, fg.1, fg.2, alpha);
+                pangocairo::show_layout(cr, layout);
+                text_y += layout.get_pixel_size().1 as f64;
+            }
+            cr.restore();
+        }
+    }
+
+    /// Render keymap to an SVG, PDF, or PNG file
+    pub fn render_to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: RenderFormat,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let (width, height) = self.size();
+
+        match format {
+            RenderFormat::Svg => {
+                let surface = cairo::SvgSurface::new(width, height, Some(path))
+                    .map_err(|err| format!("Failed to create SVG surface: {}", err))?;
+                self.draw(&cairo::Context::new(&surface));
+                surface.finish();
+                Ok(())
+            }
+            RenderFormat::Pdf => {
+                let surface = cairo::PdfSurface::new(width, height, path)
+                    .map_err(|err| format!("Failed to create PDF surface: {}", err))?;
+                self.draw(&cairo::Context::new(&surface));
+                surface.finish();
+                Ok(())
+            }
+            RenderFormat::Png => {
+                let surface = cairo::ImageSurface::create(
+                    cairo::Format::ARgb32,
+                    width.ceil() as i32,
+                    height.ceil() as i32,
+                )
+                .map_err(|err| format!("Failed to create image surface: {}", err))?;
+                self.draw(&cairo::Context::new(&surface));
+                let mut file = File::create(path)
+                    .map_err(|err| format!("Failed to create '{}': {}", path.display(), err))?;
+                surface
+                    .write_to_png(&mut file)
+                    .map_err(|err| format!("Failed to write PNG: {}", err))
+            }
+        }
+    }
+}
+
+/// Rotate `cr` by the rotation of `shape`, drawn at `scale` pixels per key
+/// width and moved by `offset`
+pub fn rotate_key(cr: &cairo::Context, shape: &KeyShape, scale: f64, offset: (f64, f64)) {
+    let origin_x = shape.rotation_origin.0 * scale + offset.0;
+    let origin_y = -shape.rotation_origin.1 * scale + offset.1;
+    cr.translate(origin_x, origin_y);
+    cr.rotate(shape.rotation.to_radians());
+    cr.translate(-origin_x, -origin_y);
+}
+
+/// Add the outline of the area covered by `rects` to the path of `cr`, with
+/// corners rounded to `radius`
+pub fn rounded_outline(cr: &cairo::Context, rects: &[Rect], radius: f64) {
+    fn direction(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+        let sign = |d: f64| {
+            if d > 0. {
+                1.
+            } else if d < 0. {
+                -1.
+            } else {
+                0.
+            }
+        };
+        (sign(to.0 - from.0), sign(to.1 - from.1))
+    }
+
+    for polygon in Rect::outline(rects) {
+        cr.new_sub_path();
+        let len = polygon.len();
+        for i in 0..len {
+            let point = polygon[i];
+            let d_in = direction(polygon[(i + len - 1) % len], point);
+            let d_out = direction(point, polygon[(i + 1) % len]);
+            // Circle touching both edges, inside the corner
+            let center_x = point.0 + (d_out.0 - d_in.0) * radius;
+            let center_y = point.1 + (d_out.1 - d_in.1) * radius;
+            let start = (-d_out.1).atan2(-d_out.0);
+            let end = d_in.1.atan2(d_in.0);
+            if d_in.0 * d_out.1 - d_in.1 * d_out.0 > 0. {
+                cr.arc(center_x, center_y, radius, start, end);
+            } else {
+                cr.arc_negative(center_x, center_y, radius, start, end);
+            }
+        }
+        cr.close_path();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn render_all_layouts() {
+        let dir = env::temp_dir().join(format!("keymap-render-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for board in backend::layouts() {
+            let layout = Layout::from_board(board).unwrap();
+            let mut renderer =
+                KeymapRenderer::new(&layout, layout.default.clone(), RenderPage::Layer(0));
+            renderer.set_legend_layers((0..layout.meta.num_layers as usize).collect());
+
+            let (width, height) = renderer.size();
+            assert!(width > 0. && height > 0., "{} has no keys", board);
+
+            for (extension, format) in &[
+                ("svg", RenderFormat::Svg),
+                ("pdf", RenderFormat::Pdf),
+                ("png", RenderFormat::Png),
+            ] {
+                let path = dir.join(format!("{}.{}", board.replace('/', "_"), extension));
+                assert_eq!(RenderFormat::from_path(&path), Some(*format));
+                renderer.render_to_file(&path, *format).unwrap();
+                assert!(fs::metadata(&path).unwrap().len() > 0);
+            }
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}